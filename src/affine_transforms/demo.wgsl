@@ -1,6 +1,13 @@
 
 @group(0) @binding(0) var<uniform> transform: mat4x4<f32>;
 
+struct Camera {
+    view_projection: mat4x4<f32>,
+    eye: vec4f,
+}
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
 struct Vertex {
     @location(0) pos: vec3f,
     @location(1) normal: vec3f,
@@ -16,9 +23,8 @@ fn vs_main(v: Vertex) -> VsOut {
     var pos = vec4f(v.pos.xyz, 1.0);
     var normal = vec4f(v.normal, 0.0) ;
 
-    pos = transform * pos;
+    pos = camera.view_projection * transform * pos;
     normal = transform * normal;
-    pos = vec4f(pos.xy, (pos.z + 1.0)/2.0, 1.0);
     
     return VsOut(
         pos,
@@ -30,11 +30,8 @@ impl winit::application::ApplicationHandler for App {
             event: winit::event::WindowEvent,
         ) {
 
-            match event {
-                winit::event::WindowEvent::CloseRequested => {
-                    event_loop.exit();
-                },
-                _ => {}
+            if let winit::event::WindowEvent::CloseRequested = event {
+                event_loop.exit();
             }
         
     }
@@ -0,0 +1,242 @@
+use std::{
+    collections::{HashMap, VecDeque, hash_map::Entry},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use super::filter::{self, Filter, PendingImage};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{self, PixelFormat},
+};
+
+/// Images submitted to the GPU before waiting for the oldest one. While the
+/// GPU works on them the next image is decoded and uploaded.
+const IN_FLIGHT: usize = 2;
+
+/// Whether `path` names several images: a directory or a file name pattern
+/// with `*` and `?` like `textures/*.png`.
+pub fn is_batch(path: &Path) -> bool {
+    path.is_dir() || pattern(path).is_some()
+}
+
+fn pattern(path: &Path) -> Option<&str> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| name.contains(['*', '?']))
+}
+
+/// The images in a directory, or the ones matching the pattern in the file
+/// name of `path`, sorted by path.
+pub fn list_images(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (dir, pattern) = match pattern(path) {
+        Some(pattern) => (
+            path.parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+            pattern,
+        ),
+        None => (path, "*"),
+    };
+    let mut paths = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let matches = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| matches(pattern, name));
+        if matches && path.is_file() && image::ImageFormat::from_path(&path).is_ok() {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        anyhow::bail!("No images found for {}", path.display());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Match `name` against a pattern where `*` stands for any number of
+/// characters and `?` for exactly one.
+fn matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the part of the name it has taken so far
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // let the last `*` take one more character
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Filters a list of images with one GPU context, building the filter once
+/// for every pixel format the images need.
+pub struct Batch<'a, F> {
+    gpu_context: &'a GpuContext,
+    format: Option<PixelFormat>,
+    make_filter: F,
+    filters: HashMap<PixelFormat, Box<dyn Filter>>,
+}
+
+/// An image that is being filtered on the GPU.
+struct Job {
+    in_path: PathBuf,
+    out_path: PathBuf,
+    original: image::DynamicImage,
+    readback: PendingImage,
+    load_time: Duration,
+    submitted: Instant,
+}
+
+impl<'a, F> Batch<'a, F>
+where
+    F: Fn(&GpuContext, PixelFormat) -> anyhow::Result<Box<dyn Filter>>,
+{
+    pub fn new(gpu_context: &'a GpuContext, format: Option<PixelFormat>, make_filter: F) -> Self {
+        Self {
+            gpu_context,
+            format,
+            make_filter,
+            filters: HashMap::new(),
+        }
+    }
+
+    /// Filter every image of `in_paths` into `out_dir` under its own file
+    /// name and print how long each of them took. Images that fail are
+    /// reported and skipped.
+    pub fn run(&mut self, in_paths: &[PathBuf], out_dir: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(out_dir)?;
+        let start = Instant::now();
+        let mut failures = 0;
+        let mut report = |in_path: &Path, result: anyhow::Result<String>| match result {
+            Ok(summary) => println!("{}: {summary}", in_path.display()),
+            Err(err) => {
+                eprintln!("{}: {err:#}", in_path.display());
+                failures += 1;
+            }
+        };
+
+        let mut jobs = VecDeque::new();
+        for in_path in in_paths {
+            let out_path = out_dir.join(in_path.file_name().unwrap());
+            if same_file(in_path, &out_path) {
+                report(
+                    in_path,
+                    Err(anyhow::anyhow!("Refusing to overwrite the input image")),
+                );
+                continue;
+            }
+            match self.submit(in_path, out_path) {
+                Ok(job) => jobs.push_back(job),
+                Err(err) => report(in_path, Err(err)),
+            }
+            if jobs.len() >= IN_FLIGHT {
+                let job = jobs.pop_front().unwrap();
+                report(&job.in_path.clone(), self.finish(job));
+            }
+        }
+        for job in jobs {
+            report(&job.in_path.clone(), self.finish(job));
+        }
+
+        println!(
+            "{} of {} images in {:.2?}",
+            in_paths.len() - failures,
+            in_paths.len(),
+            start.elapsed()
+        );
+        if failures > 0 {
+            anyhow::bail!("{failures} of {} images failed", in_paths.len());
+        }
+        Ok(())
+    }
+
+    fn submit(&mut self, in_path: &Path, out_path: PathBuf) -> anyhow::Result<Job> {
+        let start = Instant::now();
+        let original = image::ImageReader::open(in_path)?.decode()?;
+        let format = self
+            .format
+            .unwrap_or_else(|| PixelFormat::for_image(&original));
+        let filter = match self.filters.entry(format) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert((self.make_filter)(self.gpu_context, format)?),
+        };
+        let readback = filter::submit(self.gpu_context, filter.as_ref(), &original, format)?;
+        Ok(Job {
+            in_path: in_path.to_owned(),
+            out_path,
+            original,
+            readback,
+            load_time: start.elapsed(),
+            submitted: Instant::now(),
+        })
+    }
+
+    fn finish(&self, job: Job) -> anyhow::Result<String> {
+        let img = job.readback.finish(self.gpu_context)?;
+        let filter_time = job.submitted.elapsed();
+        let start = Instant::now();
+        gpu_image::with_depth_of(img, &job.original).save(&job.out_path)?;
+        Ok(format!(
+            "{}x{} -> {}, load {:.1?}, filter {:.1?}, save {:.1?}",
+            job.original.width(),
+            job.original.height(),
+            job.out_path.display(),
+            job.load_time,
+            filter_time,
+            start.elapsed()
+        ))
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn test_matches() {
+        for (pattern, name) in [
+            ("*", "lenna.png"),
+            ("*.png", "lenna.png"),
+            ("l?nna.*", "lenna.png"),
+            ("*a*a*", "banana"),
+            ("*", ""),
+        ] {
+            assert!(matches(pattern, name), "{pattern} {name}");
+        }
+        for (pattern, name) in [
+            ("*.png", "lenna.jpg"),
+            ("?", ""),
+            ("l?nna", "lenna.png"),
+            ("*a*b", "banana"),
+        ] {
+            assert!(!matches(pattern, name), "{pattern} {name}");
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use wgpu::util::DeviceExt;
+
+use super::{
+    edge::Edge,
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// An edge preserving blur compiled for one output format. Samples are
+/// weighted by a gaussian of their distance like `GaussianBlur`, times a
+/// gaussian of how far their color is from that of the pixel. The
+/// neighborhood is cut off at 3 `sigma_space`.
+pub struct Bilateral {
+    radius: u32,
+    edge: Edge,
+    params_buffer: wgpu::Buffer,
+    pass: Pass,
+}
+
+impl Bilateral {
+    const PARAMS_BINDING: u32 = 3;
+    /// Every pixel reads its whole (2r+1)^2 neighborhood, larger radii take
+    /// long enough for the driver to give up on the dispatch.
+    const MAX_RADIUS: u32 = 64;
+
+    pub fn new(
+        gpu_context: &GpuContext,
+        sigma_space: f32,
+        sigma_range: f32,
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        for (name, sigma) in [("sigma-space", sigma_space), ("sigma-range", sigma_range)] {
+            if !(sigma > 0.0 && sigma.is_finite()) {
+                anyhow::bail!("{name} has to be a finite number > 0, got {sigma}");
+            }
+        }
+        let radius = (3.0 * sigma_space).ceil() as u32;
+        if radius > Self::MAX_RADIUS {
+            anyhow::bail!(
+                "The bilateral filter supports radii up to {} (3 sigma-space), got {radius}",
+                Self::MAX_RADIUS
+            );
+        }
+        let device = &gpu_context.device;
+        // the `Params` struct in bilateral.wgsl
+        let params = [sigma_space, sigma_range];
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bilateral params buffer"),
+            usage: wgpu::BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&params),
+        });
+
+        let shader_module = device.create_shader_module(shader!("bilateral.wgsl", format));
+        let pass = Pass::new(
+            gpu_context,
+            &shader_module,
+            "main",
+            1,
+            format,
+            &[storage_buffer_entry(Self::PARAMS_BINDING)],
+            |w, h| (w.div_ceil(8), h.div_ceil(8)),
+        );
+        Ok(Self {
+            radius,
+            edge,
+            params_buffer,
+            pass,
+        })
+    }
+}
+
+impl Filter for Bilateral {
+    fn tiling(&self) -> Option<Tiling> {
+        Some(Tiling::new(self.radius, self.edge))
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [
+            input.width(),
+            input.height(),
+            self.radius,
+            self.edge.to_shader(),
+        ];
+        encode_passes(
+            gpu_context,
+            encoder,
+            std::slice::from_ref(&self.pass),
+            bytemuck::bytes_of(&shape),
+            &[wgpu::BindGroupEntry {
+                binding: Self::PARAMS_BINDING,
+                resource: self.params_buffer.as_entire_binding(),
+            }],
+            input,
+            output,
+        );
+    }
+}
+
+/// Straightforward CPU bilateral filter the shader is tested against.
+#[cfg(test)]
+pub fn bilateral_reference(
+    img: &image::RgbaImage,
+    sigma_space: f32,
+    sigma_range: f32,
+    edge: Edge,
+) -> image::RgbaImage {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let r = (3.0 * sigma_space).ceil() as i64;
+    let (sigma_space, sigma_range) = (sigma_space as f64, sigma_range as f64);
+    image::RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let center = img.get_pixel(x, y).0.map(|c| c as f64 / 255.0);
+        let (x, y) = (x as i64, y as i64);
+        let mut sum = [0.0f64; 4];
+        let mut weight_sum = 0.0f64;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let color = match (edge.sample_index(x + dx, w), edge.sample_index(y + dy, h)) {
+                    (Some(sx), Some(sy)) => img
+                        .get_pixel(sx as u32, sy as u32)
+                        .0
+                        .map(|c| c as f64 / 255.0),
+                    _ if edge == Edge::Zero => [0.0; 4],
+                    _ => continue,
+                };
+                let distance = (0..3).map(|c| (color[c] - center[c]).powi(2)).sum::<f64>();
+                let weight = (-((dx * dx + dy * dy) as f64) / (2.0 * sigma_space * sigma_space)
+                    - distance / (2.0 * sigma_range * sigma_range))
+                    .exp();
+                for c in 0..4 {
+                    sum[c] += weight * color[c];
+                }
+                weight_sum += weight;
+            }
+        }
+        image::Rgba(sum.map(|s| (s / weight_sum * 255.0).round() as u8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::{Bilateral, bilateral_reference};
+    use crate::{
+        box_blur_2d::{edge::Edge, filter},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    #[test]
+    fn test_bilateral_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // a noisy dark left half next to a noisy bright right half
+        let img = image::RgbaImage::from_fn(19, 11, |x, y| {
+            let base = if x < 9 { 40 } else { 200 };
+            let noise = ((x * 7919 + y * 104729) % 13) as u8;
+            image::Rgba([base + noise, base + 12 - noise, base, 255 - noise])
+        });
+        for &edge in Edge::value_variants() {
+            let bilateral =
+                Bilateral::new(&gpu_context, 1.5, 0.1, edge, PixelFormat::Rgba8).unwrap();
+            let out = filter::apply(
+                &gpu_context,
+                &bilateral,
+                &img.clone().into(),
+                PixelFormat::Rgba8,
+            )
+            .unwrap()
+            .into_rgba8();
+            let expected = bilateral_reference(&img, 1.5, 0.1, edge);
+            for (a, b) in out.pixels().zip(expected.pixels()) {
+                assert!(
+                    a.0.iter().zip(b.0).all(|(&a, b)| a.abs_diff(b) <= 1),
+                    "{edge:?}: {a:?} != {b:?}"
+                );
+            }
+            // the step between the halves isn't blurred
+            assert!(out.get_pixel(8, 5)[2].abs_diff(40) <= 1);
+            assert!(out.get_pixel(9, 5)[2].abs_diff(200) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_max_radius() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let new = |sigma_space| {
+            Bilateral::new(
+                &gpu_context,
+                sigma_space,
+                0.1,
+                Edge::Skip,
+                PixelFormat::Rgba8,
+            )
+        };
+        // radius 64 and 65
+        assert!(new(21.3).is_ok());
+        assert!(new(21.4).is_err());
+        assert!(new(f32::MAX).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+// Bilateral filter. Every sample in the (2r + 1)^2 neighborhood is weighted
+// by a gaussian of its distance to the pixel and another one of the
+// difference of its color, so edges between different colors stay sharp.
+// Has to match `bilateral_reference` in bilateral.rs.
+
+struct Params {
+    sigma_space: f32,
+    sigma_range: f32,
+}
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(3) var<storage, read> params: Params;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let p = vec2i(id.xy);
+    let r = i32(shape.r);
+    let center = textureLoad(img_in, p, 0);
+    let space_scale = -1.0 / (2.0 * params.sigma_space * params.sigma_space);
+    let range_scale = -1.0 / (2.0 * params.sigma_range * params.sigma_range);
+    var accum = vec4f(0.0);
+    var weight_sum = 0.0;
+    for (var dy = -r; dy <= r; dy++) {
+        let y = sample_index(p.y + dy, i32(shape.h));
+        for (var dx = -r; dx <= r; dx++) {
+            let x = sample_index(p.x + dx, i32(shape.w));
+            var color = vec4f(0.0);
+            if (x >= 0 && y >= 0) {
+                color = textureLoad(img_in, vec2i(x, y), 0);
+            } else if (shape.edge == EDGE_SKIP) {
+                continue;
+            }
+            // alpha is filtered but doesn't count towards the difference
+            let d = color.rgb - center.rgb;
+            let w = exp(f32(dx * dx + dy * dy) * space_scale + dot(d, d) * range_scale);
+            accum += w * color;
+            weight_sum += w;
+        }
+    }
+    // the pixel itself has weight 1, so weight_sum is never 0
+    textureStore(img_out, id.xy, accum / weight_sum);
+}
@@ -0,0 +1,404 @@
+use clap::ValueEnum;
+
+use super::{
+    edge::Edge,
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader},
+    summed_area::{SumType, SummedAreaPass, SummedAreaTable, summed_area_shader},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Algorithm {
+    /// Average the whole (2r+1)^2 neighborhood of every pixel
+    Naive,
+    /// Blur the rows, then blur the columns of the result
+    #[default]
+    Separable,
+    /// Separable, with every line staged in workgroup memory. The radius is
+    /// limited to 128
+    Shared,
+    /// Read every neighborhood from the summed-area table of the image, the
+    /// cost doesn't depend on the radius
+    SummedArea,
+}
+
+/// Has to match `box_blur_shared.wgsl`
+const SHARED_TILE: u32 = 256;
+const SHARED_MAX_RADIUS: u32 = 128;
+
+/// A box blur compiled for one output format. Once created it can blur any
+/// number of images.
+pub struct BoxBlur {
+    radius: u32,
+    edge: Edge,
+    passes: Passes,
+}
+
+enum Passes {
+    /// Passes from the image to the blurred image
+    Image(Vec<Pass>),
+    /// Building the summed-area table and reading the blur from it
+    SummedArea(SummedAreaTable, SummedAreaPass),
+}
+
+impl BoxBlur {
+    pub fn new(
+        gpu_context: &GpuContext,
+        radius: u32,
+        edge: Edge,
+        algorithm: Algorithm,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        let device = &gpu_context.device;
+        let passes = match algorithm {
+            Algorithm::Naive => {
+                let shader_module =
+                    device.create_shader_module(shader!("box_blur_2d.wgsl", format));
+                Passes::Image(vec![Pass::new(
+                    gpu_context,
+                    &shader_module,
+                    "main",
+                    1,
+                    format,
+                    &[],
+                    |w, h| (w.div_ceil(8), h.div_ceil(8)),
+                )])
+            }
+            Algorithm::Separable => {
+                let shader_module =
+                    device.create_shader_module(shader!("box_blur_separable.wgsl", format));
+                let workgroups = |w: u32, h: u32| (w.div_ceil(8), h.div_ceil(8));
+                Passes::Image(vec![
+                    Pass::new(
+                        gpu_context,
+                        &shader_module,
+                        "horizontal",
+                        1,
+                        PixelFormat::Rgba32f,
+                        &[],
+                        workgroups,
+                    ),
+                    Pass::new(
+                        gpu_context,
+                        &shader_module,
+                        "vertical",
+                        3,
+                        format,
+                        &[],
+                        workgroups,
+                    ),
+                ])
+            }
+            Algorithm::Shared => {
+                if radius > SHARED_MAX_RADIUS {
+                    anyhow::bail!(
+                        "The shared algorithm supports radii up to {SHARED_MAX_RADIUS}, use \
+                         separable for larger ones"
+                    );
+                }
+                let shader_module =
+                    device.create_shader_module(shader!("box_blur_shared.wgsl", format));
+                // one workgroup per TILE pixels of a line, one line per y
+                Passes::Image(vec![
+                    Pass::new(
+                        gpu_context,
+                        &shader_module,
+                        "horizontal",
+                        1,
+                        PixelFormat::Rgba32f,
+                        &[],
+                        |w, h| (w.div_ceil(SHARED_TILE), h),
+                    ),
+                    Pass::new(
+                        gpu_context,
+                        &shader_module,
+                        "vertical",
+                        3,
+                        format,
+                        &[],
+                        |w, h| (h.div_ceil(SHARED_TILE), w),
+                    ),
+                ])
+            }
+            Algorithm::SummedArea => {
+                let sum_type = SumType::for_format(format);
+                let shader_module = device.create_shader_module(summed_area_shader!(
+                    "box_blur_summed_area.wgsl",
+                    format,
+                    sum_type
+                ));
+                Passes::SummedArea(
+                    SummedAreaTable::new(gpu_context, sum_type),
+                    SummedAreaPass::new(gpu_context, &shader_module, "main", format, &[]),
+                )
+            }
+        };
+        Ok(Self {
+            radius,
+            edge,
+            passes,
+        })
+    }
+}
+
+impl Filter for BoxBlur {
+    fn tiling(&self) -> Option<Tiling> {
+        Some(Tiling::new(self.radius, self.edge))
+    }
+
+    fn max_size(&self, gpu_context: &GpuContext) -> u32 {
+        match &self.passes {
+            Passes::Image(_) => gpu_context.device.limits().max_texture_dimension_2d,
+            Passes::SummedArea(table, _) => table.max_size(gpu_context),
+        }
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [
+            input.width(),
+            input.height(),
+            self.radius,
+            self.edge.to_shader(),
+        ];
+        match &self.passes {
+            Passes::Image(passes) => encode_passes(
+                gpu_context,
+                encoder,
+                passes,
+                bytemuck::bytes_of(&shape),
+                &[],
+                input,
+                output,
+            ),
+            Passes::SummedArea(table, pass) => {
+                let table = table.encode(gpu_context, encoder, input);
+                pass.encode(
+                    gpu_context,
+                    encoder,
+                    bytemuck::bytes_of(&shape),
+                    &[],
+                    &table,
+                    output,
+                );
+            }
+        }
+    }
+}
+
+/// Blur `img` on the GPU, processing it in `format`.
+#[cfg(test)]
+pub fn blur(
+    img: &image::DynamicImage,
+    radius: u32,
+    edge: Edge,
+    algorithm: Algorithm,
+    format: PixelFormat,
+    gpu_context: &GpuContext,
+) -> anyhow::Result<image::DynamicImage> {
+    let box_blur = BoxBlur::new(gpu_context, radius, edge, algorithm, format)?;
+    super::filter::apply(gpu_context, &box_blur, img, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, blur};
+    use crate::{
+        box_blur_2d::{
+            edge::{Edge, blur_reference},
+            random_image,
+        },
+        compare::Comparison,
+        golden,
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    fn lenna() -> image::RgbaImage {
+        image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap()
+        .into_rgba8()
+    }
+
+    /// Blur an 8 bit image in rgba8.
+    fn blur8(
+        img: &image::RgbaImage,
+        radius: u32,
+        edge: Edge,
+        algorithm: Algorithm,
+        gpu_context: &GpuContext,
+    ) -> anyhow::Result<image::RgbaImage> {
+        let img = image::DynamicImage::from(img.clone());
+        blur(
+            &img,
+            radius,
+            edge,
+            algorithm,
+            PixelFormat::Rgba8,
+            gpu_context,
+        )
+        .map(|i| i.into_rgba8())
+    }
+
+    #[test]
+    fn test_golden_lenna() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let out_img = blur8(&lenna(), 3, Edge::Skip, Algorithm::Naive, &gpu_context).unwrap();
+        golden::assert_golden("box_blur_2d_lenna", &out_img, Default::default());
+    }
+
+    #[test]
+    fn test_algorithms_match_naive() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // odd sizes so the workgroups don't line up with the image
+        let img = image::imageops::crop_imm(&lenna(), 200, 200, 67, 41).to_image();
+        for radius in [0, 1, 5, 40, 128] {
+            let naive = blur8(&img, radius, Edge::Skip, Algorithm::Naive, &gpu_context).unwrap();
+            for algorithm in [
+                Algorithm::Separable,
+                Algorithm::Shared,
+                Algorithm::SummedArea,
+            ] {
+                let out = blur8(&img, radius, Edge::Skip, algorithm, &gpu_context).unwrap();
+                let comparison =
+                    Comparison::gpu(&gpu_context, &naive.clone().into(), &out.into(), 0.0).unwrap();
+                let max_diff = (comparison.max_difference * 255.0).round();
+                assert!(
+                    max_diff <= 1.0,
+                    "{algorithm:?} radius {radius}: off by {max_diff}"
+                );
+            }
+        }
+        assert!(blur8(&img, 129, Edge::Skip, Algorithm::Shared, &gpu_context).is_err());
+    }
+
+    #[test]
+    fn test_edges_match_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let images = [
+            random_image(1, 1, 1),
+            random_image(1, 6, 2),
+            random_image(6, 1, 3),
+            random_image(5, 3, 4),
+            random_image(13, 9, 5),
+        ];
+        for img in &images {
+            for radius in [1, 7] {
+                for edge in [
+                    Edge::Clamp,
+                    Edge::Mirror,
+                    Edge::Wrap,
+                    Edge::Skip,
+                    Edge::Zero,
+                ] {
+                    let expected = blur_reference(img, radius, edge);
+                    for algorithm in [
+                        Algorithm::Naive,
+                        Algorithm::Separable,
+                        Algorithm::Shared,
+                        Algorithm::SummedArea,
+                    ] {
+                        let out = blur8(img, radius, edge, algorithm, &gpu_context).unwrap();
+                        for (a, b) in out.pixels().zip(expected.pixels()) {
+                            assert!(
+                                a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 1),
+                                "{}x{} radius {radius} {edge:?} {algorithm:?}: {a:?} != {b:?}",
+                                img.width(),
+                                img.height()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_summed_area_large_float_image() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // sums of everything above and left of a pixel get far larger than
+        // the sums of its neighborhood, values from -1 to 3 make it worse
+        let (width, height) = (1024, 1024);
+        let noise = random_image(width, height, 3);
+        let img = image::Rgba32FImage::from_fn(width, height, |x, y| {
+            image::Rgba(
+                noise
+                    .get_pixel(x, y)
+                    .0
+                    .map(|v| v as f32 / 255.0 + x as f32 / width as f32 * 3.0 - 1.0),
+            )
+        });
+        let img = image::DynamicImage::ImageRgba32F(img);
+        for (edge, radius) in [(Edge::Clamp, 1), (Edge::Wrap, 30)] {
+            let [separable, summed_area] =
+                [Algorithm::Separable, Algorithm::SummedArea].map(|algorithm| {
+                    blur(
+                        &img,
+                        radius,
+                        edge,
+                        algorithm,
+                        PixelFormat::Rgba32f,
+                        &gpu_context,
+                    )
+                    .unwrap()
+                    .into_rgba32f()
+                });
+            let max_diff = separable
+                .as_raw()
+                .iter()
+                .zip(summed_area.as_raw())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max);
+            assert!(
+                max_diff <= 1e-5,
+                "{edge:?} radius {radius}: off by {max_diff}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_16_bit_not_quantized() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // steps far smaller than 1/255
+        let img = image::ImageBuffer::from_fn(37, 5, |x, y| {
+            image::Rgba([x as u16 * 3, y as u16 * 7 + 1000, 65535 - x as u16, 65535])
+        });
+        let img = image::DynamicImage::ImageRgba16(img);
+        for (format, tolerance) in [(PixelFormat::Rgba32f, 0), (PixelFormat::Rgba16f, 32)] {
+            for algorithm in [
+                Algorithm::Naive,
+                Algorithm::Separable,
+                Algorithm::Shared,
+                Algorithm::SummedArea,
+            ] {
+                let out = blur(&img, 0, Edge::Skip, algorithm, format, &gpu_context)
+                    .unwrap()
+                    .into_rgba16();
+                let max_diff = out
+                    .as_raw()
+                    .iter()
+                    .zip(img.as_rgba16().unwrap().as_raw())
+                    .map(|(a, b)| a.abs_diff(*b))
+                    .max()
+                    .unwrap();
+                assert!(
+                    max_diff <= tolerance,
+                    "{format:?} {algorithm:?}: off by {max_diff}"
+                );
+            }
+        }
+    }
+}
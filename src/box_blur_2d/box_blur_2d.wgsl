@@ -1,28 +1,5 @@
-struct Shape {
-    w: u32,
-    h: u32,
-    r: u32,
-}
-
-fn unpackRGBA(p: u32) -> vec4<u32> {
-    return vec4<u32>(
-        (p >>  0) & 0xFFu,
-        (p >>  8) & 0xFFu,
-        (p >> 16) & 0xFFu,
-        (p >> 24) & 0xFFu
-    );
-}
-
-fn packRGBA(p: vec4<u32>) -> u32 {
-    return (p.x & 0xFFu)
-         | ((p.y & 0xFFu) <<  8)
-         | ((p.z & 0xFFu) << 16)
-         | ((p.w & 0xFFu) << 24);
-}
-
-@group(0) @binding(0) var<uniform> shape: Shape;
-@group(0) @binding(1) var<storage, read> img_in: array<u32>;
-@group(0) @binding(2) var<storage, read_write> img_out: array<u32>;
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
 
 @compute
 @workgroup_size(8, 8, 1)
@@ -31,25 +8,22 @@ fn main(@builtin(global_invocation_id) id: vec3u) {
         return;
     }
 
-    let idx = id.y * shape.w + id.x;
-
-    // average the values of the neighboring pixels within the radius
-    let r = shape.r;
+    // average the values of the neighboring pixels within the radius.
+    // signed coordinates so the neighborhood can start left of / above the
+    // image, see common.wgsl for what happens there
+    let r = i32(shape.r);
+    let w = i32(shape.w);
+    let h = i32(shape.h);
     var accum = vec4f(0.0, 0.0, 0.0, 0.0);
-    var n = 0;
-    for (var x = id.x - r; x <= id.x + r; x++) {
-        for (var y = id.y - r; y <= id.y + r; y++) {
-            if (x > 0 && x < shape.w && y > 0 && y < shape.h) {
-                let sample_idx = y * shape.w + x;
-                // let c = unpackRGBA(img_in[sample_idx]);
-                // accum += vec4f(f32(c.r), f32(c.g), f32(c.b), f32(c.a));
-                accum += unpack4x8unorm(img_in[sample_idx]);
-                n += 1;
+    for (var x = i32(id.x) - r; x <= i32(id.x) + r; x++) {
+        for (var y = i32(id.y) - r; y <= i32(id.y) + r; y++) {
+            let sx = sample_index(x, w);
+            let sy = sample_index(y, h);
+            if (sx >= 0 && sy >= 0) {
+                accum += textureLoad(img_in, vec2i(sx, sy), 0);
             }
         }
     }
-    // let c_out = vec4<u32>(u32(accum.r / f32(n)), u32(accum.g / f32(n)), u32(accum.b / f32(n)), u32(accum.a / f32(n)));
-    // img_out[idx] = packRGBA(c_out);
-    img_out[idx] = pack4x8unorm(accum / f32(n));
-
-}
\ No newline at end of file
+    let n = divisor(i32(id.x), w) * divisor(i32(id.y), h);
+    textureStore(img_out, id.xy, accum / n);
+}
@@ -0,0 +1,49 @@
+// A box blur is separable: averaging the rows of the neighborhood first and
+// then averaging those averages along the column gives the same result as
+// averaging the whole neighborhood, with 2(2r+1) instead of (2r+1)^2 reads
+// per pixel.
+//
+// `horizontal` writes the blurred rows to an rgba32float texture so the
+// second pass doesn't round twice when the output is 8 or 16 bits,
+// `vertical` writes the result in the output's format.
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var rows_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var rows_in: texture_2d<f32>;
+@group(0) @binding(4) var img_out: texture_storage_2d<{{format}}, write>;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn horizontal(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let w = i32(shape.w);
+    var accum = vec4f(0.0);
+    for (var x = i32(id.x) - i32(shape.r); x <= i32(id.x) + i32(shape.r); x++) {
+        let sx = sample_index(x, w);
+        if (sx >= 0) {
+            accum += textureLoad(img_in, vec2i(sx, i32(id.y)), 0);
+        }
+    }
+    textureStore(rows_out, id.xy, accum / divisor(i32(id.x), w));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn vertical(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let h = i32(shape.h);
+    var accum = vec4f(0.0);
+    for (var y = i32(id.y) - i32(shape.r); y <= i32(id.y) + i32(shape.r); y++) {
+        let sy = sample_index(y, h);
+        if (sy >= 0) {
+            accum += textureLoad(rows_in, vec2i(i32(id.x), sy), 0);
+        }
+    }
+    textureStore(img_out, id.xy, accum / divisor(i32(id.y), h));
+}
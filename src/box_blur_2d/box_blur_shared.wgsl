@@ -0,0 +1,78 @@
+// Separable box blur where every workgroup first copies the span of the row
+// (or column) it needs into workgroup memory. Each pixel is then read from
+// global memory about once instead of 2r+1 times.
+//
+// A workgroup handles TILE pixels of one line and needs r more on each side,
+// so the radius is limited to MAX_RADIUS.
+
+const TILE: u32 = 256u;
+const MAX_RADIUS: u32 = 128u;
+
+// same bindings as box_blur_separable.wgsl
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var rows_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var rows_in: texture_2d<f32>;
+@group(0) @binding(4) var img_out: texture_storage_2d<{{format}}, write>;
+
+var<workgroup> tile: array<vec4f, 512>; // TILE + 2 * MAX_RADIUS
+
+fn sum_tile(local: u32) -> vec4f {
+    var accum = vec4f(0.0);
+    for (var i = 0u; i <= 2u * shape.r; i++) {
+        accum += tile[local + i];
+    }
+    return accum;
+}
+
+// dispatched as (ceil(w / TILE), h) workgroups
+@compute
+@workgroup_size(256, 1, 1)
+fn horizontal(
+    @builtin(workgroup_id) wg: vec3u,
+    @builtin(local_invocation_id) local: vec3u,
+) {
+    let y = wg.y;
+    let first = i32(wg.x * TILE) - i32(shape.r);
+    // samples that don't contribute are zero
+    for (var i = local.x; i < TILE + 2u * shape.r; i += TILE) {
+        let x = sample_index(first + i32(i), i32(shape.w));
+        if (x >= 0) {
+            tile[i] = textureLoad(img_in, vec2i(x, i32(y)), 0);
+        } else {
+            tile[i] = vec4f(0.0);
+        }
+    }
+    workgroupBarrier();
+
+    let x = wg.x * TILE + local.x;
+    if (x >= shape.w) {
+        return;
+    }
+    textureStore(rows_out, vec2u(x, y), sum_tile(local.x) / divisor(i32(x), i32(shape.w)));
+}
+
+// dispatched as (ceil(h / TILE), w) workgroups
+@compute
+@workgroup_size(256, 1, 1)
+fn vertical(
+    @builtin(workgroup_id) wg: vec3u,
+    @builtin(local_invocation_id) local: vec3u,
+) {
+    let x = wg.y;
+    let first = i32(wg.x * TILE) - i32(shape.r);
+    for (var i = local.x; i < TILE + 2u * shape.r; i += TILE) {
+        let y = sample_index(first + i32(i), i32(shape.h));
+        if (y >= 0) {
+            tile[i] = textureLoad(rows_in, vec2i(i32(x), y), 0);
+        } else {
+            tile[i] = vec4f(0.0);
+        }
+    }
+    workgroupBarrier();
+
+    let y = wg.x * TILE + local.x;
+    if (y >= shape.h) {
+        return;
+    }
+    textureStore(img_out, vec2u(x, y), sum_tile(local.x) / divisor(i32(y), i32(shape.h)));
+}
@@ -0,0 +1,18 @@
+// Box blur reading the sum of every neighborhood from the summed-area table
+// of the image, so it costs the same for any radius. See summed_area.wgsl.
+
+@group(0) @binding(1) var sat: texture_2d<u32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let p = vec2i(id.xy);
+    let r = i32(shape.r);
+    let n = divisor(p.x, i32(shape.w)) * divisor(p.y, i32(shape.h));
+    textureStore(img_out, id.xy, box_sum(p - r, p + r) / n);
+}
@@ -0,0 +1,195 @@
+// The steps of Canny after the Sobel gradients of sobel.wgsl, every one a
+// pass that writes an rgba32float texture for the next. `suppress` keeps the
+// magnitude of pixels that are a maximum across the edge, `threshold` marks
+// them as strong or weak edges, the hysteresis passes find the weak edges
+// connected to strong ones and `binarize` writes those and the strong edges
+// in white.
+
+struct Thresholds {
+    low: f32,
+    high: f32,
+}
+
+const WEAK: f32 = 0.5;
+const STRONG: f32 = 1.0;
+// tan(22.5°), where the gradient direction is rounded to the next 45°
+const TAN_22_5: f32 = 0.41421356;
+
+@group(0) @binding(1) var gradients: texture_2d<f32>;
+@group(0) @binding(2) var suppressed_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var suppressed_in: texture_2d<f32>;
+@group(0) @binding(4) var states_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var states_in: texture_2d<f32>;
+@group(0) @binding(6) var states_copy: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var edges_in: texture_2d<f32>;
+@group(0) @binding(8) var img_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(9) var<storage, read> thresholds: Thresholds;
+// one per pixel, see `find`
+@group(0) @binding(10) var<storage, read_write> labels: array<atomic<u32>>;
+
+fn inside(p: vec2i) -> bool {
+    return p.x >= 0 && p.y >= 0 && p.x < i32(shape.w) && p.y < i32(shape.h);
+}
+
+// step to the neighbor in the direction of gradient `g`
+fn direction(g: vec2f) -> vec2i {
+    let a = abs(g);
+    if (a.y <= TAN_22_5 * a.x) {
+        return vec2i(1, 0);
+    }
+    if (a.x <= TAN_22_5 * a.y) {
+        return vec2i(0, 1);
+    }
+    return select(vec2i(-1, 1), vec2i(1, 1), g.x * g.y > 0.0);
+}
+
+fn magnitude_at(p: vec2i) -> f32 {
+    if (!inside(p)) {
+        return 0.0;
+    }
+    return textureLoad(gradients, p, 0).b;
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn suppress(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let p = vec2i(id.xy);
+    let g = textureLoad(gradients, p, 0);
+    let d = direction(g.xy);
+    // of two equal neighbors across the edge only the second one is kept
+    let keep = g.b > 0.0 && g.b >= magnitude_at(p - d) && g.b > magnitude_at(p + d);
+    textureStore(suppressed_out, id.xy, vec4f(select(0.0, g.b, keep)));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn threshold(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let m = textureLoad(suppressed_in, vec2i(id.xy), 0).r;
+    var state = 0.0;
+    if (m >= thresholds.high) {
+        state = STRONG;
+    } else if (m >= thresholds.low) {
+        state = WEAK;
+    }
+    textureStore(states_out, id.xy, vec4f(state));
+}
+
+// The weak and strong edges touching each other make up components, which
+// the hysteresis passes find with a union-find over `labels`: every edge
+// pixel points at a pixel of its component with a lower index and the root
+// of the component points at itself. `hysteresis_init` makes every pixel
+// its own root, `hysteresis_merge` merges the components of neighboring
+// edges and `hysteresis_mark` sets STRONG_LABEL on the roots of components
+// with a strong edge in them. All three pass the states through unchanged.
+
+const STRONG_LABEL: u32 = 0x80000000u;
+
+fn pixel_index(p: vec2u) -> u32 {
+    return p.y * shape.w + p.x;
+}
+
+fn is_edge(p: vec2i) -> bool {
+    return textureLoad(states_in, p, 0).r != 0.0;
+}
+
+// root of the component of pixel `i`
+fn find(i: u32) -> u32 {
+    var root = i;
+    var parent = atomicLoad(&labels[i]) & ~STRONG_LABEL;
+    while (parent != root) {
+        root = parent;
+        parent = atomicLoad(&labels[root]) & ~STRONG_LABEL;
+    }
+    return root;
+}
+
+// merge the components of pixels `i` and `j` under the lower of their roots
+fn merge(i: u32, j: u32) {
+    var a = find(i);
+    var b = find(j);
+    loop {
+        if (a == b) {
+            return;
+        }
+        let high = max(a, b);
+        let low = min(a, b);
+        let old = atomicMin(&labels[high], low);
+        if (old == high) {
+            return;
+        }
+        // another invocation hung `high` under `old` first, go on from the
+        // root of that
+        if (a == high) {
+            a = find(old);
+        } else {
+            b = find(old);
+        }
+    }
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn hysteresis_init(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    atomicStore(&labels[pixel_index(id.xy)], pixel_index(id.xy));
+    textureStore(states_copy, id.xy, textureLoad(states_in, vec2i(id.xy), 0));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn hysteresis_merge(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let p = vec2i(id.xy);
+    if (is_edge(p)) {
+        // the neighbors before it, the ones after it merge with it themselves
+        let neighbors = array(vec2i(-1, -1), vec2i(0, -1), vec2i(1, -1), vec2i(-1, 0));
+        for (var n = 0; n < 4; n++) {
+            let q = p + neighbors[n];
+            if (inside(q) && is_edge(q)) {
+                merge(pixel_index(id.xy), pixel_index(vec2u(q)));
+            }
+        }
+    }
+    textureStore(states_copy, id.xy, textureLoad(states_in, p, 0));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn hysteresis_mark(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let state = textureLoad(states_in, vec2i(id.xy), 0);
+    if (state.r == STRONG) {
+        atomicOr(&labels[find(pixel_index(id.xy))], STRONG_LABEL);
+    }
+    textureStore(states_copy, id.xy, state);
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn binarize(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let state = textureLoad(edges_in, vec2i(id.xy), 0).r;
+    let root = atomicLoad(&labels[find(pixel_index(id.xy))]);
+    let edge = state != 0.0 && (root & STRONG_LABEL) != 0u;
+    textureStore(img_out, id.xy, vec4f(vec3f(select(0.0, 1.0, edge)), 1.0));
+}
@@ -0,0 +1,238 @@
+use clap::ValueEnum;
+use wgpu::util::DeviceExt;
+
+use super::{
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// The values a filter averages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ColorSpace {
+    /// The sRGB encoded values as stored in the image
+    #[default]
+    Srgb,
+    /// Linear light, decoded from sRGB before filtering and encoded again
+    /// afterwards
+    Linear,
+}
+
+/// A filter run on colors converted to linear light and/or premultiplied
+/// alpha. The filter itself has to be created for rgba32f so the converted
+/// colors aren't quantized.
+pub struct Converted {
+    filter: Box<dyn Filter>,
+    options_buffer: wgpu::Buffer,
+    decode: Pass,
+    encode: Pass,
+}
+
+impl Converted {
+    const OPTIONS_BINDING: u32 = 5;
+
+    pub fn new(
+        gpu_context: &GpuContext,
+        filter: Box<dyn Filter>,
+        color_space: ColorSpace,
+        premultiply: bool,
+        format: PixelFormat,
+    ) -> Self {
+        let device = &gpu_context.device;
+        // the `Options` struct in color.wgsl
+        let options = [
+            (color_space == ColorSpace::Linear) as u32,
+            premultiply as u32,
+        ];
+        let options_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("color options buffer"),
+            usage: wgpu::BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&options),
+        });
+
+        let shader_module = device.create_shader_module(shader!("color.wgsl", format));
+        let options_entry = [storage_buffer_entry(Self::OPTIONS_BINDING)];
+        let workgroups = |w: u32, h: u32| (w.div_ceil(8), h.div_ceil(8));
+        let decode = Pass::new(
+            gpu_context,
+            &shader_module,
+            "decode",
+            1,
+            PixelFormat::Rgba32f,
+            &options_entry,
+            workgroups,
+        );
+        let encode = Pass::new(
+            gpu_context,
+            &shader_module,
+            "encode",
+            3,
+            format,
+            &options_entry,
+            workgroups,
+        );
+        Self {
+            filter,
+            options_buffer,
+            decode,
+            encode,
+        }
+    }
+}
+
+impl Filter for Converted {
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        self.filter.output_size(width, height)
+    }
+
+    fn tiling(&self) -> Option<Tiling> {
+        self.filter.tiling()
+    }
+
+    fn max_size(&self, gpu_context: &GpuContext) -> u32 {
+        self.filter.max_size(gpu_context)
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        let options_entry = [wgpu::BindGroupEntry {
+            binding: Self::OPTIONS_BINDING,
+            resource: self.options_buffer.as_entire_binding(),
+        }];
+        let (width, height) = (input.width(), input.height());
+        let decoded = GpuImage::new(gpu_context, width, height, PixelFormat::Rgba32f);
+        let filtered = GpuImage::new(
+            gpu_context,
+            output.width(),
+            output.height(),
+            PixelFormat::Rgba32f,
+        );
+
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [width, height, 0, 0];
+        encode_passes(
+            gpu_context,
+            encoder,
+            std::slice::from_ref(&self.decode),
+            bytemuck::bytes_of(&shape),
+            &options_entry,
+            input,
+            &decoded,
+        );
+        self.filter
+            .encode(gpu_context, encoder, &decoded, &filtered);
+        let shape = [output.width(), output.height(), 0, 0];
+        encode_passes(
+            gpu_context,
+            encoder,
+            std::slice::from_ref(&self.encode),
+            bytemuck::bytes_of(&shape),
+            &options_entry,
+            &filtered,
+            output,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorSpace, Converted};
+    use crate::{
+        box_blur_2d::{
+            blur::{Algorithm, BoxBlur},
+            edge::Edge,
+            filter::{self, Filter},
+            pipeline::Pipeline,
+        },
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    fn run(
+        gpu_context: &GpuContext,
+        filter: Box<dyn Filter>,
+        color_space: ColorSpace,
+        premultiply: bool,
+        img: &image::RgbaImage,
+    ) -> image::RgbaImage {
+        let format = PixelFormat::Rgba8;
+        let converted = Converted::new(gpu_context, filter, color_space, premultiply, format);
+        filter::apply(gpu_context, &converted, &img.clone().into(), format)
+            .unwrap()
+            .into_rgba8()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = image::RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([
+                x as u8 * 17,
+                y as u8 * 17,
+                255 - x as u8 * 3,
+                1 + (x * y) as u8,
+            ])
+        });
+        let identity = || {
+            let pipeline = Pipeline::new(&gpu_context, &[], Edge::Skip, PixelFormat::Rgba32f);
+            Box::new(pipeline.unwrap())
+        };
+        let out = run(&gpu_context, identity(), ColorSpace::Linear, true, &img);
+        for (a, b) in img.pixels().zip(out.pixels()) {
+            // dividing by a small alpha amplifies rounding
+            let tolerance = if a[3] < 32 { 8 } else { 1 };
+            assert!(
+                a.0.iter()
+                    .zip(b.0)
+                    .all(|(&a, b)| a.abs_diff(b) <= tolerance),
+                "{a:?} != {b:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_linear_and_premultiplied_blur() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let blur = || {
+            let blur = BoxBlur::new(
+                &gpu_context,
+                1,
+                Edge::Clamp,
+                Algorithm::default(),
+                PixelFormat::Rgba32f,
+            );
+            Box::new(blur.unwrap())
+        };
+
+        // black on the left, white on the right
+        let img = image::RgbaImage::from_fn(6, 1, |x, _| match x < 3 {
+            true => image::Rgba([0, 0, 0, 255]),
+            false => image::Rgba([255, 255, 255, 255]),
+        });
+        let srgb = run(&gpu_context, blur(), ColorSpace::Srgb, false, &img);
+        assert_eq!(srgb.get_pixel(2, 0)[0], 85);
+        assert_eq!(srgb.get_pixel(3, 0)[0], 170);
+        // 1/3 and 2/3 of the light, encoded as sRGB
+        let linear = run(&gpu_context, blur(), ColorSpace::Linear, false, &img);
+        assert!(linear.get_pixel(2, 0)[0].abs_diff(156) <= 1);
+        assert!(linear.get_pixel(3, 0)[0].abs_diff(213) <= 1);
+
+        // transparent red next to opaque blue
+        let img = image::RgbaImage::from_fn(6, 1, |x, _| match x < 3 {
+            true => image::Rgba([255, 0, 0, 0]),
+            false => image::Rgba([0, 0, 255, 255]),
+        });
+        let straight = run(&gpu_context, blur(), ColorSpace::Srgb, false, &img);
+        assert_eq!(straight.get_pixel(2, 0).0, [170, 0, 85, 85]);
+        let premultiplied = run(&gpu_context, blur(), ColorSpace::Srgb, true, &img);
+        assert_eq!(premultiplied.get_pixel(2, 0).0, [0, 0, 255, 85]);
+    }
+}
@@ -0,0 +1,65 @@
+// Converts colors into the space a filter works in and back. `decode` turns
+// sRGB encoded colors into linear light and premultiplies alpha, `encode`
+// undoes both, each only if the option is set. `shape.r` and `shape.edge`
+// are unused.
+
+struct Options {
+    linear: u32,
+    premultiply: u32,
+}
+
+@group(0) @binding(1) var decode_in: texture_2d<f32>;
+@group(0) @binding(2) var decode_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var encode_in: texture_2d<f32>;
+@group(0) @binding(4) var encode_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(5) var<storage, read> options: Options;
+
+// the sRGB transfer functions, odd so filters overshooting below 0 survive
+// the round trip
+fn srgb_to_linear(c: vec3f) -> vec3f {
+    let a = abs(c);
+    let l = select(pow((a + 0.055) / 1.055, vec3f(2.4)), a / 12.92, a <= vec3f(0.04045));
+    return sign(c) * l;
+}
+
+fn linear_to_srgb(l: vec3f) -> vec3f {
+    let a = abs(l);
+    let c = select(1.055 * pow(a, vec3f(1.0 / 2.4)) - 0.055, a * 12.92, a <= vec3f(0.0031308));
+    return sign(l) * c;
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn decode(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    var color = textureLoad(decode_in, vec2i(id.xy), 0);
+    if (options.linear != 0u) {
+        color = vec4f(srgb_to_linear(color.rgb), color.a);
+    }
+    if (options.premultiply != 0u) {
+        color = vec4f(color.rgb * color.a, color.a);
+    }
+    textureStore(decode_out, id.xy, color);
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn encode(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    var color = textureLoad(encode_in, vec2i(id.xy), 0);
+    if (options.premultiply != 0u) {
+        // fully transparent pixels have no color left to recover
+        let rgb = select(vec3f(0.0), color.rgb / color.a, color.a > 0.0);
+        color = vec4f(rgb, color.a);
+    }
+    if (options.linear != 0u) {
+        color = vec4f(linear_to_srgb(color.rgb), color.a);
+    }
+    textureStore(encode_out, id.xy, color);
+}
@@ -0,0 +1,61 @@
+// Prepended to every filter shader.
+
+struct Shape {
+    w: u32,
+    h: u32,
+    r: u32,
+    edge: u32,
+}
+
+// has to match `Edge` in edge.rs
+const EDGE_CLAMP: u32 = 0u;
+const EDGE_MIRROR: u32 = 1u;
+const EDGE_WRAP: u32 = 2u;
+const EDGE_SKIP: u32 = 3u;
+const EDGE_ZERO: u32 = 4u;
+
+@group(0) @binding(0) var<uniform> shape: Shape;
+
+// a mod b for b > 0, always positive. `%` is only used on positive numbers
+// because the GL backend passes it through to GLSL where a negative operand
+// is undefined
+fn modulo(a: i32, b: i32) -> i32 {
+    if (a >= 0) {
+        return a % b;
+    }
+    return b - 1 - (-a - 1) % b;
+}
+
+// index of the pixel sampled for coordinate `i` on an axis `len` pixels
+// long, or -1 when the sample is outside of the image and contributes
+// nothing
+fn sample_index(i: i32, len: i32) -> i32 {
+    if (i >= 0 && i < len) {
+        return i;
+    }
+    switch shape.edge {
+        case EDGE_CLAMP: {
+            return clamp(i, 0, len - 1);
+        }
+        case EDGE_MIRROR: {
+            // the edge pixel is repeated: -1 -> 0, len -> len - 1
+            let m = modulo(i, 2 * len);
+            return select(m, 2 * len - 1 - m, m >= len);
+        }
+        case EDGE_WRAP: {
+            return modulo(i, len);
+        }
+        default: {
+            return -1;
+        }
+    }
+}
+
+// number of samples the sum along one axis around `i` is divided by
+fn divisor(i: i32, len: i32) -> f32 {
+    let r = i32(shape.r);
+    if (shape.edge == EDGE_SKIP) {
+        return f32(min(i + r, len - 1) - max(i - r, 0) + 1);
+    }
+    return f32(2 * r + 1);
+}
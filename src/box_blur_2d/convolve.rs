@@ -0,0 +1,376 @@
+use clap::{Args, ValueEnum};
+use wgpu::util::DeviceExt;
+
+use super::{
+    edge::Edge,
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// `width` x `height` weights in row major order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    pub width: u32,
+    pub height: u32,
+    pub weights: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Preset {
+    /// Horizontal gradient
+    SobelX,
+    /// Vertical gradient
+    SobelY,
+    /// Sum of the second derivatives, responds to edges in any direction
+    Laplacian,
+    Sharpen,
+    /// Relief lit from the top left
+    Emboss,
+}
+
+impl Kernel {
+    /// Parse one row of the kernel per line, the weights separated by
+    /// whitespace or commas. Empty lines and everything after a `#` are
+    /// ignored.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut rows = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let row = line
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|weight| !weight.is_empty())
+                .map(|weight| {
+                    weight.parse::<f32>().map_err(|e| {
+                        anyhow::anyhow!("Line {}: {weight:?} is not a number: {e}", i + 1)
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if !row.is_empty() {
+                rows.push(row);
+            }
+        }
+        let Some(width) = rows.first().map(Vec::len) else {
+            anyhow::bail!("The kernel is empty");
+        };
+        if rows.iter().any(|row| row.len() != width) {
+            anyhow::bail!("Every row of the kernel needs the same number of weights");
+        }
+        Ok(Self {
+            width: width as u32,
+            height: rows.len() as u32,
+            weights: rows.concat(),
+        })
+    }
+
+    pub fn preset(preset: Preset) -> Self {
+        #[rustfmt::skip]
+        let weights = match preset {
+            Preset::SobelX => [
+                -1.0, 0.0, 1.0,
+                -2.0, 0.0, 2.0,
+                -1.0, 0.0, 1.0,
+            ],
+            Preset::SobelY => [
+                -1.0, -2.0, -1.0,
+                 0.0,  0.0,  0.0,
+                 1.0,  2.0,  1.0,
+            ],
+            Preset::Laplacian => [
+                0.0,  1.0, 0.0,
+                1.0, -4.0, 1.0,
+                0.0,  1.0, 0.0,
+            ],
+            Preset::Sharpen => [
+                 0.0, -1.0,  0.0,
+                -1.0,  5.0, -1.0,
+                 0.0, -1.0,  0.0,
+            ],
+            Preset::Emboss => [
+                -2.0, -1.0, 0.0,
+                -1.0,  1.0, 1.0,
+                 0.0,  1.0, 2.0,
+            ],
+        };
+        Self {
+            width: 3,
+            height: 3,
+            weights: weights.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Normalize {
+    /// Divide the weights by their sum unless it is 0, like for edge
+    /// detection kernels
+    #[default]
+    Auto,
+    /// Divide the weights by their sum
+    Sum,
+    /// Use the weights as they are
+    None,
+}
+
+/// How a kernel is applied, besides the edge handling.
+#[derive(Args, Clone, Copy, Debug, Default)]
+pub struct ConvolveParams {
+    /// Whether the kernel is scaled so its weights sum to 1. With `--edge
+    /// skip` a normalized kernel is scaled to the samples inside the image
+    #[arg(long, value_enum, default_value_t)]
+    pub normalize: Normalize,
+    /// Added to the color channels afterwards, e.g. 0.5 to see negative
+    /// responses
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub bias: f32,
+    /// Convolve the alpha channel too instead of keeping the input's alpha
+    #[arg(long)]
+    pub convolve_alpha: bool,
+}
+
+impl ConvolveParams {
+    /// The weights the kernel is applied with and whether they are
+    /// normalized.
+    fn weights(&self, kernel: &Kernel) -> anyhow::Result<(Vec<f32>, bool)> {
+        let sum: f32 = kernel.weights.iter().sum();
+        let normalize = match self.normalize {
+            Normalize::Auto => sum.abs() > f32::EPSILON,
+            Normalize::Sum if sum.abs() <= f32::EPSILON => {
+                anyhow::bail!("The weights of the kernel sum to 0 so it can't be normalized")
+            }
+            Normalize::Sum => true,
+            Normalize::None => false,
+        };
+        if normalize {
+            Ok((kernel.weights.iter().map(|w| w / sum).collect(), true))
+        } else {
+            Ok((kernel.weights.clone(), false))
+        }
+    }
+
+    /// Storage buffer holding the `Kernel` struct of convolve.wgsl, which
+    /// fft_convolve.wgsl shares.
+    pub(super) fn kernel_buffer(
+        &self,
+        gpu_context: &GpuContext,
+        kernel: &Kernel,
+        edge: Edge,
+    ) -> anyhow::Result<wgpu::Buffer> {
+        let (weights, normalized) = self.weights(kernel)?;
+        let header = [
+            kernel.width,
+            kernel.height,
+            (normalized && edge == Edge::Skip) as u32,
+            self.convolve_alpha as u32,
+            self.bias.to_bits(),
+        ];
+        Ok(gpu_context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("kernel buffer"),
+                usage: wgpu::BufferUsages::STORAGE,
+                contents: &[
+                    bytemuck::cast_slice(&header),
+                    bytemuck::cast_slice(&weights),
+                ]
+                .concat(),
+            }))
+    }
+}
+
+/// A convolution with one kernel compiled for one output format.
+pub struct Convolution {
+    /// How far the kernel reaches from its center
+    radius: u32,
+    edge: Edge,
+    kernel_buffer: wgpu::Buffer,
+    passes: Vec<Pass>,
+}
+
+impl Convolution {
+    const KERNEL_BINDING: u32 = 3;
+
+    pub fn new(
+        gpu_context: &GpuContext,
+        kernel: &Kernel,
+        params: &ConvolveParams,
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        let device = &gpu_context.device;
+        let kernel_buffer = params.kernel_buffer(gpu_context, kernel, edge)?;
+
+        let shader_module = device.create_shader_module(shader!("convolve.wgsl", format));
+        let passes = vec![Pass::new(
+            gpu_context,
+            &shader_module,
+            "main",
+            1,
+            format,
+            &[storage_buffer_entry(Self::KERNEL_BINDING)],
+            |w, h| (w.div_ceil(8), h.div_ceil(8)),
+        )];
+        Ok(Self {
+            radius: kernel.width.max(kernel.height) / 2,
+            edge,
+            kernel_buffer,
+            passes,
+        })
+    }
+}
+
+impl Filter for Convolution {
+    fn tiling(&self) -> Option<Tiling> {
+        Some(Tiling::new(self.radius, self.edge))
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [input.width(), input.height(), 0, self.edge.to_shader()];
+        encode_passes(
+            gpu_context,
+            encoder,
+            &self.passes,
+            bytemuck::bytes_of(&shape),
+            &[wgpu::BindGroupEntry {
+                binding: Self::KERNEL_BINDING,
+                resource: self.kernel_buffer.as_entire_binding(),
+            }],
+            input,
+            output,
+        );
+    }
+}
+
+/// Convolve `img` with `kernel` on the GPU, processing it in `format`.
+#[cfg(test)]
+pub fn convolve(
+    img: &image::DynamicImage,
+    kernel: &Kernel,
+    params: &ConvolveParams,
+    edge: Edge,
+    format: PixelFormat,
+    gpu_context: &GpuContext,
+) -> anyhow::Result<image::DynamicImage> {
+    let convolution = Convolution::new(gpu_context, kernel, params, edge, format)?;
+    super::filter::apply(gpu_context, &convolution, img, format)
+}
+
+/// Straightforward CPU convolution the shader is tested against.
+#[cfg(test)]
+pub fn convolve_reference(
+    img: &image::RgbaImage,
+    kernel: &Kernel,
+    params: &ConvolveParams,
+    edge: Edge,
+) -> image::RgbaImage {
+    let (weights, normalized) = params.weights(kernel).unwrap();
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let (kw, kh) = (kernel.width as i64, kernel.height as i64);
+    image::RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let mut sum = [0.0f64; 4];
+        let mut weight_sum = 0.0f64;
+        for ky in 0..kh {
+            for kx in 0..kw {
+                let sx = edge.sample_index(x + kx - kw / 2, w);
+                let sy = edge.sample_index(y + ky - kh / 2, h);
+                if let (Some(sx), Some(sy)) = (sx, sy) {
+                    let weight = weights[(ky * kw + kx) as usize] as f64;
+                    let p = img.get_pixel(sx as u32, sy as u32);
+                    for c in 0..4 {
+                        sum[c] += weight * p[c] as f64 / 255.0;
+                    }
+                    weight_sum += weight;
+                }
+            }
+        }
+        if normalized && edge == Edge::Skip && weight_sum != 0.0 {
+            sum = sum.map(|s| s / weight_sum);
+        }
+        for s in &mut sum[..3] {
+            *s += params.bias as f64;
+        }
+        if !params.convolve_alpha {
+            sum[3] = img.get_pixel(x as u32, y as u32)[3] as f64 / 255.0;
+        }
+        image::Rgba(sum.map(|s| (s.clamp(0.0, 1.0) * 255.0).round() as u8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::{ConvolveParams, Kernel, Normalize, Preset, convolve, convolve_reference};
+    use crate::{
+        box_blur_2d::{edge::Edge, random_image},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    #[test]
+    fn test_parse() {
+        let kernel = Kernel::parse("# sharpen\n 0 -1 0\n-1, 5, -1\n\n0 -1 0 # last row\n").unwrap();
+        assert_eq!(kernel, Kernel::preset(Preset::Sharpen));
+        let kernel = Kernel::parse("1 2 3 4").unwrap();
+        assert_eq!((kernel.width, kernel.height), (4, 1));
+        assert!(Kernel::parse("1 2\n3").is_err());
+        assert!(Kernel::parse("1 x").is_err());
+        assert!(Kernel::parse("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = random_image(11, 7, 7);
+        let kernels = [
+            Kernel::preset(Preset::SobelX),
+            Kernel::preset(Preset::Sharpen),
+            Kernel::parse("1 2 1\n2 4 2\n1 2 1").unwrap(),
+            // even sizes are centered right of / below the middle
+            Kernel::parse("1 0.5\n0.25 -1\n2 1").unwrap(),
+            Kernel::parse("0 0 0 0 1 0 0 0 3").unwrap(),
+        ];
+        let params = [
+            ConvolveParams::default(),
+            ConvolveParams {
+                normalize: Normalize::None,
+                bias: 0.5,
+                convolve_alpha: true,
+            },
+        ];
+        for kernel in &kernels {
+            for params in &params {
+                for &edge in Edge::value_variants() {
+                    let expected = convolve_reference(&img, kernel, params, edge);
+                    let out = convolve(
+                        &img.clone().into(),
+                        kernel,
+                        params,
+                        edge,
+                        PixelFormat::Rgba8,
+                        &gpu_context,
+                    )
+                    .unwrap()
+                    .into_rgba8();
+                    for (a, b) in out.pixels().zip(expected.pixels()) {
+                        assert!(
+                            a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 1),
+                            "{kernel:?} {params:?} {edge:?}: {a:?} != {b:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
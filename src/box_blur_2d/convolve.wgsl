@@ -0,0 +1,56 @@
+// Applies an arbitrary width x height kernel. The kernel isn't flipped:
+// weights[ky * width + kx] multiplies the pixel kx columns right and ky rows
+// down of the top left of the neighborhood, which is centered on
+// (width / 2, height / 2). `shape.r` is unused.
+
+struct Kernel {
+    width: u32,
+    height: u32,
+    // divide by the sum of the weights of the samples inside the image,
+    // set for normalized kernels with EDGE_SKIP
+    renormalize: u32,
+    // otherwise the alpha of the input pixel is kept
+    convolve_alpha: u32,
+    // added to the color channels
+    bias: f32,
+    weights: array<f32>,
+}
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(3) var<storage, read> kernel: Kernel;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let first = vec2i(id.xy) - vec2i(i32(kernel.width / 2u), i32(kernel.height / 2u));
+    var accum = vec4f(0.0);
+    var weight_sum = 0.0;
+    for (var ky = 0u; ky < kernel.height; ky++) {
+        let sy = sample_index(first.y + i32(ky), i32(shape.h));
+        if (sy < 0) {
+            continue;
+        }
+        for (var kx = 0u; kx < kernel.width; kx++) {
+            let sx = sample_index(first.x + i32(kx), i32(shape.w));
+            if (sx >= 0) {
+                let weight = kernel.weights[ky * kernel.width + kx];
+                accum += weight * textureLoad(img_in, vec2i(sx, sy), 0);
+                weight_sum += weight;
+            }
+        }
+    }
+
+    if (kernel.renormalize != 0u && weight_sum != 0.0) {
+        accum /= weight_sum;
+    }
+    var color = vec4f(accum.rgb + kernel.bias, accum.a);
+    if (kernel.convolve_alpha == 0u) {
+        color.a = textureLoad(img_in, vec2i(id.xy), 0).a;
+    }
+    textureStore(img_out, id.xy, color);
+}
@@ -1,198 +1,592 @@
-use clap::Args;
-use wgpu::{ComputePipelineDescriptor, util::DeviceExt};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use crate::gpu_context::GpuContext;
+use clap::{Args, ValueEnum};
+
+use super::{
+    batch::{self, Batch},
+    bilateral::Bilateral,
+    blur::{Algorithm, BoxBlur},
+    color::{ColorSpace, Converted},
+    convolve::{Convolution, ConvolveParams, Kernel, Preset},
+    edge::Edge,
+    edges::{EdgeMethod, Edges},
+    equalize::{Equalize, EqualizeChannels},
+    fft_convolve::FftConvolution,
+    filter::{self, Filter},
+    gaussian::GaussianBlur,
+    histogram::HistogramData,
+    levels::{Levels, LevelsPoints},
+    median::Median,
+    mipmaps::{self, MipFilter},
+    pipeline::{Pipeline, Stage},
+    resize::{Resize, ResizeFilter, Size},
+};
+use crate::{
+    gpu_context::{GpuContext, GpuOpts},
+    gpu_image::{self, GpuImage, PixelFormat},
+};
+
+/// Arguments every image filter command takes.
+#[derive(Args)]
+pub struct FilterOpts {
+    /// Path of image to filter. A directory or a file name pattern like
+    /// `textures/*.png` filters all the images in it
+    in_path: PathBuf,
+    /// Output path, the directory the images are written to if there are
+    /// several of them
+    out_path: PathBuf,
+    /// How pixels near the edge of the image are treated
+    #[arg(long, value_enum, default_value_t)]
+    edge: Edge,
+    /// Format the image is filtered in on the GPU. Defaults to rgba8 for 8
+    /// bit images and rgba32f for 16 bit and float ones
+    #[arg(long, value_enum)]
+    format: Option<PixelFormat>,
+    /// Whether the filter works on the sRGB encoded values or on linear
+    /// light
+    #[arg(long, value_enum, default_value_t)]
+    color_space: ColorSpace,
+    /// Multiply the colors by alpha while filtering, so transparent pixels
+    /// don't bleed their color into their neighbors
+    #[arg(long)]
+    premultiply: bool,
+}
+
+impl FilterOpts {
+    /// Load the input images, filter them with the filter `make_filter`
+    /// builds for their format and save the results with the bit depth of
+    /// the input.
+    fn run(
+        &self,
+        gpu_opts: &GpuOpts,
+        make_filter: impl Fn(&GpuContext, PixelFormat) -> anyhow::Result<Box<dyn Filter>>,
+    ) -> anyhow::Result<()> {
+        let gpu_context = GpuContext::new(gpu_opts, wgpu::Features::empty())?;
+        let make_filter =
+            |gpu_context: &GpuContext, format| self.make_filter(gpu_context, format, &make_filter);
+
+        if batch::is_batch(&self.in_path) {
+            let in_paths = batch::list_images(&self.in_path)?;
+            return Batch::new(&gpu_context, self.format, make_filter)
+                .run(&in_paths, &self.out_path);
+        }
+
+        let img = image::ImageReader::open(&self.in_path)?.decode()?;
+        let format = self.format.unwrap_or_else(|| PixelFormat::for_image(&img));
+
+        let filter = make_filter(&gpu_context, format)?;
+        let out_img = filter::apply(&gpu_context, filter.as_ref(), &img, format)?;
+
+        gpu_image::with_depth_of(out_img, &img).save(&self.out_path)?;
+
+        Ok(())
+    }
+
+    /// The filter `make_filter` builds for `format`, run in the color space
+    /// and with the alpha given on the command line.
+    fn make_filter(
+        &self,
+        gpu_context: &GpuContext,
+        format: PixelFormat,
+        make_filter: impl Fn(&GpuContext, PixelFormat) -> anyhow::Result<Box<dyn Filter>>,
+    ) -> anyhow::Result<Box<dyn Filter>> {
+        if self.color_space == ColorSpace::Srgb && !self.premultiply {
+            return make_filter(gpu_context, format);
+        }
+        let filter = make_filter(gpu_context, PixelFormat::Rgba32f)?;
+        Ok(Box::new(Converted::new(
+            gpu_context,
+            filter,
+            self.color_space,
+            self.premultiply,
+            format,
+        )))
+    }
+}
 
 #[derive(Args)]
 pub struct Opts {
-    /// Path of image to blur
-    in_path: std::path::PathBuf,
-    /// Output path
-    out_path: std::path::PathBuf,
+    #[command(flatten)]
+    filter: FilterOpts,
     /// Radius of box blur
     #[arg(default_value_t = 3)]
-    radius: u32
+    radius: u32,
+    /// How the blur is computed. All of them give the same image
+    #[arg(long, value_enum, default_value_t)]
+    algorithm: Algorithm,
 }
 
-fn blur(img: &image::RgbaImage, radius: u32, gpu_context: &GpuContext) -> anyhow::Result<image::RgbaImage> {
-    let bind_group_layout =
-        gpu_context
-            .device
-            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("bind group layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-            });
-
-    let pipeline_layout =
-        gpu_context
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("pipeline layout"),
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-    let shader_module = gpu_context
-        .device
-        .create_shader_module(wgpu::include_wgsl!("box_blur_2d.wgsl"));
-
-    let pipeline = gpu_context
-        .device
-        .create_compute_pipeline(&ComputePipelineDescriptor {
-            label: Some("compute pipeline"),
-            layout: Some(&pipeline_layout),
-            module: &shader_module,
-            entry_point: Some("main"),
-            compilation_options: Default::default(),
-            cache: None,
-        });
-
-    let uniforms_buffer =
+pub fn demo(opts: Opts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(BoxBlur::new(
+            gpu_context,
+            opts.radius,
+            opts.filter.edge,
+            opts.algorithm,
+            format,
+        )?))
+    })
+}
+
+#[derive(Args)]
+pub struct BlurBenchmarkOpts {
+    /// Image to blur, 1024x1024 pixels of noise if left out
+    in_path: Option<PathBuf>,
+    /// Comma separated radii to time every algorithm with
+    #[arg(long, value_delimiter = ',', default_value = "1,8,32,128")]
+    radii: Vec<u32>,
+    /// Number of blurs timed per radius and algorithm
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+    /// How pixels near the edge of the image are treated
+    #[arg(long, value_enum, default_value_t)]
+    edge: Edge,
+    /// Format the image is blurred in, as for box-blur-2d
+    #[arg(long, value_enum)]
+    format: Option<PixelFormat>,
+}
+
+/// Print the time a box blur takes with every algorithm for every radius.
+pub fn blur_benchmark(opts: BlurBenchmarkOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let gpu_context = GpuContext::new(gpu_opts, wgpu::Features::empty())?;
+    let img = match &opts.in_path {
+        Some(path) => image::ImageReader::open(path)?.decode()?,
+        None => super::random_image(1024, 1024, 1).into(),
+    };
+    let format = opts.format.unwrap_or_else(|| PixelFormat::for_image(&img));
+    let input = GpuImage::upload(&gpu_context, &img, format);
+    let output = GpuImage::new(&gpu_context, input.width(), input.height(), format);
+
+    println!(
+        "ms per blur of {}x{} pixels in {format:?}",
+        input.width(),
+        input.height()
+    );
+    let names: Vec<_> = Algorithm::value_variants()
+        .iter()
+        .map(|algorithm| algorithm.to_possible_value().unwrap().get_name().to_owned())
+        .collect();
+    println!(
+        "{:>8}{}",
+        "radius",
+        names
+            .iter()
+            .map(|name| format!("{name:>13}"))
+            .collect::<String>()
+    );
+    for &radius in &opts.radii {
+        print!("{radius:>8}");
+        for &algorithm in Algorithm::value_variants() {
+            // the shared algorithm doesn't do large radii
+            match BoxBlur::new(&gpu_context, radius, opts.edge, algorithm, format) {
+                Ok(blur) => {
+                    let time = time_filter(&gpu_context, &blur, &input, &output, opts.iterations)?;
+                    print!("{:>13.3}", time.as_secs_f64() * 1000.0);
+                }
+                Err(_) => print!("{:>13}", "-"),
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Average time `filter` takes on the GPU, measured on the CPU from the
+/// submission of `iterations` runs to their end. One run before that takes
+/// care of compiling the pipelines.
+fn time_filter(
+    gpu_context: &GpuContext,
+    filter: &dyn Filter,
+    input: &GpuImage,
+    output: &GpuImage,
+    iterations: u32,
+) -> anyhow::Result<Duration> {
+    let run = |iterations| -> anyhow::Result<()> {
+        let mut encoder =
+            gpu_context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("benchmark encoder"),
+                });
+        for _ in 0..iterations {
+            filter.encode(gpu_context, &mut encoder, input, output);
+        }
+        let submission_index = gpu_context.queue.submit([encoder.finish()]);
         gpu_context
             .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("uniforms buffer"),
-                usage: wgpu::BufferUsages::UNIFORM,
-                contents: bytemuck::bytes_of(&[img.width(), img.height(), radius]),
-            });
-
-    let img_buffer = gpu_context
-        .device
-        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("img buffer"),
-            usage: wgpu::BufferUsages::STORAGE,
-            contents: img.as_raw(),
-        });
-
-    let out_buffer = gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("out buffer"),
-        size: img_buffer.size(),
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
-    });
-
-    let staging_buffer = gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("staging buffer"),
-        size: img_buffer.size(),
-        usage: wgpu::BufferUsages::MAP_READ
-            | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-
-    let bind_group = gpu_context
-        .device
-        .create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("img bind group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniforms_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: img_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: out_buffer.as_entire_binding(),
-                },
-            ],
-        });
-
-    let mut encoder = gpu_context
-        .device
-        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("command encoder"),
-        });
-
-    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-        label: Some("compute pass"),
-        timestamp_writes: None,
-    });
-
-    compute_pass.set_pipeline(&pipeline);
-    compute_pass.set_bind_group(0, Some(&bind_group), &[]);
-
-    // assuming a workgroup size of 8, 8, 1
-    // we need to dispatch width / 8 work groups in x and y
-    // we use the ceil of this quantity to make sure we always have enough
-    // work groups
-    let wg_x = (img.width() + 8 - 1) / 8;
-    let wg_y = (img.height() + 8 - 1) / 8;
-
-    compute_pass.dispatch_workgroups(wg_x, wg_y, 1);
-    drop(compute_pass);
-
-    encoder.copy_buffer_to_buffer(&out_buffer, 0, &staging_buffer, 0, out_buffer.size());
-
-    let submission_index = gpu_context.queue.submit([encoder.finish()]);
-    gpu_context
-        .device
-        .poll(wgpu::PollType::WaitForSubmissionIndex(submission_index))?;
-
-    // copy from buffer back to an image
-    let (tx, rx) = std::sync::mpsc::channel();
-    staging_buffer.map_async(wgpu::MapMode::Read, .., move |r| {
-        tx.send(r).unwrap();
-    });
-
-    gpu_context.device.poll(wgpu::PollType::Wait)?;
-
-    rx.recv()??;
-    let staging_buffer_view = staging_buffer.get_mapped_range(..);
-    let out_image = image::RgbaImage::from_vec(img.width(), img.height(), staging_buffer_view.to_vec())
-        .ok_or(anyhow::anyhow!("Unable to convert GPU buffer to image"))?;
-
-    drop(staging_buffer_view);
-    staging_buffer.unmap();
-
-    Ok(out_image)
-}
-
-pub fn demo(opts: Opts) -> anyhow::Result<()> {
-    let dynamic_img = image::ImageReader::open(opts.in_path)?.decode()?;
-
-    let img = dynamic_img.into_rgba8();
-
-    let gpu_context = GpuContext::new(wgpu::Features::empty())?;
-
-    let out_img = blur(&img, opts.radius, &gpu_context)?;
-
-    out_img.save(opts.out_path)?;
+            .poll(wgpu::PollType::WaitForSubmissionIndex(submission_index))?;
+        Ok(())
+    };
+    run(1)?;
+    let start = Instant::now();
+    run(iterations)?;
+    Ok(start.elapsed() / iterations)
+}
+
+#[derive(Args)]
+pub struct GaussianOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// Standard deviation of the gaussian in pixels
+    #[arg(default_value_t = 2.0)]
+    sigma: f32,
+}
+
+pub fn gaussian_demo(opts: GaussianOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    if !(opts.sigma >= 0.0 && opts.sigma.is_finite()) {
+        anyhow::bail!("sigma has to be a finite number >= 0, got {}", opts.sigma);
+    }
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(GaussianBlur::new(
+            gpu_context,
+            opts.sigma,
+            opts.filter.edge,
+            format,
+        )))
+    })
+}
+
+#[derive(Args)]
+pub struct MedianOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// Radius of the neighborhood the median is taken of, up to 12
+    #[arg(default_value_t = 1)]
+    radius: u32,
+}
+
+pub fn median_demo(opts: MedianOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Median::new(
+            gpu_context,
+            opts.radius,
+            opts.filter.edge,
+            format,
+        )?))
+    })
+}
+
+#[derive(Args)]
+pub struct BilateralOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// Standard deviation of the spatial gaussian in pixels
+    #[arg(default_value_t = 3.0)]
+    sigma_space: f32,
+    /// Standard deviation of the gaussian of the color difference, colors
+    /// go from 0 to 1
+    #[arg(long, default_value_t = 0.1)]
+    sigma_range: f32,
+}
+
+pub fn bilateral_demo(opts: BilateralOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Bilateral::new(
+            gpu_context,
+            opts.sigma_space,
+            opts.sigma_range,
+            opts.filter.edge,
+            format,
+        )?))
+    })
+}
+
+/// Where the kernel comes from, exactly one of them is given.
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct KernelSource {
+    /// Text file with one row of the kernel per line, the weights separated
+    /// by whitespace or commas
+    #[arg(long, value_name = "FILE")]
+    kernel: Option<PathBuf>,
+    /// Use a built in kernel
+    #[arg(long, value_enum)]
+    preset: Option<Preset>,
+}
 
+impl KernelSource {
+    fn load(&self) -> anyhow::Result<Kernel> {
+        match (&self.kernel, self.preset) {
+            (Some(path), _) => Kernel::parse(&std::fs::read_to_string(path)?),
+            (None, Some(preset)) => Ok(Kernel::preset(preset)),
+            (None, None) => unreachable!("clap requires one of them"),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ConvolveOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    #[command(flatten)]
+    kernel: KernelSource,
+    #[command(flatten)]
+    params: ConvolveParams,
+}
+
+pub fn convolve_demo(opts: ConvolveOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let kernel = opts.kernel.load()?;
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Convolution::new(
+            gpu_context,
+            &kernel,
+            &opts.params,
+            opts.filter.edge,
+            format,
+        )?))
+    })
+}
+
+/// Like [`convolve_demo`], computed with FFTs.
+pub fn fft_convolve_demo(opts: ConvolveOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let kernel = opts.kernel.load()?;
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(FftConvolution::new(
+            gpu_context,
+            &kernel,
+            &opts.params,
+            opts.filter.edge,
+            format,
+        )?))
+    })
+}
+
+#[derive(Args)]
+pub struct EdgesOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// What is written, the gradient magnitude or the Canny edges
+    #[arg(long, value_enum, default_value_t)]
+    method: EdgeMethod,
+    /// Standard deviation of the gaussian blur the image is smoothed with
+    /// first, 0 to take the gradients of the image as it is
+    #[arg(long, default_value_t = 1.4)]
+    sigma: f32,
+    /// Gradient magnitude weak edges need, a step from black to white is 1
+    #[arg(long, default_value_t = 0.04)]
+    low: f32,
+    /// Gradient magnitude strong edges need, weak edges connected to them
+    /// become edges too
+    #[arg(long, default_value_t = 0.1)]
+    high: f32,
+}
+
+pub fn edges_demo(opts: EdgesOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Edges::new(
+            gpu_context,
+            opts.method,
+            opts.sigma,
+            opts.low,
+            opts.high,
+            opts.filter.edge,
+            format,
+        )?))
+    })
+}
+
+#[derive(Args)]
+pub struct ResizeOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// New size as WIDTHxHEIGHT. Leave out one of them, e.g. `512x`, to keep
+    /// the aspect ratio
+    #[arg(value_name = "SIZE")]
+    new_size: Size,
+    /// Kernel the image is resampled with
+    #[arg(long = "filter", value_enum, default_value_t)]
+    resize_filter: ResizeFilter,
+}
+
+pub fn resize_demo(opts: ResizeOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Resize::new(
+            gpu_context,
+            opts.new_size,
+            opts.resize_filter,
+            opts.filter.edge,
+            format,
+        )?))
+    })
+}
+
+#[derive(Args)]
+pub struct MipmapsOpts {
+    /// The output is one image per level, `out.png` becomes `out-0.png`,
+    /// `out-1.png` and so on, or a single KTX2 texture if it ends in `.ktx2`
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// Kernel the levels are filtered with
+    #[arg(long = "filter", value_enum, default_value_t)]
+    mip_filter: MipFilter,
+    /// Number of levels including the image itself, all of them down to 1x1
+    /// by default
+    #[arg(long)]
+    levels: Option<u32>,
+}
+
+pub fn mipmaps_demo(opts: MipmapsOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let filter_opts = &opts.filter;
+    if batch::is_batch(&filter_opts.in_path) {
+        anyhow::bail!("mipmaps takes a single image");
+    }
+    let gpu_context = GpuContext::new(gpu_opts, wgpu::Features::empty())?;
+    let img = image::ImageReader::open(&filter_opts.in_path)?.decode()?;
+    let format = filter_opts
+        .format
+        .unwrap_or_else(|| PixelFormat::for_image(&img));
+
+    let sizes = mipmaps::level_sizes(img.width(), img.height(), opts.levels);
+    let levels = mipmaps::generate(&gpu_context, &img, format, &sizes, |size| {
+        filter_opts.make_filter(&gpu_context, format, |gpu_context, format| {
+            Ok(Box::new(Resize::new(
+                gpu_context,
+                size,
+                opts.mip_filter.resize_filter(),
+                filter_opts.edge,
+                format,
+            )?))
+        })
+    })?;
+    let levels: Vec<_> = levels
+        .into_iter()
+        .map(|level| gpu_image::with_depth_of(level, &img))
+        .collect();
+    mipmaps::save(&filter_opts.out_path, &levels)
+}
+
+#[derive(Args)]
+pub struct HistogramOpts {
+    /// Path of image to count
+    in_path: PathBuf,
+    /// Where the histograms are written, a chart if it ends in `.png` and
+    /// JSON otherwise. Printed as JSON if left out
+    out_path: Option<PathBuf>,
+}
+
+pub fn histogram_demo(opts: HistogramOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let gpu_context = GpuContext::new(gpu_opts, wgpu::Features::empty())?;
+    let img = image::ImageReader::open(&opts.in_path)?.decode()?;
+    let histogram = HistogramData::gpu(&gpu_context, &img)?;
+    match opts.out_path {
+        Some(path)
+            if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("png")) =>
+        {
+            histogram.chart().save(path)?
+        }
+        Some(path) => std::fs::write(path, histogram.to_json())?,
+        None => println!("{}", histogram.to_json()),
+    }
     Ok(())
 }
+
+#[derive(Args)]
+pub struct EqualizeOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// Equalize tiles of the image separately with limited contrast (CLAHE)
+    /// instead of the whole image at once
+    #[arg(long)]
+    clahe: bool,
+    /// Number of tiles along each side with --clahe
+    #[arg(long, default_value_t = 8, requires = "clahe")]
+    tiles: u32,
+    /// Bins are cut off at this multiple of the average bin with --clahe,
+    /// lower values boost the contrast less
+    #[arg(long, default_value_t = 2.0, requires = "clahe")]
+    clip_limit: f32,
+    /// Which histograms are equalized
+    #[arg(long, value_enum, default_value_t)]
+    channels: EqualizeChannels,
+}
+
+pub fn equalize_demo(opts: EqualizeOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let (tiles, clip_limit) = match opts.clahe {
+        true => (opts.tiles, opts.clip_limit),
+        false => (1, 0.0),
+    };
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Equalize::new(
+            gpu_context,
+            tiles,
+            clip_limit,
+            opts.channels,
+            format,
+        )?))
+    })
+}
+
+#[derive(Args)]
+pub struct LevelsOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// Values at or below this become black, from 0 to 1
+    #[arg(long, default_value_t = 0.0, conflicts_with = "auto")]
+    black: f32,
+    /// Values at or above this become white, from 0 to 1
+    #[arg(long, default_value_t = 1.0, conflicts_with = "auto")]
+    white: f32,
+    /// The values in between are raised to 1 / gamma, above 1 brightens the
+    /// midtones
+    #[arg(long, default_value_t = 1.0)]
+    gamma: f32,
+    /// Take the black and white points from the histogram
+    #[arg(long)]
+    auto: bool,
+    /// Percent of the pixels that are clipped to black and to white by
+    /// --auto
+    #[arg(long, default_value_t = 0.5, requires = "auto")]
+    clip: f32,
+    /// Stretch red, green and blue by their own histograms with --auto
+    /// instead of the luma's, which also removes color casts
+    #[arg(long, requires = "auto")]
+    per_channel: bool,
+}
+
+pub fn levels_demo(opts: LevelsOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let points = match opts.auto {
+        true => LevelsPoints::Auto {
+            clip: opts.clip / 100.0,
+            per_channel: opts.per_channel,
+        },
+        false => LevelsPoints::Manual {
+            black: opts.black,
+            white: opts.white,
+        },
+    };
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Levels::new(
+            gpu_context,
+            points,
+            opts.gamma,
+            format,
+        )?))
+    })
+}
+
+#[derive(Args)]
+pub struct PipelineOpts {
+    #[command(flatten)]
+    filter: FilterOpts,
+    /// Comma separated stages run one after the other: `blur:RADIUS`,
+    /// `gaussian:SIGMA`, `grayscale`, `resize:WIDTHxHEIGHT` or the name of a
+    /// convolve preset, e.g. `blur:3,sharpen,grayscale,resize:512x512`
+    #[arg(value_delimiter = ',', required = true)]
+    stages: Vec<Stage>,
+}
+
+pub fn pipeline_demo(opts: PipelineOpts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    opts.filter.run(gpu_opts, |gpu_context, format| {
+        Ok(Box::new(Pipeline::new(
+            gpu_context,
+            &opts.stages,
+            opts.filter.edge,
+            format,
+        )?))
+    })
+}
@@ -0,0 +1,76 @@
+use clap::ValueEnum;
+
+/// What the blur does with the part of a pixel's neighborhood that is
+/// outside of the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Edge {
+    /// Repeat the pixels at the edge
+    Clamp,
+    /// Reflect the image at its edges, the edge pixel itself is repeated
+    Mirror,
+    /// Continue with the opposite side of the image
+    Wrap,
+    /// Average only the pixels inside the image
+    #[default]
+    Skip,
+    /// Count outside pixels as transparent black
+    Zero,
+}
+
+impl Edge {
+    /// Value of `shape.edge` in common.wgsl
+    pub fn to_shader(self) -> u32 {
+        match self {
+            Edge::Clamp => 0,
+            Edge::Mirror => 1,
+            Edge::Wrap => 2,
+            Edge::Skip => 3,
+            Edge::Zero => 4,
+        }
+    }
+
+    /// CPU version of `sample_index` in common.wgsl.
+    #[cfg(test)]
+    pub fn sample_index(self, i: i64, len: i64) -> Option<i64> {
+        if (0..len).contains(&i) {
+            return Some(i);
+        }
+        match self {
+            Edge::Clamp => Some(i.clamp(0, len - 1)),
+            Edge::Mirror => {
+                let m = i.rem_euclid(2 * len);
+                Some(if m >= len { 2 * len - 1 - m } else { m })
+            }
+            Edge::Wrap => Some(i.rem_euclid(len)),
+            Edge::Skip | Edge::Zero => None,
+        }
+    }
+}
+
+/// Straightforward CPU box blur the shaders are tested against.
+#[cfg(test)]
+pub fn blur_reference(img: &image::RgbaImage, radius: u32, edge: Edge) -> image::RgbaImage {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let r = radius as i64;
+    image::RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let mut sum = [0.0f64; 4];
+        let mut n = 0;
+        for sy in y - r..=y + r {
+            for sx in x - r..=x + r {
+                match (edge.sample_index(sx, w), edge.sample_index(sy, h)) {
+                    (Some(sx), Some(sy)) => {
+                        let p = img.get_pixel(sx as u32, sy as u32);
+                        for c in 0..4 {
+                            sum[c] += p[c] as f64;
+                        }
+                        n += 1;
+                    }
+                    _ if edge == Edge::Zero => n += 1,
+                    _ => {}
+                }
+            }
+        }
+        image::Rgba(sum.map(|s| (s / n as f64).round() as u8))
+    })
+}
@@ -0,0 +1,464 @@
+use clap::ValueEnum;
+use wgpu::util::DeviceExt;
+
+use super::{
+    edge::Edge,
+    filter::{Filter, Tiling},
+    gaussian::GaussianBlur,
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// What the edge detector writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum EdgeMethod {
+    /// The magnitude of the Sobel gradient as a grayscale image
+    Sobel,
+    /// A black and white map of one pixel wide edges
+    #[default]
+    Canny,
+}
+
+/// An edge detector compiled for one output format. The image is smoothed
+/// with a gaussian blur first and the gradients are taken of its luma.
+pub struct Edges {
+    blur: Option<GaussianBlur>,
+    blur_radius: u32,
+    edge: Edge,
+    thresholds_buffer: wgpu::Buffer,
+    /// The passes up to the gradient magnitude or the thresholded edges
+    passes: Vec<Pass>,
+    /// Hysteresis and turning its result into an image, only run for Canny
+    hysteresis: Option<Vec<Pass>>,
+}
+
+impl Edges {
+    const THRESHOLDS_BINDING: u32 = 9;
+    /// The union-find labels of the hysteresis passes, one u32 per pixel
+    const LABELS_BINDING: u32 = 10;
+
+    /// `low` and `high` are the Canny thresholds of the gradient magnitude,
+    /// which is 1 for a step from black to white.
+    pub fn new(
+        gpu_context: &GpuContext,
+        method: EdgeMethod,
+        sigma: f32,
+        low: f32,
+        high: f32,
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        if !(sigma >= 0.0 && sigma.is_finite()) {
+            anyhow::bail!("Sigma has to be a finite number >= 0, got {sigma}");
+        }
+        if !(0.0 <= low && low <= high && high.is_finite()) {
+            anyhow::bail!("The thresholds have to be 0 <= low <= high, got {low} and {high}");
+        }
+        let device = &gpu_context.device;
+        let thresholds_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("canny thresholds buffer"),
+            usage: wgpu::BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&[low, high]),
+        });
+
+        let sobel_module = device.create_shader_module(shader!("sobel.wgsl", format));
+        let canny_module = device.create_shader_module(shader!("canny.wgsl", format));
+        let thresholds_entry = [storage_buffer_entry(Self::THRESHOLDS_BINDING)];
+        let workgroups = |w: u32, h: u32| (w.div_ceil(8), h.div_ceil(8));
+        let pass = |module, entry_point, input_binding, output_format| {
+            Pass::new(
+                gpu_context,
+                module,
+                entry_point,
+                input_binding,
+                output_format,
+                &thresholds_entry,
+                workgroups,
+            )
+        };
+        let labels_pass = |entry_point, input_binding, output_format| {
+            Pass::new(
+                gpu_context,
+                &canny_module,
+                entry_point,
+                input_binding,
+                output_format,
+                &[wgpu::BindGroupLayoutEntry {
+                    binding: Self::LABELS_BINDING,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                workgroups,
+            )
+        };
+        let gradient = pass(&sobel_module, "gradient", 1, PixelFormat::Rgba32f);
+        let (passes, hysteresis) = match method {
+            EdgeMethod::Sobel => (
+                vec![gradient, pass(&sobel_module, "magnitude", 3, format)],
+                None,
+            ),
+            EdgeMethod::Canny => (
+                vec![
+                    gradient,
+                    pass(&canny_module, "suppress", 1, PixelFormat::Rgba32f),
+                    pass(&canny_module, "threshold", 3, PixelFormat::Rgba32f),
+                ],
+                Some(vec![
+                    labels_pass("hysteresis_init", 5, PixelFormat::Rgba32f),
+                    labels_pass("hysteresis_merge", 5, PixelFormat::Rgba32f),
+                    labels_pass("hysteresis_mark", 5, PixelFormat::Rgba32f),
+                    labels_pass("binarize", 7, format),
+                ]),
+            ),
+        };
+
+        let blur = (sigma > 0.0)
+            .then(|| GaussianBlur::new(gpu_context, sigma, edge, PixelFormat::Rgba32f));
+        Ok(Self {
+            blur,
+            blur_radius: super::gaussian::weights(sigma).len() as u32 / 2,
+            edge,
+            thresholds_buffer,
+            passes,
+            hysteresis,
+        })
+    }
+}
+
+impl Filter for Edges {
+    /// Whether a weak edge becomes an edge can depend on pixels anywhere in
+    /// the image, so Canny only works on whole images.
+    fn tiling(&self) -> Option<Tiling> {
+        match self.hysteresis {
+            Some(_) => None,
+            None => Some(Tiling::new(self.blur_radius + 1, self.edge)),
+        }
+    }
+
+    /// Canny keeps a label per pixel in one storage buffer, which limits the
+    /// images to fewer pixels than the textures could hold.
+    fn max_size(&self, gpu_context: &GpuContext) -> u32 {
+        let limits = gpu_context.device.limits();
+        let labels = limits.max_storage_buffer_binding_size / 4;
+        match self.hysteresis {
+            Some(_) => limits
+                .max_texture_dimension_2d
+                .min((labels as f64).sqrt() as u32),
+            None => limits.max_texture_dimension_2d,
+        }
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        let (width, height) = (input.width(), input.height());
+        let blurred;
+        let source = match &self.blur {
+            Some(blur) => {
+                blurred = GpuImage::new(gpu_context, width, height, PixelFormat::Rgba32f);
+                blur.encode(gpu_context, encoder, input, &blurred);
+                &blurred
+            }
+            None => input,
+        };
+
+        let thresholds_entry = [wgpu::BindGroupEntry {
+            binding: Self::THRESHOLDS_BINDING,
+            resource: self.thresholds_buffer.as_entire_binding(),
+        }];
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [width, height, 0, self.edge.to_shader()];
+        let encode = |encoder: &mut wgpu::CommandEncoder,
+                      passes,
+                      extra_entries: &[wgpu::BindGroupEntry],
+                      input,
+                      output| {
+            encode_passes(
+                gpu_context,
+                encoder,
+                passes,
+                bytemuck::bytes_of(&shape),
+                extra_entries,
+                input,
+                output,
+            )
+        };
+        let Some(hysteresis) = &self.hysteresis else {
+            encode(encoder, &self.passes, &thresholds_entry, source, output);
+            return;
+        };
+
+        let states = GpuImage::new(gpu_context, width, height, PixelFormat::Rgba32f);
+        encode(encoder, &self.passes, &thresholds_entry, source, &states);
+        let labels = gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("canny labels buffer"),
+            size: (width * height) as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        encode(
+            encoder,
+            hysteresis,
+            &[wgpu::BindGroupEntry {
+                binding: Self::LABELS_BINDING,
+                resource: labels.as_entire_binding(),
+            }],
+            &states,
+            output,
+        );
+    }
+}
+
+/// Luma of `img` blurred and the Sobel gradient of it computed on the CPU in
+/// the same order as the shaders, as `[gx, gy, magnitude]` per pixel.
+#[cfg(test)]
+fn gradients_reference(img: &image::Rgba32FImage, sigma: f32, edge: Edge) -> Vec<[f32; 3]> {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let weights = super::gaussian::weights(sigma);
+    let r = (weights.len() / 2) as i64;
+    let blur = |get: &dyn Fn(i64, i64) -> [f32; 4], horizontal: bool| {
+        let mut out = vec![[0.0f32; 4]; (w * h) as usize];
+        for y in 0..h {
+            for x in 0..w {
+                let mut accum = [0.0f32; 4];
+                let mut weight_sum = 0.0f32;
+                for i in -r..=r {
+                    let sample = match horizontal {
+                        true => edge.sample_index(x + i, w).map(|sx| (sx, y)),
+                        false => edge.sample_index(y + i, h).map(|sy| (x, sy)),
+                    };
+                    if let Some((sx, sy)) = sample {
+                        let weight = weights[(i + r) as usize];
+                        let p = get(sx, sy);
+                        for c in 0..4 {
+                            accum[c] += weight * p[c];
+                        }
+                        weight_sum += weight;
+                    }
+                }
+                if edge == Edge::Skip {
+                    accum = accum.map(|a| a / weight_sum);
+                }
+                out[(y * w + x) as usize] = accum;
+            }
+        }
+        out
+    };
+    let rows = blur(&|x, y| img.get_pixel(x as u32, y as u32).0, true);
+    let blurred = blur(&|x, y| rows[(y * w + x) as usize], false);
+    let luma = |p: [f32; 4]| p[0] * 0.2126 + p[1] * 0.7152 + p[2] * 0.0722;
+
+    let mut gradients = Vec::with_capacity((w * h) as usize);
+    for y in 0..h {
+        for x in 0..w {
+            let center = luma(blurred[(y * w + x) as usize]);
+            let mut g = [0.0f32; 2];
+            for dy in -1..=1i64 {
+                for dx in -1..=1i64 {
+                    let v = match (edge.sample_index(x + dx, w), edge.sample_index(y + dy, h)) {
+                        (Some(sx), Some(sy)) => luma(blurred[(sy * w + sx) as usize]),
+                        _ if edge == Edge::Skip => center,
+                        _ => 0.0,
+                    };
+                    g[0] += v * (dx * (2 - dy.abs())) as f32;
+                    g[1] += v * (dy * (2 - dx.abs())) as f32;
+                }
+            }
+            let g = g.map(|g| g / 4.0);
+            gradients.push([g[0], g[1], (g[0] * g[0] + g[1] * g[1]).sqrt()]);
+        }
+    }
+    gradients
+}
+
+/// Straightforward CPU Canny, with the hysteresis following the edges
+/// however far they go.
+#[cfg(test)]
+pub fn canny_reference(
+    img: &image::DynamicImage,
+    sigma: f32,
+    low: f32,
+    high: f32,
+    edge: Edge,
+) -> image::GrayImage {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let gradients = gradients_reference(&img.to_rgba32f(), sigma, edge);
+    let magnitude = |x: i64, y: i64| match (0..w).contains(&x) && (0..h).contains(&y) {
+        true => gradients[(y * w + x) as usize][2],
+        false => 0.0,
+    };
+    const TAN_22_5: f32 = 0.41421356;
+    let mut strong = vec![false; (w * h) as usize];
+    let mut weak = vec![false; (w * h) as usize];
+    let mut stack = Vec::new();
+    for y in 0..h {
+        for x in 0..w {
+            let [gx, gy, m] = gradients[(y * w + x) as usize];
+            let (dx, dy) = if gy.abs() <= TAN_22_5 * gx.abs() {
+                (1, 0)
+            } else if gx.abs() <= TAN_22_5 * gy.abs() {
+                (0, 1)
+            } else if gx * gy > 0.0 {
+                (1, 1)
+            } else {
+                (-1, 1)
+            };
+            if !(m > 0.0 && m >= magnitude(x - dx, y - dy) && m > magnitude(x + dx, y + dy)) {
+                continue;
+            }
+            let i = (y * w + x) as usize;
+            if m >= high {
+                strong[i] = true;
+                stack.push((x, y));
+            } else if m >= low {
+                weak[i] = true;
+            }
+        }
+    }
+    while let Some((x, y)) = stack.pop() {
+        for (nx, ny) in (y - 1..=y + 1).flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny))) {
+            if !((0..w).contains(&nx) && (0..h).contains(&ny)) {
+                continue;
+            }
+            let i = (ny * w + nx) as usize;
+            if weak[i] && !strong[i] {
+                strong[i] = true;
+                stack.push((nx, ny));
+            }
+        }
+    }
+    image::GrayImage::from_fn(w as u32, h as u32, |x, y| {
+        image::Luma([if strong[(y as i64 * w + x as i64) as usize] {
+            255
+        } else {
+            0
+        }])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EdgeMethod, Edges, canny_reference, gradients_reference};
+    use crate::{
+        box_blur_2d::{edge::Edge, filter},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    fn edges(
+        gpu_context: &GpuContext,
+        img: &image::DynamicImage,
+        method: EdgeMethod,
+        sigma: f32,
+        edge: Edge,
+    ) -> image::GrayImage {
+        let format = PixelFormat::Rgba8;
+        let edges = Edges::new(gpu_context, method, sigma, 0.1, 0.2, edge, format).unwrap();
+        filter::apply(gpu_context, &edges, img, format)
+            .unwrap()
+            .into_luma8()
+    }
+
+    #[test]
+    fn test_edges_match_references() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let lenna = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap();
+        for edge in [Edge::Clamp, Edge::Skip, Edge::Zero] {
+            let sobel = edges(&gpu_context, &lenna, EdgeMethod::Sobel, 1.0, edge);
+            let gradients = gradients_reference(&lenna.to_rgba32f(), 1.0, edge);
+            for (p, g) in sobel.pixels().zip(gradients) {
+                let expected = (g[2].min(1.0) * 255.0).round() as u8;
+                assert!(p[0].abs_diff(expected) <= 1, "{edge:?} {p:?} {g:?}");
+            }
+
+            // the face
+            let face = lenna.crop_imm(192, 192, 256, 256);
+            let canny = edges(&gpu_context, &face, EdgeMethod::Canny, 1.4, edge);
+            let expected = canny_reference(&face, 1.4, 0.1, 0.2, edge);
+            let edge_pixels = expected.pixels().filter(|p| p[0] == 255).count();
+            assert!((500..20_000).contains(&edge_pixels), "{edge_pixels}");
+            // rounding differences can tip pixels over a threshold or make
+            // them a maximum
+            let mismatched = canny
+                .pixels()
+                .zip(expected.pixels())
+                .filter(|(a, b)| a != b);
+            assert!(mismatched.count() < edge_pixels / 200, "{edge:?}");
+        }
+    }
+
+    #[test]
+    fn test_hysteresis_follows_weak_edge() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // a vertical step that is strong at the top and fades to a weak one
+        // over 10 rows, below that it is only an edge because it connects to
+        // the top across several tiles
+        let img = image::RgbaImage::from_fn(100, 70, |x, y| {
+            let v = match x < 50 {
+                true => 51,
+                false => 128 + (123 * 10u32.saturating_sub(y) / 10) as u8,
+            };
+            image::Rgba([v, v, v, 255])
+        });
+        let canny = edges(
+            &gpu_context,
+            &img.into(),
+            EdgeMethod::Canny,
+            1.4,
+            Edge::Clamp,
+        );
+        for y in 12..70 {
+            let row: Vec<u32> = (0..100)
+                .filter(|&x| canny.get_pixel(x, y)[0] == 255)
+                .collect();
+            assert!(
+                row.len() == 1 && row[0].abs_diff(50) <= 1,
+                "row {y}: {row:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_hysteresis_follows_serpentine() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // a band with weak edges winding right and left across the tiles,
+        // down one tile at every turn, strong only at its start
+        let in_band = |x: u32, y: u32| {
+            let (row, in_row) = (y.saturating_sub(8) / 16, y.saturating_sub(8) % 16);
+            let run = y >= 8 && row < 6 && in_row < 4 && (6..106).contains(&x);
+            let turn_x = if row % 2 == 0 { 102..106 } else { 6..10 };
+            let turn = y >= 8 && row < 5 && turn_x.contains(&x);
+            run || turn
+        };
+        let img = image::RgbaImage::from_fn(112, 104, |x, y| {
+            let v = match in_band(x, y) {
+                true if y < 12 && x < 14 => 230,
+                true => 140,
+                false => 100,
+            };
+            image::Rgba([v, v, v, 255])
+        });
+        let img = image::DynamicImage::from(img);
+        let canny = edges(&gpu_context, &img, EdgeMethod::Canny, 0.0, Edge::Clamp);
+        let expected = canny_reference(&img, 0.0, 0.1, 0.2, Edge::Clamp);
+        // the edges reach the end of the last run, at the bottom left
+        assert!((86..94).any(|y| (4..12).any(|x| expected.get_pixel(x, y)[0] == 255)));
+        assert!(canny == expected);
+    }
+}
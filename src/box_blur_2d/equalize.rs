@@ -0,0 +1,243 @@
+use clap::ValueEnum;
+use wgpu::util::DeviceExt;
+
+use super::{
+    filter::{Filter, Tiling},
+    histogram::Histogram,
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// Which histograms an image is equalized with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum EqualizeChannels {
+    /// Equalize the luma and shift the colors by as much, keeping the hue
+    #[default]
+    Luma,
+    /// Equalize red, green and blue separately, which shifts the colors
+    Rgb,
+}
+
+impl EqualizeChannels {
+    /// Value of `params.channels` in equalize.wgsl
+    fn to_shader(self) -> u32 {
+        match self {
+            EqualizeChannels::Luma => 0,
+            EqualizeChannels::Rgb => 1,
+        }
+    }
+}
+
+/// Histogram equalization compiled for one output format. With more than
+/// one tile per side it is CLAHE: every tile is equalized on its own, with
+/// its bins clipped at `clip_limit` times the average bin to limit how much
+/// the contrast of flat regions is boosted, and the tiles are blended.
+pub struct Equalize {
+    histogram: Histogram,
+    tiles: u32,
+    clip_limit: f32,
+    channels: EqualizeChannels,
+    pass: Pass,
+}
+
+impl Equalize {
+    const EQUALIZED_BINDING: u32 = 3;
+    const PARAMS_BINDING: u32 = 4;
+
+    pub fn new(
+        gpu_context: &GpuContext,
+        tiles: u32,
+        clip_limit: f32,
+        channels: EqualizeChannels,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        if tiles == 0 {
+            anyhow::bail!("There has to be at least one tile");
+        }
+        if !(clip_limit >= 0.0 && clip_limit.is_finite()) {
+            anyhow::bail!("The clip limit has to be a finite number >= 0, got {clip_limit}");
+        }
+        let shader_module = gpu_context
+            .device
+            .create_shader_module(shader!("equalize.wgsl", format));
+        let pass = Pass::new(
+            gpu_context,
+            &shader_module,
+            "main",
+            1,
+            format,
+            &[
+                storage_buffer_entry(Self::EQUALIZED_BINDING),
+                storage_buffer_entry(Self::PARAMS_BINDING),
+            ],
+            |w, h| (w.div_ceil(8), h.div_ceil(8)),
+        );
+        Ok(Self {
+            histogram: Histogram::new(gpu_context),
+            tiles,
+            clip_limit,
+            channels,
+            pass,
+        })
+    }
+}
+
+impl Filter for Equalize {
+    fn tiling(&self) -> Option<Tiling> {
+        None
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        let (width, height) = (input.width(), input.height());
+        let buffers = self.histogram.encode(
+            gpu_context,
+            encoder,
+            input,
+            (self.tiles, self.tiles),
+            self.clip_limit,
+        );
+
+        // the `Params` struct in equalize.wgsl
+        let params = [
+            buffers.tiles.0,
+            buffers.tiles.1,
+            buffers.tile_size.0,
+            buffers.tile_size.1,
+            self.channels.to_shader(),
+        ];
+        let params_buffer =
+            gpu_context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("equalize params buffer"),
+                    usage: wgpu::BufferUsages::STORAGE,
+                    contents: bytemuck::cast_slice(&params),
+                });
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [width, height, 0, 0];
+        encode_passes(
+            gpu_context,
+            encoder,
+            std::slice::from_ref(&self.pass),
+            bytemuck::bytes_of(&shape),
+            &[
+                wgpu::BindGroupEntry {
+                    binding: Self::EQUALIZED_BINDING,
+                    resource: buffers.equalized.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::PARAMS_BINDING,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+            input,
+            output,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Equalize, EqualizeChannels};
+    use crate::{
+        box_blur_2d::{filter, histogram::histogram_reference},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    fn equalize(
+        gpu_context: &GpuContext,
+        img: &image::RgbaImage,
+        tiles: u32,
+        clip_limit: f32,
+        channels: EqualizeChannels,
+    ) -> image::RgbaImage {
+        let format = PixelFormat::Rgba8;
+        let equalize = Equalize::new(gpu_context, tiles, clip_limit, channels, format).unwrap();
+        filter::apply(gpu_context, &equalize, &img.clone().into(), format)
+            .unwrap()
+            .into_rgba8()
+    }
+
+    #[test]
+    fn test_global_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // low contrast noise
+        let img = image::RgbaImage::from_fn(37, 23, |x, y| {
+            let v = |c: u32| 100 + ((x * 7919 + y * 104729 + c * 31) % 41) as u8;
+            image::Rgba([v(0), v(1), v(2), 255])
+        });
+        let out = equalize(&gpu_context, &img, 1, 0.0, EqualizeChannels::Rgb);
+
+        let cdf = histogram_reference(&img.clone().into()).cdf;
+        let pixels = img.width() * img.height();
+        for (p, q) in img.pixels().zip(out.pixels()) {
+            for c in 0..3 {
+                let cdf_min = *cdf[c].iter().find(|&&n| n > 0).unwrap();
+                let expected = (cdf[c][p[c] as usize] - cdf_min) as f32 / (pixels - cdf_min) as f32;
+                let expected = (expected * 255.0).round() as u8;
+                assert!(q[c].abs_diff(expected) <= 1, "{p:?} -> {q:?}");
+            }
+        }
+        // stretched to the whole range
+        let reds = || out.pixels().map(|p| p[0]);
+        assert_eq!((reds().min(), reds().max()), (Some(0), Some(255)));
+    }
+
+    #[test]
+    fn test_clahe_stretches_every_tile() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // a dark left and a bright right half, both with little contrast
+        let img = image::RgbaImage::from_fn(64, 32, |x, y| {
+            let base = if x < 32 { 30 } else { 190 };
+            let v = base + ((x * 7919 + y * 104729) % 31) as u8;
+            image::Rgba([v, v, v, 255])
+        });
+        // the spread of the middle of each half, which only depends on its
+        // own tile
+        let spreads = |clip_limit| {
+            let out = equalize(&gpu_context, &img, 2, clip_limit, EqualizeChannels::Luma);
+            [4..12, 52..60].map(|x_range| {
+                let values: Vec<u8> = x_range
+                    .flat_map(|x| (4..28).map(move |y| (x, y)))
+                    .map(|(x, y)| out.get_pixel(x, y)[0])
+                    .collect();
+                values.iter().max().unwrap() - values.iter().min().unwrap()
+            })
+        };
+        let unclipped = spreads(0.0);
+        let clipped = spreads(3.0);
+        for (unclipped, clipped) in unclipped.into_iter().zip(clipped) {
+            assert!(unclipped > 200, "{unclipped}");
+            // clipping limits the contrast, but it still goes up
+            assert!((90..unclipped).contains(&clipped), "{clipped} {unclipped}");
+        }
+    }
+
+    #[test]
+    fn test_clahe_tiles_not_dividing_the_image() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = image::RgbaImage::from_fn(10, 10, |x, y| {
+            let v = 60 + ((x * 7919 + y * 104729) % 97) as u8;
+            image::Rgba([v, v / 2, 255 - v, 255])
+        });
+        // 8 tiles are 2 pixels wide, so the image only has room for 5 and
+        // the last 3 would be empty
+        for channels in [EqualizeChannels::Luma, EqualizeChannels::Rgb] {
+            for clip_limit in [0.0, 2.0] {
+                let eight = equalize(&gpu_context, &img, 8, clip_limit, channels);
+                let five = equalize(&gpu_context, &img, 5, clip_limit, channels);
+                assert!(eight == five, "{channels:?} clip limit {clip_limit}");
+            }
+        }
+    }
+}
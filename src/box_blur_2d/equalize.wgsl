@@ -0,0 +1,72 @@
+// Maps every pixel through the equalized histograms computed by
+// histogram.wgsl. With more than one tile the mappings of the four tiles
+// whose centers surround the pixel are blended bilinearly, so the tile
+// edges don't show. `shape.r` and `shape.edge` are unused.
+
+struct Params {
+    tiles_x: u32,
+    tiles_y: u32,
+    tile_w: u32,
+    tile_h: u32,
+    // `EqualizeChannels::to_shader`
+    channels: u32,
+}
+
+const BINS: u32 = 256u;
+const TILE_BINS: u32 = 1024u;
+const LUMA: u32 = 3u;
+const CHANNELS_LUMA: u32 = 0u;
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(3) var<storage, read> equalized: array<f32>;
+@group(0) @binding(4) var<storage, read> params: Params;
+
+fn luma(color: vec4f) -> f32 {
+    return dot(color.rgb, vec3f(0.2126, 0.7152, 0.0722));
+}
+
+fn bin(v: f32) -> u32 {
+    return u32(clamp(v * f32(BINS), 0.0, f32(BINS - 1u)));
+}
+
+fn tile_value(tile: vec2u, channel: u32, v: f32) -> f32 {
+    return equalized[(tile.y * params.tiles_x + tile.x) * TILE_BINS + channel * BINS + bin(v)];
+}
+
+// equalized `v` of `channel` at `p`
+fn lookup(p: vec2u, channel: u32, v: f32) -> f32 {
+    // position in tiles relative to the tile centers
+    let t = (vec2f(p) + 0.5) / vec2f(f32(params.tile_w), f32(params.tile_h)) - 0.5;
+    let last = vec2i(i32(params.tiles_x), i32(params.tiles_y)) - 1;
+    let t0 = vec2u(clamp(vec2i(floor(t)), vec2i(0), last));
+    let t1 = vec2u(clamp(vec2i(floor(t)) + 1, vec2i(0), last));
+    let f = t - floor(t);
+    let top = mix(tile_value(t0, channel, v), tile_value(vec2u(t1.x, t0.y), channel, v), f.x);
+    let bottom = mix(tile_value(vec2u(t0.x, t1.y), channel, v), tile_value(t1, channel, v), f.x);
+    return mix(top, bottom, f.y);
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let color = textureLoad(img_in, vec2i(id.xy), 0);
+    var rgb: vec3f;
+    if (params.channels == CHANNELS_LUMA) {
+        // shifting all channels by the same amount changes the luma by that
+        // amount and keeps the hue
+        let y = luma(color);
+        rgb = clamp(color.rgb + lookup(id.xy, LUMA, y) - y, vec3f(0.0), vec3f(1.0));
+    } else {
+        rgb = vec3f(
+            lookup(id.xy, 0u, color.r),
+            lookup(id.xy, 1u, color.g),
+            lookup(id.xy, 2u, color.b),
+        );
+    }
+    textureStore(img_out, id.xy, vec4f(rgb, color.a));
+}
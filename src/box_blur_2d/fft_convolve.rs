@@ -0,0 +1,340 @@
+use wgpu::util::DeviceExt;
+
+use super::{
+    convolve::{ConvolveParams, Kernel},
+    edge::Edge,
+    filter::{Filter, Tiling},
+    pass::shader,
+};
+use crate::{
+    fft::{Direction, Fft, Spectrum},
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// A convolution like [`super::convolve::Convolution`] computed with FFTs,
+/// so the cost barely grows with the size of the kernel. The image and the
+/// kernel are padded to powers of two at least as large as the image plus
+/// the kernel.
+pub struct FftConvolution {
+    kernel_size: (u32, u32),
+    edge: Edge,
+    kernel_buffer: wgpu::Buffer,
+    fft: Fft,
+    load_image: wgpu::ComputePipeline,
+    load_kernel: wgpu::ComputePipeline,
+    store: wgpu::ComputePipeline,
+}
+
+impl FftConvolution {
+    pub fn new(
+        gpu_context: &GpuContext,
+        kernel: &Kernel,
+        params: &ConvolveParams,
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        let device = &gpu_context.device;
+        let kernel_buffer = params.kernel_buffer(gpu_context, kernel, edge)?;
+
+        let shader_module = device.create_shader_module(shader!("fft_convolve.wgsl", format));
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform = wgpu::BufferBindingType::Uniform;
+        let read = wgpu::BufferBindingType::Storage { read_only: true };
+        let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+        // only ever read with textureLoad, so rgba32float works without the
+        // float32-filterable feature
+        let img_in_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let img_out_entry = wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: format.texture_format(),
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let pipeline = |entry_point, entries: &[wgpu::BindGroupLayoutEntry]| {
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("fft convolve bind group layout"),
+                    entries,
+                });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("fft convolve pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        Ok(Self {
+            kernel_size: (kernel.width, kernel.height),
+            edge,
+            kernel_buffer,
+            fft: Fft::new(gpu_context),
+            load_image: pipeline(
+                "load_image",
+                &[
+                    buffer_entry(0, uniform),
+                    img_in_entry,
+                    buffer_entry(3, read),
+                    buffer_entry(4, uniform),
+                    buffer_entry(5, read_write),
+                ],
+            ),
+            load_kernel: pipeline(
+                "load_kernel",
+                &[
+                    buffer_entry(3, read),
+                    buffer_entry(4, uniform),
+                    buffer_entry(5, read_write),
+                ],
+            ),
+            store: pipeline(
+                "store",
+                &[
+                    buffer_entry(0, uniform),
+                    img_in_entry,
+                    img_out_entry,
+                    buffer_entry(3, read),
+                    buffer_entry(4, uniform),
+                    buffer_entry(6, read),
+                ],
+            ),
+        })
+    }
+
+    /// Size of the spectra for a `width` x `height` image, the image and
+    /// the neighborhoods of its edge pixels.
+    fn padded_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (
+            (width + self.kernel_size.0 - 1).next_power_of_two(),
+            (height + self.kernel_size.1 - 1).next_power_of_two(),
+        )
+    }
+}
+
+impl Filter for FftConvolution {
+    fn tiling(&self) -> Option<Tiling> {
+        let (width, height) = self.kernel_size;
+        Some(Tiling::new(width.max(height) / 2, self.edge))
+    }
+
+    /// The padded image has to fit in a storage buffer.
+    fn max_size(&self, gpu_context: &GpuContext) -> u32 {
+        let limits = gpu_context.device.limits();
+        let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let mut side = 1;
+        while Spectrum::size(side * 2, side * 2) <= max_bytes {
+            side *= 2;
+        }
+        let (width, height) = self.kernel_size;
+        (side + 1)
+            .saturating_sub(width.max(height))
+            .min(limits.max_texture_dimension_2d)
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        let device = &gpu_context.device;
+        let (width, height) = (input.width(), input.height());
+        let padded = self.padded_size(width, height);
+        let mut image_spectrum = Spectrum::new(gpu_context, padded.0, padded.1);
+        let mut kernel_spectrum = Spectrum::new(gpu_context, padded.0, padded.1);
+
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [width, height, 0, self.edge.to_shader()];
+        let uniform_buffer = |label, contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                usage: wgpu::BufferUsages::UNIFORM,
+                contents,
+            })
+        };
+        let shape_buffer = uniform_buffer("uniforms buffer", bytemuck::bytes_of(&shape));
+        let padded_buffer = uniform_buffer(
+            "padded size buffer",
+            bytemuck::bytes_of(&[padded.0, padded.1]),
+        );
+        let bind_group = |pipeline: &wgpu::ComputePipeline,
+                          entries: &[(u32, wgpu::BindingResource)]| {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(binding, resource)| wgpu::BindGroupEntry {
+                    binding: *binding,
+                    resource: resource.clone(),
+                })
+                .collect();
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("fft convolve bind group"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &entries,
+            })
+        };
+        let load_image = bind_group(
+            &self.load_image,
+            &[
+                (0, shape_buffer.as_entire_binding()),
+                (1, wgpu::BindingResource::TextureView(&input.view)),
+                (3, self.kernel_buffer.as_entire_binding()),
+                (4, padded_buffer.as_entire_binding()),
+                (5, image_spectrum.buffer().as_entire_binding()),
+            ],
+        );
+        let load_kernel = bind_group(
+            &self.load_kernel,
+            &[
+                (3, self.kernel_buffer.as_entire_binding()),
+                (4, padded_buffer.as_entire_binding()),
+                (5, kernel_spectrum.buffer().as_entire_binding()),
+            ],
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("fft convolve load pass"),
+            timestamp_writes: None,
+        });
+        for (pipeline, bind_group) in [
+            (&self.load_image, &load_image),
+            (&self.load_kernel, &load_kernel),
+        ] {
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, Some(bind_group), &[]);
+            compute_pass.dispatch_workgroups(padded.0.div_ceil(8), padded.1.div_ceil(8), 1);
+        }
+        drop(compute_pass);
+
+        self.fft.encode(
+            gpu_context,
+            encoder,
+            &mut image_spectrum,
+            Direction::Forward,
+        );
+        self.fft.encode(
+            gpu_context,
+            encoder,
+            &mut kernel_spectrum,
+            Direction::Forward,
+        );
+        self.fft
+            .multiply(gpu_context, encoder, &image_spectrum, &kernel_spectrum);
+        self.fft.encode(
+            gpu_context,
+            encoder,
+            &mut image_spectrum,
+            Direction::Inverse,
+        );
+
+        let store = bind_group(
+            &self.store,
+            &[
+                (0, shape_buffer.as_entire_binding()),
+                (1, wgpu::BindingResource::TextureView(&input.view)),
+                (2, wgpu::BindingResource::TextureView(&output.view)),
+                (3, self.kernel_buffer.as_entire_binding()),
+                (4, padded_buffer.as_entire_binding()),
+                (6, image_spectrum.buffer().as_entire_binding()),
+            ],
+        );
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("fft convolve store pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.store);
+        compute_pass.set_bind_group(0, Some(&store), &[]);
+        compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::FftConvolution;
+    use crate::{
+        box_blur_2d::{
+            convolve::{Convolution, ConvolveParams, Kernel, Normalize, Preset},
+            edge::Edge,
+            filter, random_image,
+        },
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    #[test]
+    fn test_matches_spatial_convolution() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img: image::DynamicImage = random_image(13, 9, 11).into();
+        let kernels = [
+            Kernel::preset(Preset::SobelX),
+            Kernel::preset(Preset::Sharpen),
+            Kernel::parse("1 2 1\n2 4 2\n1 2 1").unwrap(),
+            // even sizes are centered right of / below the middle
+            Kernel::parse("1 0.5\n0.25 -1\n2 1").unwrap(),
+            // reaches further than the image is wide
+            Kernel::parse(&"1 ".repeat(17)).unwrap(),
+        ];
+        let params = [
+            ConvolveParams::default(),
+            ConvolveParams {
+                normalize: Normalize::None,
+                bias: 0.5,
+                convolve_alpha: true,
+            },
+        ];
+        let format = PixelFormat::Rgba8;
+        for kernel in &kernels {
+            for params in &params {
+                for &edge in Edge::value_variants() {
+                    let spatial =
+                        Convolution::new(&gpu_context, kernel, params, edge, format).unwrap();
+                    let expected = filter::apply(&gpu_context, &spatial, &img, format)
+                        .unwrap()
+                        .into_rgba8();
+                    let fft =
+                        FftConvolution::new(&gpu_context, kernel, params, edge, format).unwrap();
+                    let out = filter::apply(&gpu_context, &fft, &img, format)
+                        .unwrap()
+                        .into_rgba8();
+                    for (a, b) in out.pixels().zip(expected.pixels()) {
+                        assert!(
+                            a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 1),
+                            "{kernel:?} {params:?} {edge:?}: {a:?} != {b:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+// Applies a kernel like convolve.wgsl, by multiplying the Fourier
+// transforms of the image and the kernel. `load_image` and `load_kernel`
+// write both into spectra of `padded` size, which leaves room for the
+// neighborhoods of the pixels at the edges, and after the transforms
+// `store` writes the result like convolve.wgsl would. `shape.r` is unused.
+
+// has to match `Complex4` in fft.wgsl
+struct Complex4 {
+    re: vec4f,
+    im: vec4f,
+}
+
+// has to match convolve.wgsl
+struct Kernel {
+    width: u32,
+    height: u32,
+    renormalize: u32,
+    convolve_alpha: u32,
+    bias: f32,
+    weights: array<f32>,
+}
+
+struct Padded {
+    width: u32,
+    height: u32,
+}
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(3) var<storage, read> kernel: Kernel;
+@group(0) @binding(4) var<uniform> padded: Padded;
+@group(0) @binding(5) var<storage, read_write> spectrum_out: array<Complex4>;
+@group(0) @binding(6) var<storage, read> spectrum_in: array<Complex4>;
+
+// The coordinate index `i` of a padded axis `len` long stands for. The
+// convolution wraps around, so the `before` coordinates below 0 are at the
+// end.
+fn coordinate(i: u32, len: u32, before: u32) -> i32 {
+    if (i + before >= len) {
+        return i32(i) - i32(len);
+    }
+    return i32(i);
+}
+
+// The image with its surroundings given by the edge mode. The convolution
+// is linear and the kernel real, so red and green share a complex number
+// as do blue and alpha. The third one counts the samples inside the image
+// for renormalizing with EDGE_SKIP.
+@compute
+@workgroup_size(8, 8, 1)
+fn load_image(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= padded.width || id.y >= padded.height) {
+        return;
+    }
+
+    let c = vec2i(
+        coordinate(id.x, padded.width, kernel.width / 2u),
+        coordinate(id.y, padded.height, kernel.height / 2u),
+    );
+    let sx = sample_index(c.x, i32(shape.w));
+    let sy = sample_index(c.y, i32(shape.h));
+    var color = vec4f(0.0);
+    var inside = 0.0;
+    if (sx >= 0 && sy >= 0) {
+        color = textureLoad(img_in, vec2i(sx, sy), 0);
+        inside = 1.0;
+    }
+    spectrum_out[id.y * padded.width + id.x] = Complex4(
+        vec4f(color.r, color.b, inside, 0.0),
+        vec4f(color.g, color.a, 0.0, 0.0),
+    );
+}
+
+// The kernel mirrored around its center, which goes to index 0, so the
+// convolution applies it unflipped like convolve.wgsl. Every complex number
+// of the image is multiplied with the same weight.
+@compute
+@workgroup_size(8, 8, 1)
+fn load_kernel(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= padded.width || id.y >= padded.height) {
+        return;
+    }
+
+    let center = vec2i(vec2u(kernel.width, kernel.height) / 2u);
+    let after = vec2u(kernel.width, kernel.height) - 1u - vec2u(center);
+    let k = center - vec2i(
+        coordinate(id.x, padded.width, after.x),
+        coordinate(id.y, padded.height, after.y),
+    );
+    var weight = 0.0;
+    if (all(k >= vec2i(0)) && k.x < i32(kernel.width) && k.y < i32(kernel.height)) {
+        weight = kernel.weights[u32(k.y) * kernel.width + u32(k.x)];
+    }
+    spectrum_out[id.y * padded.width + id.x] = Complex4(vec4f(weight), vec4f(0.0));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn store(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let c = spectrum_in[id.y * padded.width + id.x];
+    var accum = vec4f(c.re.x, c.im.x, c.re.y, c.im.y);
+    let weight_sum = c.re.z;
+    // sums of 0 come out of the transforms as rounding noise
+    if (kernel.renormalize != 0u && abs(weight_sum) > 1e-6) {
+        accum /= weight_sum;
+    }
+    var color = vec4f(accum.rgb + kernel.bias, accum.a);
+    if (kernel.convolve_alpha == 0u) {
+        color.a = textureLoad(img_in, vec2i(id.xy), 0).a;
+    }
+    textureStore(img_out, id.xy, color);
+}
@@ -0,0 +1,415 @@
+use image::{GenericImageView, ImageBuffer, Pixel};
+
+use super::edge::Edge;
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, ImageReadback, PixelFormat},
+};
+
+/// An image filter running on the GPU. It is created for one output format
+/// and can then be recorded for any number of images.
+pub trait Filter {
+    /// Size of the image the filter makes out of a `width` x `height` one.
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (width, height)
+    }
+
+    /// How the filter can be run on tiles of an image, None if it only works
+    /// on whole images. Filters have to opt in since tiles only give the
+    /// same pixels when the filter reads a bounded neighborhood.
+    fn tiling(&self) -> Option<Tiling> {
+        None
+    }
+
+    /// Largest width and height of image the filter takes in one piece,
+    /// larger images are split into tiles.
+    fn max_size(&self, gpu_context: &GpuContext) -> u32 {
+        gpu_context.device.limits().max_texture_dimension_2d
+    }
+
+    /// Record the passes filtering `input` into `output`, which has the size
+    /// given by `output_size` and the format the filter was created for.
+    /// Nothing is submitted or waited for, the caller decides when the
+    /// encoder goes to the GPU.
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    );
+}
+
+/// What a filter needs around a tile to give the same pixels as it does for
+/// the whole image.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tiling {
+    /// How many pixels around a pixel the filter reads
+    pub overlap: u32,
+    /// Whether it reads from the opposite side of the image at the edges, so
+    /// tiles at the edge have to be extended with that side
+    pub wrap: bool,
+}
+
+impl Tiling {
+    pub fn new(overlap: u32, edge: Edge) -> Self {
+        Self {
+            overlap,
+            wrap: edge == Edge::Wrap,
+        }
+    }
+
+    /// Tiling of one filter run after the other.
+    pub fn then(self, next: Tiling) -> Self {
+        Self {
+            overlap: self.overlap + next.overlap,
+            wrap: self.wrap || next.wrap,
+        }
+    }
+}
+
+/// Upload `img` in `format`, filter it and read the result back.
+pub fn apply(
+    gpu_context: &GpuContext,
+    filter: &dyn Filter,
+    img: &image::DynamicImage,
+    format: PixelFormat,
+) -> anyhow::Result<image::DynamicImage> {
+    submit(gpu_context, filter, img, format)?.finish(gpu_context)
+}
+
+/// Like [`apply`] but without waiting for the GPU, so the next image can be
+/// prepared while this one is filtered. Images larger than the filter's
+/// [`Filter::max_size`] are split into tiles.
+pub fn submit(
+    gpu_context: &GpuContext,
+    filter: &dyn Filter,
+    img: &image::DynamicImage,
+    format: PixelFormat,
+) -> anyhow::Result<PendingImage> {
+    let tile_size = filter.max_size(gpu_context);
+    submit_tiled(gpu_context, filter, img, format, tile_size)
+}
+
+/// Filter `img` in tiles of at most `tile_size` x `tile_size` pixels if it
+/// is larger than that.
+fn submit_tiled(
+    gpu_context: &GpuContext,
+    filter: &dyn Filter,
+    img: &image::DynamicImage,
+    format: PixelFormat,
+    tile_size: u32,
+) -> anyhow::Result<PendingImage> {
+    let (width, height) = img.dimensions();
+    if width <= tile_size && height <= tile_size {
+        let input = GpuImage::upload(gpu_context, img, format);
+        return Ok(PendingImage::Whole(submit_image(
+            gpu_context,
+            filter,
+            &input,
+        )?));
+    }
+
+    let Some(tiling) = filter.tiling() else {
+        anyhow::bail!(
+            "The {width}x{height} image is larger than the {tile_size}x{tile_size} textures \
+             of the GPU and the filter can't be split into tiles"
+        );
+    };
+    let core_size = tile_size.saturating_sub(2 * tiling.overlap);
+    if core_size == 0 {
+        anyhow::bail!(
+            "The filter reads {} pixels around every pixel, too many for tiles of \
+             {tile_size}x{tile_size}",
+            tiling.overlap
+        );
+    }
+    let tiles = match format {
+        PixelFormat::Rgba8 => submit_tiles(
+            gpu_context,
+            filter,
+            &img.to_rgba8(),
+            format,
+            tiling,
+            core_size,
+        ),
+        PixelFormat::Rgba16f | PixelFormat::Rgba32f => submit_tiles(
+            gpu_context,
+            filter,
+            &img.to_rgba32f(),
+            format,
+            tiling,
+            core_size,
+        ),
+    }?;
+    Ok(PendingImage::Tiled {
+        width,
+        height,
+        format,
+        tiles,
+    })
+}
+
+/// The part of the image a tile is responsible for, in image coordinates,
+/// and where it is in the filtered tile.
+pub struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    x_in_tile: u32,
+    y_in_tile: u32,
+}
+
+fn submit_tiles<P: Pixel>(
+    gpu_context: &GpuContext,
+    filter: &dyn Filter,
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    format: PixelFormat,
+    tiling: Tiling,
+    core_size: u32,
+) -> anyhow::Result<Vec<(Tile, ImageReadback)>>
+where
+    image::DynamicImage: From<ImageBuffer<P, Vec<P::Subpixel>>>,
+{
+    let (width, height) = img.dimensions();
+    // the tiles overlap by `tiling.overlap` pixels, at the edges of the
+    // image they only do so when the filter wraps around
+    let extend = |start: u32, size: u32, len: u32| -> (i64, i64) {
+        let (from, to) = (
+            start as i64 - tiling.overlap as i64,
+            (start + size + tiling.overlap) as i64,
+        );
+        match tiling.wrap {
+            true => (from, to),
+            false => (from.max(0), to.min(len as i64)),
+        }
+    };
+
+    let mut tiles = vec![];
+    for y in (0..height).step_by(core_size as usize) {
+        for x in (0..width).step_by(core_size as usize) {
+            let (tile_width, tile_height) = (core_size.min(width - x), core_size.min(height - y));
+            let (x_from, x_to) = extend(x, tile_width, width);
+            let (y_from, y_to) = extend(y, tile_height, height);
+            let input =
+                ImageBuffer::from_fn((x_to - x_from) as u32, (y_to - y_from) as u32, |tx, ty| {
+                    *img.get_pixel(
+                        (x_from + tx as i64).rem_euclid(width as i64) as u32,
+                        (y_from + ty as i64).rem_euclid(height as i64) as u32,
+                    )
+                });
+            let input = GpuImage::upload(gpu_context, &input.into(), format);
+            let readback = submit_image(gpu_context, filter, &input)?;
+            let tile = Tile {
+                x,
+                y,
+                width: tile_width,
+                height: tile_height,
+                x_in_tile: (x as i64 - x_from) as u32,
+                y_in_tile: (y as i64 - y_from) as u32,
+            };
+            tiles.push((tile, readback));
+        }
+    }
+    Ok(tiles)
+}
+
+fn submit_image(
+    gpu_context: &GpuContext,
+    filter: &dyn Filter,
+    input: &GpuImage,
+) -> anyhow::Result<ImageReadback> {
+    let (width, height) = filter.output_size(input.width(), input.height());
+    let max_size = gpu_context.device.limits().max_texture_dimension_2d;
+    if width > max_size || height > max_size {
+        anyhow::bail!(
+            "The filter makes a {width}x{height} image, larger than the {max_size}x{max_size} \
+             textures of the GPU"
+        );
+    }
+    let output = GpuImage::new(gpu_context, width, height, input.format);
+    let mut encoder = gpu_context
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("command encoder"),
+        });
+    filter.encode(gpu_context, &mut encoder, input, &output);
+    output.submit_read(gpu_context, encoder)
+}
+
+/// A filtered image on its way back from the GPU, in one piece or in tiles.
+pub enum PendingImage {
+    Whole(ImageReadback),
+    Tiled {
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        tiles: Vec<(Tile, ImageReadback)>,
+    },
+}
+
+impl PendingImage {
+    /// Wait for the GPU and put the tiles back together.
+    pub fn finish(self, gpu_context: &GpuContext) -> anyhow::Result<image::DynamicImage> {
+        let (width, height, format, tiles) = match self {
+            PendingImage::Whole(readback) => return readback.finish(gpu_context),
+            PendingImage::Tiled {
+                width,
+                height,
+                format,
+                tiles,
+            } => (width, height, format, tiles),
+        };
+
+        let mut img = match format {
+            PixelFormat::Rgba8 => image::RgbaImage::new(width, height).into(),
+            PixelFormat::Rgba16f | PixelFormat::Rgba32f => {
+                image::Rgba32FImage::new(width, height).into()
+            }
+        };
+        for (tile, readback) in tiles {
+            let filtered = readback.finish(gpu_context)?;
+            match (&mut img, filtered) {
+                (
+                    image::DynamicImage::ImageRgba8(img),
+                    image::DynamicImage::ImageRgba8(filtered),
+                ) => place(img, &filtered, &tile),
+                (
+                    image::DynamicImage::ImageRgba32F(img),
+                    image::DynamicImage::ImageRgba32F(filtered),
+                ) => place(img, &filtered, &tile),
+                _ => unreachable!("tiles are read back in the format of the image"),
+            }
+        }
+        Ok(img)
+    }
+}
+
+fn place<P: Pixel>(
+    img: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    filtered: &ImageBuffer<P, Vec<P::Subpixel>>,
+    tile: &Tile,
+) {
+    let core = filtered.view(tile.x_in_tile, tile.y_in_tile, tile.width, tile.height);
+    image::imageops::replace(img, &*core, tile.x as i64, tile.y as i64);
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::{Filter, apply, submit_tiled};
+    use crate::{
+        box_blur_2d::{
+            blur::{Algorithm, BoxBlur},
+            convolve::{Convolution, ConvolveParams, Kernel, Preset},
+            edge::Edge,
+            gaussian::GaussianBlur,
+            pipeline::{Pipeline, Stage},
+            resize::{Resize, ResizeFilter, Size},
+        },
+        gpu_context::GpuContext,
+        gpu_image::{GpuImage, PixelFormat},
+    };
+
+    #[test]
+    fn test_tiles_match_whole_image() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let lenna = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap();
+        let img = lenna.crop_imm(200, 200, 70, 45);
+        let format = PixelFormat::Rgba32f;
+        let tile_size = 24;
+
+        for &edge in Edge::value_variants() {
+            let filters: [Box<dyn Filter>; 5] = [
+                Box::new(BoxBlur::new(&gpu_context, 3, edge, Algorithm::Naive, format).unwrap()),
+                Box::new(
+                    BoxBlur::new(&gpu_context, 5, edge, Algorithm::Separable, format).unwrap(),
+                ),
+                Box::new(GaussianBlur::new(&gpu_context, 1.5, edge, format)),
+                Box::new(
+                    Convolution::new(
+                        &gpu_context,
+                        &Kernel::preset(Preset::Emboss),
+                        &ConvolveParams::default(),
+                        edge,
+                        format,
+                    )
+                    .unwrap(),
+                ),
+                Box::new(
+                    Pipeline::new(
+                        &gpu_context,
+                        &[
+                            Stage::BoxBlur(2),
+                            Stage::Grayscale,
+                            Stage::Convolve(Preset::Sharpen),
+                        ],
+                        edge,
+                        format,
+                    )
+                    .unwrap(),
+                ),
+            ];
+            for (i, filter) in filters.iter().enumerate() {
+                let whole = apply(&gpu_context, filter.as_ref(), &img, format).unwrap();
+                let tiled = submit_tiled(&gpu_context, filter.as_ref(), &img, format, tile_size)
+                    .unwrap()
+                    .finish(&gpu_context)
+                    .unwrap();
+                assert!(
+                    whole.into_rgba32f() == tiled.into_rgba32f(),
+                    "filter {i} {edge:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_untileable() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = image::DynamicImage::new_rgba8(40, 40);
+        let format = PixelFormat::Rgba8;
+        let tile_size = 24;
+
+        // changes the size of the image
+        let resize =
+            Pipeline::new(&gpu_context, &[Stage::Resize(20, 20)], Edge::Skip, format).unwrap();
+        assert!(submit_tiled(&gpu_context, &resize, &img, format, tile_size).is_err());
+        // larger than any texture, whether the size is given or follows from
+        // the aspect ratio
+        let max_size = gpu_context.device.limits().max_texture_dimension_2d;
+        let too_large = Stage::Resize(max_size + 1, 20);
+        assert!(Pipeline::new(&gpu_context, &[too_large], Edge::Skip, format).is_err());
+        let size = Size {
+            width: None,
+            height: Some(max_size),
+        };
+        let tall = Resize::new(&gpu_context, size, ResizeFilter::Box, Edge::Skip, format);
+        let wide = image::DynamicImage::new_rgba8(40, 20);
+        assert!(submit_tiled(&gpu_context, &tall.unwrap(), &wide, format, 40).is_err());
+        // the overlap leaves no room in the tiles
+        let blur = BoxBlur::new(&gpu_context, 12, Edge::Skip, Algorithm::default(), format);
+        assert!(submit_tiled(&gpu_context, &blur.unwrap(), &img, format, tile_size).is_err());
+        // doesn't opt in to tiling
+        struct Whole;
+        impl Filter for Whole {
+            fn encode(
+                &self,
+                _gpu_context: &GpuContext,
+                _encoder: &mut wgpu::CommandEncoder,
+                _input: &GpuImage,
+                _output: &GpuImage,
+            ) {
+            }
+        }
+        assert!(submit_tiled(&gpu_context, &Whole, &img, format, tile_size).is_err());
+        // both are fine as long as the image fits
+        assert!(submit_tiled(&gpu_context, &resize, &img, format, 40).is_ok());
+    }
+}
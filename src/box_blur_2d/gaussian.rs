@@ -0,0 +1,241 @@
+use wgpu::util::DeviceExt;
+
+use super::{
+    edge::Edge,
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// Normalized 1D weights of a gaussian with standard deviation `sigma`, cut
+/// off at 3 sigma. `weights[r + i]` is the weight of the sample `i` pixels
+/// from the center.
+pub fn weights(sigma: f32) -> Vec<f32> {
+    if sigma <= 0.0 {
+        return vec![1.0];
+    }
+    let radius = (3.0 * sigma).ceil() as i32;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma as f64 * sigma as f64)).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|w| (w / sum) as f32).collect()
+}
+
+/// A separable gaussian blur compiled for one output format.
+pub struct GaussianBlur {
+    radius: u32,
+    edge: Edge,
+    weights_buffer: wgpu::Buffer,
+    passes: Vec<Pass>,
+}
+
+impl GaussianBlur {
+    const WEIGHTS_BINDING: u32 = 5;
+
+    pub fn new(gpu_context: &GpuContext, sigma: f32, edge: Edge, format: PixelFormat) -> Self {
+        let device = &gpu_context.device;
+        let weights = weights(sigma);
+        let weights_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("gaussian weights buffer"),
+            usage: wgpu::BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&weights),
+        });
+
+        let shader_module = device.create_shader_module(shader!("gaussian_blur.wgsl", format));
+        let weights_entry = [storage_buffer_entry(Self::WEIGHTS_BINDING)];
+        let workgroups = |w: u32, h: u32| (w.div_ceil(8), h.div_ceil(8));
+        let passes = vec![
+            Pass::new(
+                gpu_context,
+                &shader_module,
+                "horizontal",
+                1,
+                PixelFormat::Rgba32f,
+                &weights_entry,
+                workgroups,
+            ),
+            Pass::new(
+                gpu_context,
+                &shader_module,
+                "vertical",
+                3,
+                format,
+                &weights_entry,
+                workgroups,
+            ),
+        ];
+        Self {
+            radius: (weights.len() / 2) as u32,
+            edge,
+            weights_buffer,
+            passes,
+        }
+    }
+}
+
+impl Filter for GaussianBlur {
+    fn tiling(&self) -> Option<Tiling> {
+        Some(Tiling::new(self.radius, self.edge))
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [
+            input.width(),
+            input.height(),
+            self.radius,
+            self.edge.to_shader(),
+        ];
+        encode_passes(
+            gpu_context,
+            encoder,
+            &self.passes,
+            bytemuck::bytes_of(&shape),
+            &[wgpu::BindGroupEntry {
+                binding: Self::WEIGHTS_BINDING,
+                resource: self.weights_buffer.as_entire_binding(),
+            }],
+            input,
+            output,
+        );
+    }
+}
+
+/// Gaussian blur `img` on the GPU, processing it in `format`.
+#[cfg(test)]
+pub fn gaussian_blur(
+    img: &image::DynamicImage,
+    sigma: f32,
+    edge: Edge,
+    format: PixelFormat,
+    gpu_context: &GpuContext,
+) -> anyhow::Result<image::DynamicImage> {
+    let gaussian_blur = GaussianBlur::new(gpu_context, sigma, edge, format);
+    super::filter::apply(gpu_context, &gaussian_blur, img, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::{gaussian_blur, weights};
+    use crate::{
+        box_blur_2d::{edge::Edge, random_image},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    /// Straightforward CPU version of the two passes, rows and then columns
+    /// with each pass renormalized on its own for `Edge::Skip`.
+    fn gaussian_reference(img: &image::RgbaImage, sigma: f32, edge: Edge) -> image::RgbaImage {
+        let weights = weights(sigma);
+        let r = (weights.len() / 2) as i64;
+        let (w, h) = (img.width() as i64, img.height() as i64);
+        let pass = |src: &[[f64; 4]], horizontal: bool| -> Vec<[f64; 4]> {
+            let mut out = vec![[0.0; 4]; src.len()];
+            for y in 0..h {
+                for x in 0..w {
+                    let mut accum = [0.0; 4];
+                    let mut weight_sum = 0.0;
+                    for i in -r..=r {
+                        let sample = if horizontal {
+                            edge.sample_index(x + i, w).map(|sx| y * w + sx)
+                        } else {
+                            edge.sample_index(y + i, h).map(|sy| sy * w + x)
+                        };
+                        if let Some(s) = sample {
+                            let weight = weights[(i + r) as usize] as f64;
+                            for c in 0..4 {
+                                accum[c] += weight * src[s as usize][c];
+                            }
+                            weight_sum += weight;
+                        }
+                    }
+                    if edge == Edge::Skip {
+                        accum = accum.map(|a| a / weight_sum);
+                    }
+                    out[(y * w + x) as usize] = accum;
+                }
+            }
+            out
+        };
+        let pixels: Vec<_> = img.pixels().map(|p| p.0.map(|c| c as f64)).collect();
+        let out = pass(&pass(&pixels, true), false);
+        image::RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+            image::Rgba(out[(y as i64 * w + x as i64) as usize].map(|c| c.round() as u8))
+        })
+    }
+
+    #[test]
+    fn test_weights() {
+        assert_eq!(weights(0.0), [1.0]);
+        let w = weights(1.5);
+        // cut off at 3 sigma
+        assert_eq!(w.len(), 2 * 5 + 1);
+        assert!((w.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(w.iter().eq(w.iter().rev()));
+        assert!(w.windows(2).take(5).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_sigma_zero_is_identity() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let lenna = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap();
+        for sigma in [0.0, 0.01, 0.1] {
+            for edge in [Edge::Skip, Edge::Clamp] {
+                let out =
+                    gaussian_blur(&lenna, sigma, edge, PixelFormat::Rgba8, &gpu_context).unwrap();
+                assert!(
+                    out.to_rgba8() == lenna.to_rgba8(),
+                    "sigma {sigma} {edge:?} changed the image"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let images = [
+            random_image(1, 1, 3),
+            random_image(6, 1, 4),
+            random_image(1, 6, 5),
+            random_image(13, 9, 6),
+        ];
+        for img in &images {
+            let dynamic = image::DynamicImage::from(img.clone());
+            // radius 3 and 8, the second reaching past the whole image
+            for sigma in [0.8, 2.5] {
+                for &edge in Edge::value_variants() {
+                    let expected = gaussian_reference(img, sigma, edge);
+                    let out =
+                        gaussian_blur(&dynamic, sigma, edge, PixelFormat::Rgba8, &gpu_context)
+                            .unwrap()
+                            .into_rgba8();
+                    for (a, b) in out.pixels().zip(expected.pixels()) {
+                        assert!(
+                            a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 1),
+                            "{}x{} sigma {sigma} {edge:?}: {a:?} != {b:?}",
+                            img.width(),
+                            img.height()
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
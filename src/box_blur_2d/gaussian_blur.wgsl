@@ -0,0 +1,60 @@
+// Separable gaussian blur. The normalized 1D weights are computed on the
+// CPU, `weights[r + i]` is the weight of the sample `i` pixels away.
+// Like the separable box blur the rows go through an rgba32float texture.
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var rows_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var rows_in: texture_2d<f32>;
+@group(0) @binding(4) var img_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(5) var<storage, read> weights: array<f32>;
+
+// the weights sum to 1, except with EDGE_SKIP where only the samples inside
+// the image count and the sum is scaled back up
+fn renormalize(accum: vec4f, weight_sum: f32) -> vec4f {
+    if (shape.edge == EDGE_SKIP) {
+        return accum / weight_sum;
+    }
+    return accum;
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn horizontal(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let r = i32(shape.r);
+    var accum = vec4f(0.0);
+    var weight_sum = 0.0;
+    for (var i = -r; i <= r; i++) {
+        let sx = sample_index(i32(id.x) + i, i32(shape.w));
+        if (sx >= 0) {
+            let weight = weights[i + r];
+            accum += weight * textureLoad(img_in, vec2i(sx, i32(id.y)), 0);
+            weight_sum += weight;
+        }
+    }
+    textureStore(rows_out, id.xy, renormalize(accum, weight_sum));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn vertical(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let r = i32(shape.r);
+    var accum = vec4f(0.0);
+    var weight_sum = 0.0;
+    for (var i = -r; i <= r; i++) {
+        let sy = sample_index(i32(id.y) + i, i32(shape.h));
+        if (sy >= 0) {
+            let weight = weights[i + r];
+            accum += weight * textureLoad(rows_in, vec2i(i32(id.x), sy), 0);
+            weight_sum += weight;
+        }
+    }
+    textureStore(img_out, id.xy, renormalize(accum, weight_sum));
+}
@@ -0,0 +1,58 @@
+use super::{
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// Replaces the color of every pixel with its luma.
+pub struct Grayscale {
+    passes: Vec<Pass>,
+}
+
+impl Grayscale {
+    pub fn new(gpu_context: &GpuContext, format: PixelFormat) -> Self {
+        let shader_module = gpu_context
+            .device
+            .create_shader_module(shader!("grayscale.wgsl", format));
+        let passes = vec![Pass::new(
+            gpu_context,
+            &shader_module,
+            "main",
+            1,
+            format,
+            &[],
+            |w, h| (w.div_ceil(8), h.div_ceil(8)),
+        )];
+        Self { passes }
+    }
+}
+
+impl Filter for Grayscale {
+    /// Every pixel only depends on itself.
+    fn tiling(&self) -> Option<Tiling> {
+        Some(Tiling::default())
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [input.width(), input.height(), 0, 0];
+        encode_passes(
+            gpu_context,
+            encoder,
+            &self.passes,
+            bytemuck::bytes_of(&shape),
+            &[],
+            input,
+            output,
+        );
+    }
+}
@@ -0,0 +1,17 @@
+// Replaces the color with its Rec. 709 luma, alpha is kept. `shape.r` and
+// `shape.edge` are unused.
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let color = textureLoad(img_in, vec2i(id.xy), 0);
+    let luma = dot(color.rgb, vec3f(0.2126, 0.7152, 0.0722));
+    textureStore(img_out, id.xy, vec4f(vec3f(luma), color.a));
+}
@@ -0,0 +1,330 @@
+use std::fmt::Write;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+    offscreen,
+};
+
+/// Bins per channel, has to match histogram.wgsl
+pub const BINS: usize = 256;
+/// Red, green, blue and luma, has to match histogram.wgsl
+pub const CHANNELS: usize = 4;
+pub const CHANNEL_NAMES: [&str; CHANNELS] = ["red", "green", "blue", "luma"];
+
+/// Histogram compute pipelines, reusable for any number of images.
+pub struct Histogram {
+    count: wgpu::ComputePipeline,
+    scan: wgpu::ComputePipeline,
+}
+
+/// Per tile histograms recorded by [`Histogram::encode`], laid out as
+/// `[tile][channel][bin]`.
+pub struct HistogramBuffers {
+    /// The grid of tiles the image was split into, see [`Histogram::encode`]
+    pub tiles: (u32, u32),
+    pub tile_size: (u32, u32),
+    pub counts: wgpu::Buffer,
+    /// Cumulative counts
+    pub cdf: wgpu::Buffer,
+    /// Value every bin is mapped to by histogram equalization, from 0 to 1
+    pub equalized: wgpu::Buffer,
+}
+
+impl Histogram {
+    pub fn new(gpu_context: &GpuContext) -> Self {
+        let device = &gpu_context.device;
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("histogram.wgsl"));
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = wgpu::BufferBindingType::Storage { read_only: false };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("histogram bind group layout"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                // only ever read with textureLoad, so rgba32float works
+                // without the float32-filterable feature
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                buffer_entry(2, storage),
+                buffer_entry(3, storage),
+                buffer_entry(4, storage),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("histogram pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        Self {
+            count: pipeline("count"),
+            scan: pipeline("scan"),
+        }
+    }
+
+    /// Record counting the pixels of `img` in a grid of `tiles` and
+    /// computing the cumulative counts of every tile. The grid has fewer
+    /// tiles where the last ones would be empty, e.g. 8 tiles 2 pixels wide
+    /// cover an image 10 pixels wide with 5. `clip_limit` cuts off the bins
+    /// at that multiple of the average bin before equalizing, 0 keeps them
+    /// as they are.
+    pub fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        img: &GpuImage,
+        tiles: (u32, u32),
+        clip_limit: f32,
+    ) -> HistogramBuffers {
+        let device = &gpu_context.device;
+        let (width, height) = (img.width(), img.height());
+        let tile_size = (width.div_ceil(tiles.0), height.div_ceil(tiles.1));
+        let tiles = (width.div_ceil(tile_size.0), height.div_ceil(tile_size.1));
+        // the `Params` struct in histogram.wgsl
+        let params: [u32; 8] = [
+            width,
+            height,
+            tiles.0,
+            tiles.1,
+            tile_size.0,
+            tile_size.1,
+            clip_limit.to_bits(),
+            0,
+        ];
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("histogram params buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::bytes_of(&params),
+        });
+        let size = (tiles.0 * tiles.1) as u64 * (CHANNELS * BINS * 4) as u64;
+        let buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        };
+        // new buffers start out zeroed
+        let buffers = HistogramBuffers {
+            tiles,
+            tile_size,
+            counts: buffer("histogram counts buffer"),
+            cdf: buffer("histogram cdf buffer"),
+            equalized: buffer("histogram equalized buffer"),
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("histogram bind group"),
+            layout: &self.count.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&img.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffers.counts.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers.cdf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: buffers.equalized.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("histogram pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_bind_group(0, Some(&bind_group), &[]);
+        // one workgroup per 16x16 block of every tile
+        compute_pass.set_pipeline(&self.count);
+        compute_pass.dispatch_workgroups(
+            tiles.0 * tile_size.0.div_ceil(16),
+            tiles.1 * tile_size.1.div_ceil(16),
+            1,
+        );
+        // one workgroup per channel of every tile
+        compute_pass.set_pipeline(&self.scan);
+        compute_pass.dispatch_workgroups(CHANNELS as u32, tiles.0 * tiles.1, 1);
+        drop(compute_pass);
+        buffers
+    }
+}
+
+/// The histograms of a whole image.
+pub struct HistogramData {
+    pub counts: [[u32; BINS]; CHANNELS],
+    pub cdf: [[u32; BINS]; CHANNELS],
+}
+
+impl HistogramData {
+    /// Histograms of `img`, computed on the GPU.
+    pub fn gpu(gpu_context: &GpuContext, img: &image::DynamicImage) -> anyhow::Result<Self> {
+        let gpu_image = GpuImage::upload(gpu_context, img, PixelFormat::for_image(img));
+        let histogram = Histogram::new(gpu_context);
+        let new_encoder = || {
+            gpu_context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("histogram encoder"),
+                })
+        };
+        let mut encoder = new_encoder();
+        let buffers = histogram.encode(gpu_context, &mut encoder, &gpu_image, (1, 1), 0.0);
+        let read = |encoder, buffer| -> anyhow::Result<[[u32; BINS]; CHANNELS]> {
+            let data = offscreen::read_buffer(gpu_context, encoder, buffer)?;
+            Ok(bytemuck::pod_read_unaligned(&data))
+        };
+        Ok(Self {
+            counts: read(encoder, &buffers.counts)?,
+            cdf: read(new_encoder(), &buffers.cdf)?,
+        })
+    }
+
+    /// `{"red": {"counts": [...], "cdf": [...]}, "green": ...}`
+    pub fn to_json(&self) -> String {
+        let list = |values: &[u32]| {
+            let values: Vec<_> = values.iter().map(u32::to_string).collect();
+            values.join(", ")
+        };
+        let mut json = String::from("{\n");
+        for (c, name) in CHANNEL_NAMES.iter().enumerate() {
+            let separator = if c + 1 < CHANNELS { "," } else { "" };
+            writeln!(
+                json,
+                "  \"{name}\": {{\n    \"counts\": [{}],\n    \"cdf\": [{}]\n  }}{separator}",
+                list(&self.counts[c]),
+                list(&self.cdf[c]),
+            )
+            .unwrap();
+        }
+        json.push('}');
+        json
+    }
+
+    /// Bar chart of the red, green and blue counts, added up where they
+    /// overlap, with the luma counts outlined in white and the cumulative
+    /// luma as a yellow line.
+    pub fn chart(&self) -> image::RgbImage {
+        const HEIGHT: u32 = 200;
+        const BAR_WIDTH: u32 = 2;
+        let max = self.counts[..3]
+            .iter()
+            .flatten()
+            .chain(&self.counts[3])
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let total = self.cdf[3][BINS - 1].max(1);
+        // rows from the bottom up to `n` are filled
+        let height = |n: u32, of: u32| (n as u64 * HEIGHT as u64 / of as u64) as u32;
+        let mut chart = image::RgbImage::from_fn(BINS as u32 * BAR_WIDTH, HEIGHT, |x, y| {
+            let bin = (x / BAR_WIDTH) as usize;
+            let row = HEIGHT - 1 - y;
+            let filled = |c: usize| height(self.counts[c][bin], max) > row;
+            image::Rgb([0, 1, 2].map(|c| if filled(c) { 200 } else { 32 }))
+        });
+        for bin in 0..BINS {
+            for (n, of, color) in [
+                (self.counts[3][bin], max, [255, 255, 255]),
+                (self.cdf[3][bin], total, [255, 220, 0]),
+            ] {
+                let row = height(n, of).min(HEIGHT - 1);
+                for dx in 0..BAR_WIDTH {
+                    chart.put_pixel(
+                        bin as u32 * BAR_WIDTH + dx,
+                        HEIGHT - 1 - row,
+                        image::Rgb(color),
+                    );
+                }
+            }
+        }
+        chart
+    }
+}
+
+/// Histograms of `img` counted on the CPU, in the same bins as the shader.
+#[cfg(test)]
+pub fn histogram_reference(img: &image::DynamicImage) -> HistogramData {
+    let bin = |v: f32| (v * BINS as f32).clamp(0.0, (BINS - 1) as f32) as usize;
+    let mut counts = [[0; BINS]; CHANNELS];
+    for p in img.to_rgba32f().pixels() {
+        let luma = p[0] * 0.2126 + p[1] * 0.7152 + p[2] * 0.0722;
+        for (c, v) in [p[0], p[1], p[2], luma].into_iter().enumerate() {
+            counts[c][bin(v)] += 1;
+        }
+    }
+    let cdf = counts.map(|counts| {
+        let mut sum = 0;
+        counts.map(|n| {
+            sum += n;
+            sum
+        })
+    });
+    HistogramData { counts, cdf }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BINS, HistogramData, histogram_reference};
+    use crate::gpu_context::GpuContext;
+
+    #[test]
+    fn test_histogram_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let lenna = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap();
+        let gpu = HistogramData::gpu(&gpu_context, &lenna).unwrap();
+        let cpu = histogram_reference(&lenna);
+
+        // 8 bit channels land in their own bin
+        assert_eq!(gpu.counts[..3], cpu.counts[..3]);
+        assert_eq!(gpu.cdf[..3], cpu.cdf[..3]);
+        // luma can round into the neighboring bin
+        assert_eq!(gpu.cdf[3][BINS - 1], 512 * 512);
+        for bin in 0..BINS {
+            assert!(gpu.cdf[3][bin].abs_diff(cpu.cdf[3][bin]) <= 16, "bin {bin}");
+        }
+    }
+}
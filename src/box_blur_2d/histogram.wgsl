@@ -0,0 +1,138 @@
+// Histograms of the red, green, blue and luma channels of every tile of an
+// image, with 256 bins each, and their cumulative sums. `count` adds up the
+// pixels of a 16x16 block in workgroup memory and then adds those counts to
+// the tile's. `scan` turns the counts of one channel of one tile into the
+// cumulative distribution and the equalized value of every bin. Has to
+// match histogram.rs.
+
+struct Params {
+    w: u32,
+    h: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    tile_w: u32,
+    tile_h: u32,
+    // bins are cut off at this multiple of the average count and the rest is
+    // spread over all bins, 0 to keep them as they are
+    clip_limit: f32,
+}
+
+const BINS: u32 = 256u;
+const CHANNELS: u32 = 4u;
+// counts of one tile
+const TILE_BINS: u32 = BINS * CHANNELS;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+// [tile][channel][bin]
+@group(0) @binding(2) var<storage, read_write> counts: array<atomic<u32>>;
+@group(0) @binding(3) var<storage, read_write> cdf: array<u32>;
+// value every bin is mapped to by histogram equalization, from 0 to 1
+@group(0) @binding(4) var<storage, read_write> equalized: array<f32>;
+
+fn luma(color: vec4f) -> f32 {
+    return dot(color.rgb, vec3f(0.2126, 0.7152, 0.0722));
+}
+
+fn bin(v: f32) -> u32 {
+    return u32(clamp(v * f32(BINS), 0.0, f32(BINS - 1u)));
+}
+
+var<workgroup> block_counts: array<atomic<u32>, TILE_BINS>;
+
+// one workgroup per 16x16 block of a tile, so all its pixels are in the
+// same tile
+@compute
+@workgroup_size(16, 16, 1)
+fn count(
+    @builtin(local_invocation_id) local: vec3u,
+    @builtin(local_invocation_index) index: u32,
+    @builtin(workgroup_id) group: vec3u,
+) {
+    for (var i = index; i < TILE_BINS; i += 256u) {
+        atomicStore(&block_counts[i], 0u);
+    }
+    workgroupBarrier();
+
+    let tile_size = vec2u(params.tile_w, params.tile_h);
+    let blocks = (tile_size + 15u) / 16u;
+    let tile = group.xy / blocks;
+    let in_tile = group.xy % blocks * 16u + local.xy;
+    let p = tile * tile_size + in_tile;
+    if (all(in_tile < tile_size) && p.x < params.w && p.y < params.h) {
+        let color = textureLoad(img_in, vec2i(p), 0);
+        let v = vec4f(color.rgb, luma(color));
+        for (var c = 0u; c < CHANNELS; c++) {
+            atomicAdd(&block_counts[c * BINS + bin(v[c])], 1u);
+        }
+    }
+    workgroupBarrier();
+
+    let offset = (tile.y * params.tiles_x + tile.x) * TILE_BINS;
+    for (var i = index; i < TILE_BINS; i += 256u) {
+        let n = atomicLoad(&block_counts[i]);
+        if (n > 0u) {
+            atomicAdd(&counts[offset + i], n);
+        }
+    }
+}
+
+var<workgroup> partial: array<u32, BINS>;
+var<workgroup> lowest: atomic<u32>;
+
+// sum of `value` over the invocations up to and including `index`, Hillis
+// and Steele style. Has to be called by all BINS invocations.
+fn inclusive_scan(index: u32, value: u32) -> u32 {
+    // others may still be reading the result of the previous scan
+    workgroupBarrier();
+    partial[index] = value;
+    workgroupBarrier();
+    for (var offset = 1u; offset < BINS; offset *= 2u) {
+        var sum = partial[index];
+        if (index >= offset) {
+            sum += partial[index - offset];
+        }
+        workgroupBarrier();
+        partial[index] = sum;
+        workgroupBarrier();
+    }
+    return partial[index];
+}
+
+// one workgroup per channel of a tile, one invocation per bin
+@compute
+@workgroup_size(256, 1, 1)
+fn scan(@builtin(local_invocation_index) index: u32, @builtin(workgroup_id) group: vec3u) {
+    let i = group.y * TILE_BINS + group.x * BINS + index;
+    var n = atomicLoad(&counts[i]);
+    var running = inclusive_scan(index, n);
+    let total = workgroupUniformLoad(&partial[BINS - 1u]);
+
+    if (params.clip_limit > 0.0) {
+        let limit = max(1u, u32(params.clip_limit * f32(total) / f32(BINS)));
+        let clipped = min(n, limit);
+        inclusive_scan(index, n - clipped);
+        let excess = workgroupUniformLoad(&partial[BINS - 1u]);
+        // spread the excess evenly, the first bins get the remainder
+        n = clipped + excess / BINS + select(0u, 1u, index < excess % BINS);
+        running = inclusive_scan(index, n);
+    }
+    cdf[i] = running;
+
+    // the smallest value of the distribution maps to 0
+    if (index == 0u) {
+        atomicStore(&lowest, total);
+    }
+    workgroupBarrier();
+    if (n > 0u) {
+        atomicMin(&lowest, running);
+    }
+    workgroupBarrier();
+    let cdf_min = atomicLoad(&lowest);
+    if (total > cdf_min) {
+        equalized[i] = f32(running - cdf_min) / f32(total - cdf_min);
+    } else {
+        // only one value, keep it
+        equalized[i] = f32(index) / f32(BINS - 1u);
+    }
+}
@@ -0,0 +1,203 @@
+//! Just enough of KTX2 to store the uncompressed mip levels of an RGBA
+//! image, see https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// Identifier, the 9 header fields and the index up to the level index
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// How the channels of a level are stored.
+struct Layout {
+    vk_format: u32,
+    /// Bytes per channel
+    type_size: u32,
+    srgb: bool,
+    float: bool,
+}
+
+impl Layout {
+    fn of(color: image::ColorType) -> anyhow::Result<Self> {
+        // the vkFormat values from vulkan_core.h
+        Ok(match color {
+            image::ColorType::Rgba8 => Self {
+                vk_format: 43, // VK_FORMAT_R8G8B8A8_SRGB
+                type_size: 1,
+                srgb: true,
+                float: false,
+            },
+            // there is no 16 bit sRGB format, the transfer function in the
+            // data format descriptor says the values are sRGB encoded
+            image::ColorType::Rgba16 => Self {
+                vk_format: 91, // VK_FORMAT_R16G16B16A16_UNORM
+                type_size: 2,
+                srgb: true,
+                float: false,
+            },
+            image::ColorType::Rgba32F => Self {
+                vk_format: 109, // VK_FORMAT_R32G32B32A32_SFLOAT
+                type_size: 4,
+                srgb: false,
+                float: true,
+            },
+            color => anyhow::bail!("KTX2 output of {color:?} images isn't supported"),
+        })
+    }
+
+    fn texel_size(&self) -> u32 {
+        4 * self.type_size
+    }
+
+    /// Basic data format descriptor block with one sample per channel,
+    /// preceded by the total size of the descriptor.
+    fn data_format_descriptor(&self) -> Vec<u32> {
+        const MODEL_RGBSDA: u32 = 1;
+        const PRIMARIES_BT709: u32 = 1;
+        const QUALIFIER_LINEAR: u32 = 0x10;
+        const QUALIFIER_SIGNED: u32 = 0x40;
+        const QUALIFIER_FLOAT: u32 = 0x80;
+
+        let transfer = if self.srgb { 2 } else { 1 };
+        let block_size = 24 + 16 * 4;
+        let mut words = vec![
+            4 + block_size,
+            0,
+            2 | (block_size << 16),
+            MODEL_RGBSDA | (PRIMARIES_BT709 << 8) | (transfer << 16),
+            0,
+            self.texel_size(),
+            0,
+        ];
+        let bits = 8 * self.type_size;
+        for (i, channel) in [0, 1, 2, 15].into_iter().enumerate() {
+            let mut channel_type = channel;
+            // alpha isn't sRGB encoded
+            if self.srgb && channel == 15 {
+                channel_type |= QUALIFIER_LINEAR;
+            }
+            if self.float {
+                channel_type |= QUALIFIER_SIGNED | QUALIFIER_FLOAT;
+            }
+            let (lower, upper) = match self.float {
+                true => ((-1.0f32).to_bits(), 1.0f32.to_bits()),
+                false => (0, u32::MAX >> (32 - bits)),
+            };
+            words.extend([
+                (i as u32 * bits) | ((bits - 1) << 16) | (channel_type << 24),
+                0,
+                lower,
+                upper,
+            ]);
+        }
+        words
+    }
+}
+
+/// Encode `levels`, the full size image first and every level half the
+/// size of the one before it, as a KTX2 texture. They all have to be of the
+/// same RGBA type.
+pub fn encode(levels: &[image::DynamicImage]) -> anyhow::Result<Vec<u8>> {
+    let Some(base) = levels.first() else {
+        anyhow::bail!("A texture needs at least one level");
+    };
+    if levels.iter().any(|level| level.color() != base.color()) {
+        anyhow::bail!("All the levels have to be of the same type");
+    }
+    let layout = Layout::of(base.color())?;
+    let dfd = layout.data_format_descriptor();
+
+    let level_index_offset = HEADER_SIZE;
+    let dfd_offset = level_index_offset + LEVEL_INDEX_ENTRY_SIZE * levels.len();
+    let dfd_size = 4 * dfd.len();
+    // levels start at a multiple of the texel size and of 4
+    let alignment = (layout.texel_size() as usize).max(4);
+
+    // the smallest level comes first in the file
+    let mut data = Vec::new();
+    let mut level_ranges = vec![(0, 0); levels.len()];
+    let data_offset = dfd_offset + dfd_size;
+    for (i, level) in levels.iter().enumerate().rev() {
+        while !(data_offset + data.len()).is_multiple_of(alignment) {
+            data.push(0);
+        }
+        level_ranges[i] = (data_offset + data.len(), level.as_bytes().len());
+        data.extend_from_slice(level.as_bytes());
+    }
+
+    let mut out = Vec::with_capacity(data_offset + data.len());
+    out.extend_from_slice(&IDENTIFIER);
+    for field in [
+        layout.vk_format,
+        layout.type_size,
+        base.width(),
+        base.height(),
+        0, // pixelDepth
+        0, // layerCount
+        1, // faceCount
+        levels.len() as u32,
+        0, // supercompressionScheme
+        dfd_offset as u32,
+        dfd_size as u32,
+        0, // kvdByteOffset
+        0, // kvdByteLength
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    // sgdByteOffset and sgdByteLength
+    out.extend_from_slice(&[0; 16]);
+    for (offset, length) in level_ranges {
+        // byteOffset, byteLength and uncompressedByteLength
+        for field in [offset, length, length] {
+            out.extend_from_slice(&(field as u64).to_le_bytes());
+        }
+    }
+    out.extend(dfd.iter().flat_map(|word| word.to_le_bytes()));
+    out.extend_from_slice(&data);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode;
+
+    #[test]
+    fn test_encode() {
+        let levels: Vec<image::DynamicImage> = [(5, 3), (2, 1), (1, 1)]
+            .into_iter()
+            .map(|(w, h)| {
+                image::Rgba32FImage::from_fn(w, h, |x, _| image::Rgba([x as f32; 4])).into()
+            })
+            .collect();
+        let ktx2 = encode(&levels).unwrap();
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(ktx2[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(ktx2[offset..offset + 8].try_into().unwrap());
+
+        assert_eq!(&ktx2[1..4], b"KTX");
+        // vkFormat, typeSize, width, height and levelCount
+        assert_eq!(
+            [u32_at(12), u32_at(16), u32_at(20), u32_at(24), u32_at(40)],
+            [109, 4, 5, 3, 3]
+        );
+        // the data format descriptor follows the level index
+        let (dfd_offset, dfd_size) = (u32_at(48) as usize, u32_at(52) as usize);
+        assert_eq!(dfd_offset, 80 + 3 * 24);
+        assert_eq!(u32_at(dfd_offset) as usize, dfd_size);
+
+        // stored from the smallest to the largest level, which ends the file
+        let mut level_end = ktx2.len();
+        for (i, level) in levels.iter().enumerate() {
+            let entry = 80 + 24 * i;
+            let (offset, length) = (u64_at(entry) as usize, u64_at(entry + 8) as usize);
+            assert_eq!(offset % 16, 0);
+            assert!(offset >= dfd_offset + dfd_size && offset + length <= level_end);
+            assert_eq!(&ktx2[offset..offset + length], level.as_bytes());
+            if i == 0 {
+                assert_eq!(offset + length, ktx2.len());
+            }
+            level_end = offset;
+        }
+    }
+}
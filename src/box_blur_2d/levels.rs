@@ -0,0 +1,272 @@
+use wgpu::util::DeviceExt;
+
+use super::{
+    filter::{Filter, Tiling},
+    histogram::Histogram,
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// Where the black and white points of [`Levels`] come from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LevelsPoints {
+    /// Values from 0 to 1, the same for every channel
+    Manual { black: f32, white: f32 },
+    /// The values with a `clip` fraction of the pixels below and above them,
+    /// from the luma histogram or from every channel's own histogram, which
+    /// also removes color casts
+    Auto { clip: f32, per_channel: bool },
+}
+
+/// Levels compiled for one output format: values at the black point or
+/// below become 0, values at the white point or above 1, and the values in
+/// between are stretched and raised to 1 / `gamma`.
+pub struct Levels {
+    /// Only for auto levels
+    histogram: Option<Histogram>,
+    /// Stands in for the histograms the pass reads with manual levels
+    no_cdf: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    pass: Pass,
+}
+
+impl Levels {
+    const CDF_BINDING: u32 = 3;
+    const PARAMS_BINDING: u32 = 4;
+
+    pub fn new(
+        gpu_context: &GpuContext,
+        points: LevelsPoints,
+        gamma: f32,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        if !(gamma > 0.0 && gamma.is_finite()) {
+            anyhow::bail!("Gamma has to be a finite number > 0, got {gamma}");
+        }
+        let (black, white, clip, per_channel) = match points {
+            LevelsPoints::Manual { black, white } => {
+                if !(0.0 <= black && black < white && white <= 1.0) {
+                    anyhow::bail!(
+                        "The black and white points need 0 <= black < white <= 1, got {black} and {white}"
+                    );
+                }
+                (black, white, 0.0, false)
+            }
+            LevelsPoints::Auto { clip, per_channel } => {
+                if !(0.0..0.5).contains(&clip) {
+                    anyhow::bail!("Auto levels can clip from 0 up to half the pixels, got {clip}");
+                }
+                (0.0, 1.0, clip, per_channel)
+            }
+        };
+        let auto_levels = matches!(points, LevelsPoints::Auto { .. });
+
+        let device = &gpu_context.device;
+        // the `Params` struct in levels.wgsl
+        let params = [
+            black.to_bits(),
+            white.to_bits(),
+            gamma.to_bits(),
+            clip.to_bits(),
+            auto_levels as u32,
+            per_channel as u32,
+        ];
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("levels params buffer"),
+            usage: wgpu::BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&params),
+        });
+        let no_cdf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("levels empty cdf buffer"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let shader_module = device.create_shader_module(shader!("levels.wgsl", format));
+        let pass = Pass::new(
+            gpu_context,
+            &shader_module,
+            "main",
+            1,
+            format,
+            &[
+                storage_buffer_entry(Self::CDF_BINDING),
+                storage_buffer_entry(Self::PARAMS_BINDING),
+            ],
+            |w, h| (w.div_ceil(8), h.div_ceil(8)),
+        );
+        Ok(Self {
+            histogram: auto_levels.then(|| Histogram::new(gpu_context)),
+            no_cdf,
+            params_buffer,
+            pass,
+        })
+    }
+}
+
+impl Filter for Levels {
+    /// Manual levels only look at the pixel itself, auto levels at the
+    /// histogram of the whole image.
+    fn tiling(&self) -> Option<Tiling> {
+        match self.histogram {
+            Some(_) => None,
+            None => Some(Tiling::default()),
+        }
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        let buffers = self
+            .histogram
+            .as_ref()
+            .map(|histogram| histogram.encode(gpu_context, encoder, input, (1, 1), 0.0));
+        let cdf = buffers
+            .as_ref()
+            .map_or(&self.no_cdf, |buffers| &buffers.cdf);
+
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [input.width(), input.height(), 0, 0];
+        encode_passes(
+            gpu_context,
+            encoder,
+            std::slice::from_ref(&self.pass),
+            bytemuck::bytes_of(&shape),
+            &[
+                wgpu::BindGroupEntry {
+                    binding: Self::CDF_BINDING,
+                    resource: cdf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::PARAMS_BINDING,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+            input,
+            output,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Levels, LevelsPoints};
+    use crate::{box_blur_2d::filter, gpu_context::GpuContext, gpu_image::PixelFormat};
+
+    fn levels(
+        gpu_context: &GpuContext,
+        img: &image::RgbaImage,
+        points: LevelsPoints,
+        gamma: f32,
+    ) -> image::RgbaImage {
+        let format = PixelFormat::Rgba8;
+        let levels = Levels::new(gpu_context, points, gamma, format).unwrap();
+        filter::apply(gpu_context, &levels, &img.clone().into(), format)
+            .unwrap()
+            .into_rgba8()
+    }
+
+    #[test]
+    fn test_manual_levels() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = image::RgbaImage::from_fn(256, 1, |x, _| {
+            image::Rgba([x as u8, 255 - x as u8, x as u8 / 2, 100])
+        });
+        let (black, white) = (0.2, 0.6);
+        for gamma in [1.0, 2.2] {
+            let points = LevelsPoints::Manual { black, white };
+            let out = levels(&gpu_context, &img, points, gamma);
+            for (p, q) in img.pixels().zip(out.pixels()) {
+                for c in 0..3 {
+                    let t = ((p[c] as f32 / 255.0 - black) / (white - black)).clamp(0.0, 1.0);
+                    let expected = (t.powf(1.0 / gamma) * 255.0).round() as u8;
+                    assert!(q[c].abs_diff(expected) <= 1, "{p:?} -> {q:?}");
+                }
+                assert_eq!(q[3], 100);
+            }
+        }
+
+        let invalid =
+            |points, gamma| Levels::new(&gpu_context, points, gamma, PixelFormat::Rgba8).is_err();
+        let manual = |black, white| LevelsPoints::Manual { black, white };
+        assert!(invalid(manual(0.6, 0.2), 1.0));
+        assert!(invalid(manual(0.0, 1.5), 1.0));
+        assert!(invalid(manual(0.0, 1.0), 0.0));
+        let auto = |clip| LevelsPoints::Auto {
+            clip,
+            per_channel: false,
+        };
+        assert!(invalid(auto(0.5), 1.0));
+    }
+
+    #[test]
+    fn test_auto_levels_match_percentiles() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // low contrast noise with a few outliers, and a blue cast
+        let img = image::RgbaImage::from_fn(40, 25, |x, y| {
+            let v = 90 + ((x * 7919 + y * 104729) % 61) as u8;
+            match (x, y) {
+                (0, 0) => image::Rgba([0, 0, 0, 255]),
+                (1, 0) => image::Rgba([255, 255, 255, 255]),
+                _ => image::Rgba([v, v, v / 2 + 120, 255]),
+            }
+        });
+        // the clip cuts off the outliers
+        let points = |c: usize, clip: f32| {
+            let mut values: Vec<u8> = img.pixels().map(|p| p[c]).collect();
+            values.sort();
+            let n = values.len() as f32;
+            let black = values[(clip * n) as usize];
+            let white = values[((1.0 - clip) * n).ceil() as usize - 1];
+            LevelsPoints::Manual {
+                black: black as f32 / 255.0,
+                white: white as f32 / 255.0,
+            }
+        };
+        let clip = 0.01;
+        let per_channel = levels(
+            &gpu_context,
+            &img,
+            LevelsPoints::Auto {
+                clip,
+                per_channel: true,
+            },
+            1.0,
+        );
+        let reds = || per_channel.pixels().map(|p| p[0]);
+        assert_eq!((reds().min(), reds().max()), (Some(0), Some(255)));
+        for c in 0..3 {
+            let manual = levels(&gpu_context, &img, points(c, clip), 1.0);
+            assert!(
+                per_channel
+                    .pixels()
+                    .zip(manual.pixels())
+                    .all(|(p, q)| p[c] == q[c]),
+                "channel {c}"
+            );
+        }
+
+        // the same points for every channel keep the blue cast
+        let luma = levels(
+            &gpu_context,
+            &img,
+            LevelsPoints::Auto {
+                clip,
+                per_channel: false,
+            },
+            1.0,
+        );
+        let p = luma.get_pixel(5, 5);
+        assert!(p[2] > p[0], "{p:?}");
+        let p = per_channel.get_pixel(5, 5);
+        assert!(p[2].abs_diff(p[0]) < 60, "{p:?}");
+    }
+}
@@ -0,0 +1,71 @@
+// Maps the values from the black point to the white point onto the whole
+// range, with a gamma curve in between. Auto levels take the points from the
+// cumulative histograms computed by histogram.wgsl, cutting off `clip` of
+// the pixels at each end. `shape.r` and `shape.edge` are unused.
+
+struct Params {
+    black: f32,
+    white: f32,
+    gamma: f32,
+    clip: f32,
+    auto_levels: u32,
+    per_channel: u32,
+}
+
+const BINS: u32 = 256u;
+const LUMA: u32 = 3u;
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+// [channel][bin] of the whole image
+@group(0) @binding(3) var<storage, read> cdf: array<u32>;
+@group(0) @binding(4) var<storage, read> params: Params;
+
+// lowest bin of `channel` with more than `count` pixels at or below it
+fn first_bin_above(channel: u32, count: f32) -> u32 {
+    var low = 0u;
+    var high = BINS - 1u;
+    while (low < high) {
+        let mid = (low + high) / 2u;
+        if (f32(cdf[channel * BINS + mid]) > count) {
+            high = mid;
+        } else {
+            low = mid + 1u;
+        }
+    }
+    return low;
+}
+
+// black and white point of `channel`
+fn points(channel: u32) -> vec2f {
+    if (params.auto_levels == 0u) {
+        return vec2f(params.black, params.white);
+    }
+    let total = f32(cdf[channel * BINS + BINS - 1u]);
+    let black = first_bin_above(channel, params.clip * total);
+    // the first bin with at least 1 - clip of the pixels at or below it
+    let white = first_bin_above(channel, ceil((1.0 - params.clip) * total) - 1.0);
+    if (white <= black) {
+        // only one value, keep it
+        return vec2f(0.0, 1.0);
+    }
+    // an 8 bit value lands in the bin with its number
+    return vec2f(f32(black), f32(white)) / f32(BINS - 1u);
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let color = textureLoad(img_in, vec2i(id.xy), 0);
+    var rgb: vec3f;
+    for (var c = 0u; c < 3u; c++) {
+        let p = points(select(LUMA, c, params.per_channel != 0u));
+        let t = clamp((color[c] - p.x) / (p.y - p.x), 0.0, 1.0);
+        rgb[c] = pow(t, 1.0 / params.gamma);
+    }
+    textureStore(img_out, id.xy, vec4f(rgb, color.a));
+}
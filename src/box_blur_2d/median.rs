@@ -0,0 +1,158 @@
+use super::{
+    edge::Edge,
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// A median filter compiled for one output format. Every channel is the
+/// median of that channel in the (2r+1)^2 neighborhood, so it is always one
+/// of the input values.
+pub struct Median {
+    radius: u32,
+    edge: Edge,
+    pass: Pass,
+}
+
+impl Median {
+    /// Radii up to this sort the whole neighborhood in registers.
+    const SMALL_MAX_RADIUS: u32 = 2;
+    /// Has to match `TILE_SIDE` in median.wgsl
+    const MAX_RADIUS: u32 = 12;
+
+    pub fn new(
+        gpu_context: &GpuContext,
+        radius: u32,
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        if radius > Self::MAX_RADIUS {
+            anyhow::bail!(
+                "The median filter supports radii up to {}, got {radius}",
+                Self::MAX_RADIUS
+            );
+        }
+        let entry = match radius <= Self::SMALL_MAX_RADIUS {
+            true => "small",
+            false => "large",
+        };
+        let shader_module = gpu_context
+            .device
+            .create_shader_module(shader!("median.wgsl", format));
+        let pass = Pass::new(
+            gpu_context,
+            &shader_module,
+            entry,
+            1,
+            format,
+            &[],
+            |w, h| (w.div_ceil(8), h.div_ceil(8)),
+        );
+        Ok(Self { radius, edge, pass })
+    }
+}
+
+impl Filter for Median {
+    fn tiling(&self) -> Option<Tiling> {
+        Some(Tiling::new(self.radius, self.edge))
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        // contents of the `Shape` uniform in common.wgsl
+        let shape = [
+            input.width(),
+            input.height(),
+            self.radius,
+            self.edge.to_shader(),
+        ];
+        encode_passes(
+            gpu_context,
+            encoder,
+            std::slice::from_ref(&self.pass),
+            bytemuck::bytes_of(&shape),
+            &[],
+            input,
+            output,
+        );
+    }
+}
+
+/// Straightforward CPU median filter the shader is tested against. Takes the
+/// lower of the two middle values when `Edge::Skip` leaves an even number
+/// of samples.
+#[cfg(test)]
+pub fn median_reference(img: &image::Rgba32FImage, radius: u32, edge: Edge) -> image::Rgba32FImage {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let r = radius as i64;
+    image::Rgba32FImage::from_fn(img.width(), img.height(), |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let mut samples: [Vec<f32>; 4] = Default::default();
+        for sy in y - r..=y + r {
+            for sx in x - r..=x + r {
+                let p = match (edge.sample_index(sx, w), edge.sample_index(sy, h)) {
+                    (Some(sx), Some(sy)) => img.get_pixel(sx as u32, sy as u32).0,
+                    _ if edge == Edge::Zero => [0.0; 4],
+                    _ => continue,
+                };
+                for c in 0..4 {
+                    samples[c].push(p[c]);
+                }
+            }
+        }
+        image::Rgba(samples.map(|mut s| {
+            s.sort_by(f32::total_cmp);
+            s[(s.len() - 1) / 2]
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::{Median, median_reference};
+    use crate::{
+        box_blur_2d::{edge::Edge, filter},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    #[test]
+    fn test_median_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // noise with negative values and plenty of ties
+        let img = image::Rgba32FImage::from_fn(21, 13, |x, y| {
+            image::Rgba(std::array::from_fn(|c| {
+                ((x * 7919 + y * 104729 + c as u32 * 31) % 23) as f32 / 11.0 - 0.5
+            }))
+        });
+        // both the sorting network and the bitwise search
+        for radius in [1, 2, 3, 5] {
+            for &edge in Edge::value_variants() {
+                let median = Median::new(&gpu_context, radius, edge, PixelFormat::Rgba32f).unwrap();
+                let out = filter::apply(
+                    &gpu_context,
+                    &median,
+                    &img.clone().into(),
+                    PixelFormat::Rgba32f,
+                )
+                .unwrap()
+                .into_rgba32f();
+                assert_eq!(
+                    out,
+                    median_reference(&img, radius, edge),
+                    "radius {radius} {edge:?}"
+                );
+            }
+        }
+    }
+}
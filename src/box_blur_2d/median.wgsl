@@ -0,0 +1,122 @@
+// Median of every channel over the (2r + 1)^2 neighborhood of a pixel. The
+// channels are compared as u32 keys that sort like the floats they are made
+// of, so the median is exactly one of the samples in every format. `small`
+// sorts the neighborhood for radii up to 2, `large` stages the neighborhood
+// of the whole workgroup in workgroup memory and finds the median one bit at
+// a time.
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var img_out: texture_storage_2d<{{format}}, write>;
+
+// sorts after every real sample, used for the samples EDGE_SKIP leaves out
+const EXCLUDED: u32 = 0xffffffffu;
+
+// flip every bit of negative floats and only the sign of the others
+fn to_key(v: vec4f) -> vec4u {
+    let bits = bitcast<vec4u>(v);
+    let negative = (bits >> vec4u(31u)) == vec4u(1u);
+    return bits ^ select(vec4u(0x80000000u), vec4u(0xffffffffu), negative);
+}
+
+fn from_key(key: vec4u) -> vec4f {
+    let negative = (key >> vec4u(31u)) == vec4u(0u);
+    return bitcast<vec4f>(key ^ select(vec4u(0x80000000u), vec4u(0xffffffffu), negative));
+}
+
+fn sample_key(p: vec2i) -> vec4u {
+    let x = sample_index(p.x, i32(shape.w));
+    let y = sample_index(p.y, i32(shape.h));
+    if (x < 0 || y < 0) {
+        if (shape.edge == EDGE_SKIP) {
+            return vec4u(EXCLUDED);
+        }
+        return to_key(vec4f(0.0));
+    }
+    return to_key(textureLoad(img_in, vec2i(x, y), 0));
+}
+
+// position in the sorted samples, starting at 1, of the median of the
+// neighborhood of `p`. The lower one of the two for an even number of them.
+fn median_rank(p: vec2i) -> u32 {
+    let r = i32(shape.r);
+    if (shape.edge == EDGE_SKIP) {
+        let w = min(p.x + r, i32(shape.w) - 1) - max(p.x - r, 0) + 1;
+        let h = min(p.y + r, i32(shape.h) - 1) - max(p.y - r, 0) + 1;
+        return u32(w * h + 1) / 2u;
+    }
+    let d = 2 * r + 1;
+    return u32(d * d + 1) / 2u;
+}
+
+const SMALL_MAX_SAMPLES: u32 = 25u;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn small(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let p = vec2i(id.xy);
+    let r = i32(shape.r);
+    var keys: array<vec4u, SMALL_MAX_SAMPLES>;
+    var n = 0u;
+    for (var dy = -r; dy <= r; dy++) {
+        for (var dx = -r; dx <= r; dx++) {
+            keys[n] = sample_key(p + vec2i(dx, dy));
+            n++;
+        }
+    }
+    // odd-even transposition sort, min and max sort every channel at once
+    for (var round = 0u; round < n; round++) {
+        for (var i = round % 2u; i + 1u < n; i += 2u) {
+            let a = keys[i];
+            let b = keys[i + 1u];
+            keys[i] = min(a, b);
+            keys[i + 1u] = max(a, b);
+        }
+    }
+    textureStore(img_out, id.xy, from_key(keys[median_rank(p) - 1u]));
+}
+
+// 8 + 2 * `Median::MAX_RADIUS`
+const TILE_SIDE: u32 = 32u;
+var<workgroup> tile: array<vec4u, TILE_SIDE * TILE_SIDE>;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn large(
+    @builtin(global_invocation_id) id: vec3u,
+    @builtin(local_invocation_id) local: vec3u,
+    @builtin(local_invocation_index) index: u32,
+    @builtin(workgroup_id) group: vec3u,
+) {
+    let r = i32(shape.r);
+    let side = 8 + 2 * r;
+    let origin = vec2i(group.xy * 8u) - vec2i(r);
+    for (var i = i32(index); i < side * side; i += 64) {
+        tile[i] = sample_key(origin + vec2i(i % side, i / side));
+    }
+    workgroupBarrier();
+
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    // the largest key with fewer than `rank` samples below it is the median,
+    // built from the highest bit down
+    let rank = vec4u(median_rank(vec2i(id.xy)));
+    var median = vec4u(0u);
+    for (var bit = 31; bit >= 0; bit--) {
+        let candidate = median | vec4u(1u << u32(bit));
+        var below = vec4u(0u);
+        for (var dy = 0; dy <= 2 * r; dy++) {
+            let row = (i32(local.y) + dy) * side + i32(local.x);
+            for (var dx = 0; dx <= 2 * r; dx++) {
+                below += select(vec4u(0u), vec4u(1u), tile[row + dx] < candidate);
+            }
+        }
+        median = select(median, candidate, below < rank);
+    }
+    textureStore(img_out, id.xy, from_key(median));
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use super::{
+    filter::{self, Filter},
+    ktx2,
+    resize::{ResizeFilter, Size},
+};
+use crate::{gpu_context::GpuContext, gpu_image::PixelFormat};
+
+/// The kernel the mip levels are filtered with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum MipFilter {
+    /// Average of the pixels a texel of the level covers
+    #[default]
+    Box,
+    /// Kaiser windowed sinc, sharper but can ring at hard edges
+    Kaiser,
+}
+
+impl MipFilter {
+    pub fn resize_filter(self) -> ResizeFilter {
+        match self {
+            MipFilter::Box => ResizeFilter::Box,
+            MipFilter::Kaiser => ResizeFilter::Kaiser,
+        }
+    }
+}
+
+/// Sizes of the mip levels of a `width` x `height` image, from the image
+/// itself down to 1x1 or the first `levels` of them.
+pub fn level_sizes(width: u32, height: u32, levels: Option<u32>) -> Vec<(u32, u32)> {
+    let count = 32 - width.max(height).leading_zeros();
+    (0..levels.map_or(count, |levels| levels.clamp(1, count)))
+        .map(|level| ((width >> level).max(1), (height >> level).max(1)))
+        .collect()
+}
+
+/// The mip chain of `img` with the levels in `sizes`, starting with `img`
+/// itself. Every level is filtered from `img` directly with the filter
+/// `make_filter` builds for its size, so the errors of the levels don't add
+/// up, and all of them are on the GPU at the same time.
+pub fn generate(
+    gpu_context: &GpuContext,
+    img: &image::DynamicImage,
+    format: PixelFormat,
+    sizes: &[(u32, u32)],
+    make_filter: impl Fn(Size) -> anyhow::Result<Box<dyn Filter>>,
+) -> anyhow::Result<Vec<image::DynamicImage>> {
+    let pending = sizes[1..]
+        .iter()
+        .map(|&(width, height)| {
+            let filter = make_filter(Size::exact(width, height))?;
+            filter::submit(gpu_context, filter.as_ref(), img, format)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    std::iter::once(Ok(img.clone()))
+        .chain(pending.into_iter().map(|level| level.finish(gpu_context)))
+        .collect()
+}
+
+/// Save the levels to a KTX2 texture if `path` ends in `.ktx2`, otherwise
+/// one image per level: `out.png` is saved as `out-0.png`, `out-1.png` and
+/// so on.
+pub fn save(path: &Path, levels: &[image::DynamicImage]) -> anyhow::Result<()> {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    if extension.is_some_and(|ext| ext.eq_ignore_ascii_case("ktx2")) {
+        return Ok(std::fs::write(path, ktx2::encode(levels)?)?);
+    }
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("");
+    for (i, level) in levels.iter().enumerate() {
+        let name = match extension {
+            Some(ext) => format!("{stem}-{i}.{ext}"),
+            None => format!("{stem}-{i}"),
+        };
+        level.save(path.with_file_name(name))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MipFilter, generate, level_sizes};
+    use crate::{
+        box_blur_2d::{edge::Edge, resize::Resize},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    #[test]
+    fn test_level_sizes() {
+        assert_eq!(level_sizes(9, 5, None), [(9, 5), (4, 2), (2, 1), (1, 1)]);
+        assert_eq!(level_sizes(1, 1, None), [(1, 1)]);
+        assert_eq!(level_sizes(256, 16, Some(2)), [(256, 16), (128, 8)]);
+        assert_eq!(level_sizes(4, 4, Some(0)), [(4, 4)]);
+    }
+
+    #[test]
+    fn test_generate() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = image::DynamicImage::from(image::Rgba32FImage::from_fn(8, 4, |x, y| {
+            image::Rgba([x as f32, y as f32, (x * y) as f32, 1.0])
+        }));
+        let format = PixelFormat::Rgba32f;
+        for mip_filter in [MipFilter::Box, MipFilter::Kaiser] {
+            let levels = generate(
+                &gpu_context,
+                &img,
+                format,
+                &level_sizes(8, 4, None),
+                |size| {
+                    let filter = mip_filter.resize_filter();
+                    Ok(Box::new(Resize::new(
+                        &gpu_context,
+                        size,
+                        filter,
+                        Edge::Clamp,
+                        format,
+                    )?))
+                },
+            )
+            .unwrap();
+            let sizes: Vec<_> = levels.iter().map(|l| (l.width(), l.height())).collect();
+            assert_eq!(sizes, [(8, 4), (4, 2), (2, 1), (1, 1)]);
+
+            let last = levels[3].to_rgba32f();
+            let average = |c: usize| img.to_rgba32f().pixels().map(|p| p[c]).sum::<f32>() / 32.0;
+            if mip_filter == MipFilter::Box {
+                let level_1 = levels[1].to_rgba32f();
+                // the 2x2 block of x = 2..4, y = 2..4
+                assert_eq!(level_1.get_pixel(1, 1).0, [2.5, 2.5, 6.25, 1.0]);
+                for c in 0..4 {
+                    assert!((last.get_pixel(0, 0)[c] - average(c)).abs() < 1e-5);
+                }
+            }
+            // the weights are normalized
+            assert!(
+                (last.get_pixel(0, 0)[3] - 1.0).abs() < 1e-5,
+                "{mip_filter:?}"
+            );
+        }
+    }
+}
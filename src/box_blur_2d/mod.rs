@@ -1,3 +1,42 @@
+mod batch;
+mod bilateral;
+mod blur;
+mod color;
+mod convolve;
 mod demo;
+mod edge;
+mod edges;
+mod equalize;
+mod fft_convolve;
+mod filter;
+mod gaussian;
+mod grayscale;
+mod histogram;
+mod ktx2;
+mod levels;
+mod median;
+mod mipmaps;
+mod pass;
+mod pipeline;
+mod resize;
+mod summed_area;
 
-pub use demo::{demo, Opts};
\ No newline at end of file
+pub use demo::{
+    BilateralOpts, BlurBenchmarkOpts, ConvolveOpts, EdgesOpts, EqualizeOpts, GaussianOpts,
+    HistogramOpts, LevelsOpts, MedianOpts, MipmapsOpts, Opts, PipelineOpts, ResizeOpts,
+    bilateral_demo, blur_benchmark, convolve_demo, demo, edges_demo, equalize_demo,
+    fft_convolve_demo, gaussian_demo, histogram_demo, levels_demo, median_demo, mipmaps_demo,
+    pipeline_demo, resize_demo,
+};
+
+/// Noise to test and benchmark filters with, every channel of every pixel
+/// drawn from a linear congruential generator started at `seed`.
+pub fn random_image(width: u32, height: u32, seed: u32) -> image::RgbaImage {
+    let mut state = seed;
+    image::RgbaImage::from_fn(width, height, |_, _| {
+        image::Rgba([(); 4].map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        }))
+    })
+}
@@ -0,0 +1,179 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// Every shader starts with the shape uniform and the edge handling from
+/// common.wgsl and writes its output in `$format`.
+macro_rules! shader {
+    ($file:literal, $format:expr) => {
+        wgpu::ShaderModuleDescriptor {
+            label: Some($file),
+            source: wgpu::ShaderSource::Wgsl(
+                crate::gpu_image::shader_source(
+                    concat!(include_str!("common.wgsl"), include_str!($file)),
+                    $format,
+                )
+                .into(),
+            ),
+        }
+    };
+}
+pub(super) use shader;
+
+/// One compute pass of a filter. Binding 0 is the uniform buffer, the pass
+/// reads the texture at `input_binding` and writes the storage texture at
+/// the binding after it. Filters can add more bindings after those.
+pub(super) struct Pass {
+    pipeline: wgpu::ComputePipeline,
+    input_binding: u32,
+    /// Workgroups to dispatch for an image of the given width and height
+    workgroups: fn(u32, u32) -> (u32, u32),
+}
+
+impl Pass {
+    pub fn new(
+        gpu_context: &GpuContext,
+        shader_module: &wgpu::ShaderModule,
+        entry_point: &str,
+        input_binding: u32,
+        output_format: PixelFormat,
+        extra_entries: &[wgpu::BindGroupLayoutEntry],
+        workgroups: fn(u32, u32) -> (u32, u32),
+    ) -> Self {
+        let device = &gpu_context.device;
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            // only ever read with textureLoad, so rgba32float works
+            // without the float32-filterable feature
+            wgpu::BindGroupLayoutEntry {
+                binding: input_binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: input_binding + 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: output_format.texture_format(),
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ];
+        entries.extend_from_slice(extra_entries);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("filter bind group layout"),
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("filter pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            module: shader_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Self {
+            pipeline,
+            input_binding,
+            workgroups,
+        }
+    }
+}
+
+/// Layout entry of a read-only storage buffer, e.g. filter weights.
+pub(super) fn storage_buffer_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Record `passes` filtering `input` into `output`. The passes are
+/// dispatched for the size of the output and every pass but the last writes
+/// an unquantized rgba32float image of that size that the next one reads.
+pub(super) fn encode_passes(
+    gpu_context: &GpuContext,
+    encoder: &mut wgpu::CommandEncoder,
+    passes: &[Pass],
+    uniforms: &[u8],
+    extra_entries: &[wgpu::BindGroupEntry],
+    input: &GpuImage,
+    output: &GpuImage,
+) {
+    let device = &gpu_context.device;
+    let (width, height) = (output.width(), output.height());
+    let uniforms_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("uniforms buffer"),
+        usage: wgpu::BufferUsages::UNIFORM,
+        contents: uniforms,
+    });
+    let intermediates: Vec<_> = passes[1..]
+        .iter()
+        .map(|_| GpuImage::new(gpu_context, width, height, PixelFormat::Rgba32f))
+        .collect();
+    let views: Vec<_> = std::iter::once(&input.view)
+        .chain(intermediates.iter().map(|img| &img.view))
+        .chain(std::iter::once(&output.view))
+        .collect();
+
+    for (pass, views) in passes.iter().zip(views.windows(2)) {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: pass.input_binding,
+                resource: wgpu::BindingResource::TextureView(views[0]),
+            },
+            wgpu::BindGroupEntry {
+                binding: pass.input_binding + 1,
+                resource: wgpu::BindingResource::TextureView(views[1]),
+            },
+        ];
+        entries.extend_from_slice(extra_entries);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("img bind group"),
+            layout: &pass.pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("filter pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&pass.pipeline);
+        compute_pass.set_bind_group(0, Some(&bind_group), &[]);
+        let (wg_x, wg_y) = (pass.workgroups)(width, height);
+        compute_pass.dispatch_workgroups(wg_x, wg_y, 1);
+    }
+}
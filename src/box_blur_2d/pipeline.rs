@@ -0,0 +1,342 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use super::{
+    blur::{Algorithm, BoxBlur},
+    convolve::{Convolution, ConvolveParams, Kernel, Preset},
+    edge::Edge,
+    filter::{Filter, Tiling},
+    gaussian::GaussianBlur,
+    grayscale::Grayscale,
+    resize::{Resize, ResizeFilter, Size},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// One step of an image pipeline, parsed from `name` or `name:argument`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stage {
+    /// `blur:RADIUS`, radius 3 if it is left out
+    BoxBlur(u32),
+    /// `gaussian:SIGMA`, sigma 2 if it is left out
+    Gaussian(f32),
+    /// The name of a convolve preset, e.g. `sharpen`
+    Convolve(Preset),
+    /// `grayscale`
+    Grayscale,
+    /// `resize:WIDTHxHEIGHT`, bilinear
+    Resize(u32, u32),
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn parse<T: FromStr>(argument: Option<&str>, default: T) -> Result<T, String> {
+            argument.map_or(Ok(default), |argument| {
+                argument
+                    .parse()
+                    .map_err(|_| format!("{argument:?} is not a valid argument"))
+            })
+        }
+
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (s, None),
+        };
+        match (name, argument) {
+            ("blur", argument) => Ok(Stage::BoxBlur(parse(argument, 3)?)),
+            ("gaussian", argument) => {
+                let sigma = parse(argument, 2.0)?;
+                if !(sigma >= 0.0 && f32::is_finite(sigma)) {
+                    return Err(format!("sigma has to be a finite number >= 0, got {sigma}"));
+                }
+                Ok(Stage::Gaussian(sigma))
+            }
+            ("grayscale", None) => Ok(Stage::Grayscale),
+            ("resize", Some(size)) => {
+                let (width, height) = size
+                    .split_once('x')
+                    .ok_or_else(|| format!("Expected resize:WIDTHxHEIGHT, got {s:?}"))?;
+                let (width, height) = (parse(Some(width), 0)?, parse(Some(height), 0)?);
+                if width == 0 || height == 0 {
+                    return Err(format!("Can't resize to {width}x{height}"));
+                }
+                Ok(Stage::Resize(width, height))
+            }
+            (name, None) => Preset::from_str(name, false)
+                .map(Stage::Convolve)
+                .map_err(|_| format!("Unknown stage {name:?}")),
+            (name, Some(_)) => Err(format!("The {name} stage takes no argument")),
+        }
+    }
+}
+
+impl Stage {
+    fn filter(
+        &self,
+        gpu_context: &GpuContext,
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Box<dyn Filter>> {
+        Ok(match *self {
+            Stage::BoxBlur(radius) => Box::new(BoxBlur::new(
+                gpu_context,
+                radius,
+                edge,
+                Algorithm::default(),
+                format,
+            )?),
+            Stage::Gaussian(sigma) => Box::new(GaussianBlur::new(gpu_context, sigma, edge, format)),
+            Stage::Convolve(preset) => Box::new(Convolution::new(
+                gpu_context,
+                &Kernel::preset(preset),
+                &ConvolveParams::default(),
+                edge,
+                format,
+            )?),
+            Stage::Grayscale => Box::new(Grayscale::new(gpu_context, format)),
+            Stage::Resize(width, height) => Box::new(Resize::new(
+                gpu_context,
+                Size::exact(width, height),
+                ResizeFilter::Bilinear,
+                edge,
+                format,
+            )?),
+        })
+    }
+}
+
+/// Filters applied one after the other without leaving the GPU. The images
+/// between the stages are rgba32f so nothing is rounded before the end, only
+/// the last stage writes the output format.
+pub struct Pipeline {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl Pipeline {
+    pub fn new(
+        gpu_context: &GpuContext,
+        stages: &[Stage],
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        let filters = stages
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                let format = if i + 1 == stages.len() {
+                    format
+                } else {
+                    PixelFormat::Rgba32f
+                };
+                stage.filter(gpu_context, edge, format)
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { filters })
+    }
+}
+
+impl Filter for Pipeline {
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        self.filters
+            .iter()
+            .fold((width, height), |(w, h), filter| filter.output_size(w, h))
+    }
+
+    /// The overlaps of the stages add up since every stage reads around the
+    /// pixels of the one before.
+    fn tiling(&self) -> Option<Tiling> {
+        self.filters
+            .iter()
+            .try_fold(Tiling::default(), |tiling, filter| {
+                Some(tiling.then(filter.tiling()?))
+            })
+    }
+
+    /// The stages between the first and the last take turns writing to two
+    /// images, which are only reallocated when a stage changes the size.
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        let Some((last, filters)) = self.filters.split_last() else {
+            encoder.copy_texture_to_texture(
+                input.texture.as_image_copy(),
+                output.texture.as_image_copy(),
+                input.texture.size(),
+            );
+            return;
+        };
+
+        let mut images: [Option<GpuImage>; 2] = [None, None];
+        // index into `images` of the last stage's output, None for `input`
+        let mut source = None;
+        let mut size = (input.width(), input.height());
+        for filter in filters {
+            let target = source.map_or(0, |source: usize| 1 - source);
+            size = filter.output_size(size.0, size.1);
+            if images[target]
+                .as_ref()
+                .is_none_or(|img| (img.width(), img.height()) != size)
+            {
+                images[target] = Some(GpuImage::new(
+                    gpu_context,
+                    size.0,
+                    size.1,
+                    PixelFormat::Rgba32f,
+                ));
+            }
+            let input = source.map_or(input, |source| images[source].as_ref().unwrap());
+            filter.encode(
+                gpu_context,
+                encoder,
+                input,
+                images[target].as_ref().unwrap(),
+            );
+            source = Some(target);
+        }
+        let input = source.map_or(input, |source| images[source].as_ref().unwrap());
+        last.encode(gpu_context, encoder, input, output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pipeline, Stage};
+    use crate::{
+        box_blur_2d::{convolve::Preset, edge::Edge, filter},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    #[test]
+    fn test_parse_stages() {
+        let stages: Vec<Stage> = "blur:3,sharpen,grayscale,resize:512x256,gaussian,blur"
+            .split(',')
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                Stage::BoxBlur(3),
+                Stage::Convolve(Preset::Sharpen),
+                Stage::Grayscale,
+                Stage::Resize(512, 256),
+                Stage::Gaussian(2.0),
+                Stage::BoxBlur(3),
+            ]
+        );
+        for invalid in [
+            "blur:x",
+            "resize",
+            "resize:0x5",
+            "grayscale:1",
+            "gaussian:-1",
+            "foo",
+        ] {
+            assert!(invalid.parse::<Stage>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_pipeline_matches_stages() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let lenna = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap();
+        let img = lenna.crop_imm(100, 100, 61, 43);
+        // rgba32f so running the stages one by one doesn't round in between
+        let format = PixelFormat::Rgba32f;
+
+        let stages = [
+            Stage::BoxBlur(2),
+            Stage::Grayscale,
+            Stage::Convolve(Preset::Sharpen),
+        ];
+        let pipeline = Pipeline::new(&gpu_context, &stages, Edge::Clamp, format).unwrap();
+        let piped = filter::apply(&gpu_context, &pipeline, &img, format).unwrap();
+
+        let staged = stages.iter().fold(img, |img, stage| {
+            let filter = stage.filter(&gpu_context, Edge::Clamp, format).unwrap();
+            filter::apply(&gpu_context, filter.as_ref(), &img, format).unwrap()
+        });
+        assert!(piped.into_rgba32f() == staged.into_rgba32f());
+    }
+
+    #[test]
+    fn test_intermediates_are_not_rounded() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let lenna = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap();
+        let img = lenna.crop_imm(100, 100, 61, 43);
+
+        // sharpening amplifies the rounding of an rgba8 image in between
+        let stages = [
+            Stage::BoxBlur(1),
+            Stage::Convolve(Preset::Sharpen),
+            Stage::Convolve(Preset::Sharpen),
+        ];
+        let pipeline =
+            Pipeline::new(&gpu_context, &stages, Edge::Clamp, PixelFormat::Rgba8).unwrap();
+        let piped = filter::apply(&gpu_context, &pipeline, &img, PixelFormat::Rgba8)
+            .unwrap()
+            .into_rgba8();
+
+        let format = PixelFormat::Rgba32f;
+        let staged = stages
+            .iter()
+            .fold(img, |img, stage| {
+                let filter = stage.filter(&gpu_context, Edge::Clamp, format).unwrap();
+                filter::apply(&gpu_context, filter.as_ref(), &img, format).unwrap()
+            })
+            .into_rgba8();
+        for (a, b) in piped.pixels().zip(staged.pixels()) {
+            assert!(
+                a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 1),
+                "{a:?} != {b:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = image::DynamicImage::from(image::RgbaImage::from_fn(9, 5, |x, y| {
+            image::Rgba([x as u8 * 20, y as u8 * 40, 100, 255])
+        }));
+        let format = PixelFormat::Rgba8;
+        let resize = |stages: &[Stage]| {
+            let pipeline = Pipeline::new(&gpu_context, stages, Edge::Skip, format).unwrap();
+            filter::apply(&gpu_context, &pipeline, &img, format)
+                .unwrap()
+                .into_rgba8()
+        };
+
+        assert!(resize(&[Stage::Resize(9, 5)]) == img.to_rgba8());
+
+        // the images in between are reallocated when the size changes
+        let out = resize(&[
+            Stage::Resize(18, 10),
+            Stage::Grayscale,
+            Stage::Resize(5, 3),
+            Stage::Grayscale,
+        ]);
+        assert_eq!(out.dimensions(), (5, 3));
+        // brighter towards the bottom right like the input
+        let luma = |p: &image::Rgba<u8>| p[0];
+        assert!(luma(out.get_pixel(0, 0)) < luma(out.get_pixel(2, 1)));
+        assert!(luma(out.get_pixel(2, 1)) < luma(out.get_pixel(4, 2)));
+    }
+}
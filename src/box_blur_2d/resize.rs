@@ -0,0 +1,380 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use wgpu::util::DeviceExt;
+
+use super::{
+    edge::Edge,
+    filter::{Filter, Tiling},
+    pass::{Pass, encode_passes, shader, storage_buffer_entry},
+};
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// The kernel an image is resampled with. All but nearest are stretched
+/// when shrinking an image, so they average every pixel instead of skipping
+/// some.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ResizeFilter {
+    /// The input pixel each output pixel lands on
+    Nearest,
+    /// Average of the pixels covered by the output pixel
+    Box,
+    /// Linear interpolation between the 2x2 nearest pixels
+    Bilinear,
+    /// Catmull-Rom spline through the 4x4 nearest pixels
+    Bicubic,
+    /// Windowed sinc of the 6x6 nearest pixels, the sharpest
+    #[default]
+    Lanczos,
+    /// Sinc with a Kaiser window reaching 3 pixels, for mipmaps
+    Kaiser,
+}
+
+impl ResizeFilter {
+    /// Value of `params.kernel` in resize.wgsl
+    fn to_shader(self) -> u32 {
+        match self {
+            ResizeFilter::Nearest => 0,
+            ResizeFilter::Box => 1,
+            ResizeFilter::Bilinear => 2,
+            ResizeFilter::Bicubic => 3,
+            ResizeFilter::Lanczos => 4,
+            ResizeFilter::Kaiser => 5,
+        }
+    }
+}
+
+/// Size an image is resized to, parsed from `WIDTHxHEIGHT`. Either side can
+/// be left out, e.g. `512x`, to keep the aspect ratio of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Size {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Size {
+    pub fn exact(width: u32, height: u32) -> Self {
+        Self {
+            width: Some(width),
+            height: Some(height),
+        }
+    }
+
+    /// The size a `width` x `height` image is resized to.
+    pub fn of(self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |len: u32, to: u32, from: u32| {
+            ((len as f64 * to as f64 / from as f64).round() as u32).max(1)
+        };
+        match (self.width, self.height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, scaled(height, w, width)),
+            (None, Some(h)) => (scaled(width, h, height), h),
+            (None, None) => (width, height),
+        }
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("Expected WIDTHxHEIGHT, got {s:?}"))?;
+        let parse = |side: &str| match side {
+            "" => Ok(None),
+            side => match side.parse() {
+                Ok(0) | Err(_) => Err(format!("{side:?} is not a valid size")),
+                Ok(len) => Ok(Some(len)),
+            },
+        };
+        let size = Self {
+            width: parse(width)?,
+            height: parse(height)?,
+        };
+        if size.width.is_none() && size.height.is_none() {
+            return Err(format!("Expected at least one side, got {s:?}"));
+        }
+        Ok(size)
+    }
+}
+
+/// Resize to a fixed size or width or height with any `ResizeFilter`. The
+/// rows are resized first and the columns of the result after.
+pub struct Resize {
+    size: Size,
+    edge: Edge,
+    params_buffer: wgpu::Buffer,
+    horizontal: Pass,
+    vertical: Pass,
+}
+
+impl Resize {
+    const PARAMS_BINDING: u32 = 5;
+
+    pub fn new(
+        gpu_context: &GpuContext,
+        size: Size,
+        filter: ResizeFilter,
+        edge: Edge,
+        format: PixelFormat,
+    ) -> anyhow::Result<Self> {
+        let device = &gpu_context.device;
+        let max_size = device.limits().max_texture_dimension_2d;
+        if let Some(side) = [size.width, size.height]
+            .into_iter()
+            .flatten()
+            .find(|&side| side > max_size)
+        {
+            anyhow::bail!("Can't resize to {side} pixels, the GPU supports up to {max_size}");
+        }
+        // the `Params` struct in resize.wgsl
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("resize params buffer"),
+            usage: wgpu::BufferUsages::STORAGE,
+            contents: bytemuck::bytes_of(&filter.to_shader()),
+        });
+
+        let shader_module = device.create_shader_module(shader!("resize.wgsl", format));
+        let params_entry = [storage_buffer_entry(Self::PARAMS_BINDING)];
+        let workgroups = |w: u32, h: u32| (w.div_ceil(8), h.div_ceil(8));
+        let horizontal = Pass::new(
+            gpu_context,
+            &shader_module,
+            "horizontal",
+            1,
+            PixelFormat::Rgba32f,
+            &params_entry,
+            workgroups,
+        );
+        let vertical = Pass::new(
+            gpu_context,
+            &shader_module,
+            "vertical",
+            3,
+            format,
+            &params_entry,
+            workgroups,
+        );
+        Ok(Self {
+            size,
+            edge,
+            params_buffer,
+            horizontal,
+            vertical,
+        })
+    }
+}
+
+impl Filter for Resize {
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        self.size.of(width, height)
+    }
+
+    fn tiling(&self) -> Option<Tiling> {
+        None
+    }
+
+    fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &GpuImage,
+        output: &GpuImage,
+    ) {
+        let params_entry = [wgpu::BindGroupEntry {
+            binding: Self::PARAMS_BINDING,
+            resource: self.params_buffer.as_entire_binding(),
+        }];
+        // the rows have the new width but still the old height
+        let rows = GpuImage::new(
+            gpu_context,
+            output.width(),
+            input.height(),
+            PixelFormat::Rgba32f,
+        );
+        for (pass, input, output) in [
+            (&self.horizontal, input, &rows),
+            (&self.vertical, &rows, output),
+        ] {
+            // contents of the `Shape` uniform in common.wgsl, the size the
+            // pass writes
+            let shape = [output.width(), output.height(), 0, self.edge.to_shader()];
+            encode_passes(
+                gpu_context,
+                encoder,
+                std::slice::from_ref(pass),
+                bytemuck::bytes_of(&shape),
+                &params_entry,
+                input,
+                output,
+            );
+        }
+    }
+}
+
+/// CPU version of `weight` in resize.wgsl.
+#[cfg(test)]
+fn weight(filter: ResizeFilter, t: f64) -> f64 {
+    let sinc = |x: f64| match x.abs() < 1e-6 {
+        true => 1.0,
+        false => (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x),
+    };
+    let bessel_i0 = |x: f64| {
+        let mut term = 1.0;
+        1.0 + (1..16)
+            .map(|k| {
+                term *= (x / (2.0 * k as f64)).powi(2);
+                term
+            })
+            .sum::<f64>()
+    };
+    let a = t.abs();
+    match filter {
+        ResizeFilter::Nearest => 1.0,
+        ResizeFilter::Box => ((-0.5..0.5).contains(&t)) as u8 as f64,
+        ResizeFilter::Bilinear => (1.0 - a).max(0.0),
+        ResizeFilter::Bicubic if a < 1.0 => (1.5 * a - 2.5) * a * a + 1.0,
+        ResizeFilter::Bicubic if a < 2.0 => ((-0.5 * a + 2.5) * a - 4.0) * a + 2.0,
+        ResizeFilter::Lanczos if a < 3.0 => sinc(t) * sinc(t / 3.0),
+        ResizeFilter::Kaiser if a < 3.0 => {
+            let r = t / 3.0;
+            sinc(t) * bessel_i0(4.0 * (1.0 - r * r).sqrt()) / bessel_i0(4.0)
+        }
+        _ => 0.0,
+    }
+}
+
+/// Straightforward CPU resize the shader is tested against.
+#[cfg(test)]
+pub fn resize_reference(
+    img: &image::Rgba32FImage,
+    width: u32,
+    height: u32,
+    filter: ResizeFilter,
+    edge: Edge,
+) -> image::Rgba32FImage {
+    let support = match filter {
+        ResizeFilter::Nearest | ResizeFilter::Box => 0.5,
+        ResizeFilter::Bilinear => 1.0,
+        ResizeFilter::Bicubic => 2.0,
+        ResizeFilter::Lanczos | ResizeFilter::Kaiser => 3.0,
+    };
+    // the weights of the input pixels making up output pixel `i`, computed
+    // in f32 like the shader so they agree about which pixels are in range
+    let window = |i: u32, in_len: u32, out_len: u32| -> Vec<(i64, f64)> {
+        let scale = in_len as f32 / out_len as f32;
+        if filter == ResizeFilter::Nearest {
+            let nearest = (((i as f32 + 0.5) * scale).floor() as i64).min(in_len as i64 - 1);
+            return vec![(nearest, 1.0)];
+        }
+        let center = (i as f32 + 0.5) * scale - 0.5;
+        let stretch = scale.max(1.0);
+        let reach = support * stretch;
+        let (first, last) = (
+            (center - reach).ceil() as i64,
+            (center + reach).floor() as i64,
+        );
+        (first..=last)
+            .map(|j| (j, weight(filter, ((j as f32 - center) / stretch) as f64)))
+            .collect()
+    };
+    // resize along x if `horizontal`, otherwise along y
+    let resample = |img: &image::Rgba32FImage, len: u32, horizontal: bool| {
+        let (w, h) = img.dimensions();
+        let (out_w, out_h) = if horizontal { (len, h) } else { (w, len) };
+        image::Rgba32FImage::from_fn(out_w, out_h, |x, y| {
+            let (i, in_len, out_len) = if horizontal {
+                (x, w, out_w)
+            } else {
+                (y, h, out_h)
+            };
+            let mut sum = [0.0f64; 4];
+            let mut weight_sum = 0.0;
+            for (j, weight) in window(i, in_len, out_len) {
+                let color = match edge.sample_index(j, in_len as i64) {
+                    Some(j) if horizontal => img.get_pixel(j as u32, y).0,
+                    Some(j) => img.get_pixel(x, j as u32).0,
+                    None if edge == Edge::Zero => [0.0; 4],
+                    None => continue,
+                };
+                for c in 0..4 {
+                    sum[c] += weight * color[c] as f64;
+                }
+                weight_sum += weight;
+            }
+            image::Rgba(sum.map(|s| (s / weight_sum) as f32))
+        })
+    };
+    resample(&resample(img, width, true), height, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::{Resize, ResizeFilter, Size, resize_reference};
+    use crate::{
+        box_blur_2d::{edge::Edge, filter},
+        gpu_context::GpuContext,
+        gpu_image::PixelFormat,
+    };
+
+    #[test]
+    fn test_size() {
+        assert_eq!("20x10".parse(), Ok(Size::exact(20, 10)));
+        let width_only: Size = "50x".parse().unwrap();
+        assert_eq!(width_only.of(200, 101), (50, 25));
+        let height_only: Size = "x3".parse().unwrap();
+        assert_eq!(height_only.of(1000, 2), (1500, 3));
+        for invalid in ["20", "x", "0x10", "ax10", "10x-1"] {
+            assert!(invalid.parse::<Size>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_resize_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let img = image::Rgba32FImage::from_fn(13, 9, |x, y| {
+            image::Rgba(std::array::from_fn(|c| {
+                ((x * 7919 + y * 104729 + c as u32 * 31) % 17) as f32 / 16.0
+            }))
+        });
+        // shrinking, growing and both at once
+        for (width, height) in [(5, 4), (29, 20), (4, 23)] {
+            for &resize_filter in ResizeFilter::value_variants() {
+                for edge in [Edge::Clamp, Edge::Wrap, Edge::Skip, Edge::Zero] {
+                    let resize = Resize::new(
+                        &gpu_context,
+                        Size::exact(width, height),
+                        resize_filter,
+                        edge,
+                        PixelFormat::Rgba32f,
+                    )
+                    .unwrap();
+                    let out = filter::apply(
+                        &gpu_context,
+                        &resize,
+                        &img.clone().into(),
+                        PixelFormat::Rgba32f,
+                    )
+                    .unwrap()
+                    .into_rgba32f();
+                    let expected = resize_reference(&img, width, height, resize_filter, edge);
+                    let max_difference = out
+                        .iter()
+                        .zip(expected.iter())
+                        .map(|(a, b)| (a - b).abs())
+                        .fold(0.0, f32::max);
+                    assert!(
+                        max_difference < 1e-4,
+                        "{width}x{height} {resize_filter:?} {edge:?}: {max_difference}"
+                    );
+                }
+            }
+        }
+    }
+}
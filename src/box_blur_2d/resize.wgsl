@@ -0,0 +1,174 @@
+// Separable resize to `shape.w` x `shape.h`. `horizontal` scales the rows
+// into an rgba32float image that is already `shape.w` wide, `vertical` then
+// scales its columns. The pixel centers of the input and output line up, so
+// output pixel x is centered on input coordinate (x + 0.5) * in_w / out_w -
+// 0.5. When shrinking the kernel is stretched by the scale so every input
+// pixel is part of the result. `shape.edge` decides what the kernel reads
+// past the edge of the image, `shape.r` is unused. Has to match
+// `resize_reference` in resize.rs.
+
+struct Params {
+    // `ResizeFilter::to_shader`
+    kernel: u32,
+}
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var rows_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var rows_in: texture_2d<f32>;
+@group(0) @binding(4) var img_out: texture_storage_2d<{{format}}, write>;
+@group(0) @binding(5) var<storage, read> params: Params;
+
+const NEAREST: u32 = 0u;
+const BOX: u32 = 1u;
+const BILINEAR: u32 = 2u;
+const BICUBIC: u32 = 3u;
+const LANCZOS: u32 = 4u;
+const KAISER: u32 = 5u;
+
+const PI: f32 = 3.14159265358979;
+const KAISER_ALPHA: f32 = 4.0;
+
+// how far from its center the kernel is non-zero
+fn support() -> f32 {
+    switch params.kernel {
+        case BOX: {
+            return 0.5;
+        }
+        case BILINEAR: {
+            return 1.0;
+        }
+        case BICUBIC: {
+            return 2.0;
+        }
+        default: {
+            return 3.0;
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if (abs(x) < 1e-6) {
+        return 1.0;
+    }
+    return sin(PI * x) / (PI * x);
+}
+
+// modified Bessel function of the first kind of order 0
+fn bessel_i0(x: f32) -> f32 {
+    var sum = 1.0;
+    var term = 1.0;
+    for (var k = 1; k < 16; k++) {
+        let half_x = x / (2.0 * f32(k));
+        term *= half_x * half_x;
+        sum += term;
+    }
+    return sum;
+}
+
+// unnormalized weight of a sample `t` pixels from the center
+fn weight(t: f32) -> f32 {
+    let a = abs(t);
+    switch params.kernel {
+        case BOX: {
+            return select(0.0, 1.0, t >= -0.5 && t < 0.5);
+        }
+        case BILINEAR: {
+            return max(0.0, 1.0 - a);
+        }
+        case BICUBIC: {
+            // Catmull-Rom
+            if (a < 1.0) {
+                return (1.5 * a - 2.5) * a * a + 1.0;
+            }
+            if (a < 2.0) {
+                return ((-0.5 * a + 2.5) * a - 4.0) * a + 2.0;
+            }
+            return 0.0;
+        }
+        case LANCZOS: {
+            if (a >= 3.0) {
+                return 0.0;
+            }
+            return sinc(t) * sinc(t / 3.0);
+        }
+        case KAISER: {
+            if (a >= 3.0) {
+                return 0.0;
+            }
+            let r = t / 3.0;
+            return sinc(t) * bessel_i0(KAISER_ALPHA * sqrt(1.0 - r * r)) / bessel_i0(KAISER_ALPHA);
+        }
+        default: {
+            return 1.0;
+        }
+    }
+}
+
+// the input pixels `first` to `last` along one axis make up output pixel
+// `i`, weighted by `weight((i - center) / stretch)`
+struct Window {
+    center: f32,
+    stretch: f32,
+    first: i32,
+    last: i32,
+}
+
+fn window(i: u32, in_len: u32, out_len: u32) -> Window {
+    let scale = f32(in_len) / f32(out_len);
+    if (params.kernel == NEAREST) {
+        let nearest = min(i32(floor((f32(i) + 0.5) * scale)), i32(in_len) - 1);
+        return Window(f32(nearest), 1.0, nearest, nearest);
+    }
+    let center = (f32(i) + 0.5) * scale - 0.5;
+    let stretch = max(scale, 1.0);
+    let reach = support() * stretch;
+    return Window(center, stretch, i32(ceil(center - reach)), i32(floor(center + reach)));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn horizontal(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let in_w = textureDimensions(img_in).x;
+    let win = window(id.x, in_w, shape.w);
+    var accum = vec4f(0.0);
+    var weight_sum = 0.0;
+    for (var i = win.first; i <= win.last; i++) {
+        let w = weight((f32(i) - win.center) / win.stretch);
+        let x = sample_index(i, i32(in_w));
+        if (x >= 0) {
+            accum += w * textureLoad(img_in, vec2i(x, i32(id.y)), 0);
+        } else if (shape.edge == EDGE_SKIP) {
+            continue;
+        }
+        weight_sum += w;
+    }
+    textureStore(rows_out, id.xy, accum / weight_sum);
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn vertical(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let in_h = textureDimensions(rows_in).y;
+    let win = window(id.y, in_h, shape.h);
+    var accum = vec4f(0.0);
+    var weight_sum = 0.0;
+    for (var i = win.first; i <= win.last; i++) {
+        let w = weight((f32(i) - win.center) / win.stretch);
+        let y = sample_index(i, i32(in_h));
+        if (y >= 0) {
+            accum += w * textureLoad(rows_in, vec2i(i32(id.x), y), 0);
+        } else if (shape.edge == EDGE_SKIP) {
+            continue;
+        }
+        weight_sum += w;
+    }
+    textureStore(img_out, id.xy, accum / weight_sum);
+}
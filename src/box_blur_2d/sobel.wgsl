@@ -0,0 +1,55 @@
+// Sobel gradients of the luma. `gradient` writes the x and y gradients and
+// their magnitude to an rgba32float texture, scaled so a step from black to
+// white is 1. `magnitude` turns those into a grayscale image. Pixels past
+// the edge are sampled according to `shape.edge`, with EDGE_SKIP they take
+// the value of the center pixel so they add no gradient.
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var gradients_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var gradients_in: texture_2d<f32>;
+@group(0) @binding(4) var img_out: texture_storage_2d<{{format}}, write>;
+
+fn luma(color: vec4f) -> f32 {
+    return dot(color.rgb, vec3f(0.2126, 0.7152, 0.0722));
+}
+
+fn luma_at(p: vec2i, center: f32) -> f32 {
+    let x = sample_index(p.x, i32(shape.w));
+    let y = sample_index(p.y, i32(shape.h));
+    if (x < 0 || y < 0) {
+        return select(0.0, center, shape.edge == EDGE_SKIP);
+    }
+    return luma(textureLoad(img_in, vec2i(x, y), 0));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn gradient(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let p = vec2i(id.xy);
+    let center = luma_at(p, 0.0);
+    var g = vec2f(0.0);
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let v = luma_at(p + vec2i(dx, dy), center);
+            // the rows of the x kernel are 1 2 1 times -1 0 1
+            g += v * vec2f(f32(dx * (2 - abs(dy))), f32(dy * (2 - abs(dx))));
+        }
+    }
+    g /= 4.0;
+    textureStore(gradients_out, id.xy, vec4f(g, sqrt(g.x * g.x + g.y * g.y), 0.0));
+}
+
+@compute
+@workgroup_size(8, 8, 1)
+fn magnitude(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= shape.w || id.y >= shape.h) {
+        return;
+    }
+
+    let m = min(textureLoad(gradients_in, vec2i(id.xy), 0).b, 1.0);
+    textureStore(img_out, id.xy, vec4f(vec3f(m), 1.0));
+}
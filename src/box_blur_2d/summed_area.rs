@@ -0,0 +1,474 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+};
+
+/// What the sums of a summed-area table are stored in. Either way they are
+/// integers that wrap around, so the sum of a box read from the table is
+/// exact however far it is from the top left corner, as long as the sum of
+/// the box itself fits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SumType {
+    /// Sums of 8 bit values in one u32 word, exact for boxes of up to
+    /// 4000x4000 pixels.
+    U32,
+    /// Sums of values rounded to multiples of 2^-24, in 64 bit two's
+    /// complement fixed point split into two u32 words. Boxes have to sum to
+    /// less than 2^39 in magnitude.
+    Fixed64,
+}
+
+impl SumType {
+    /// The texel format of the tables, every texel holds one word of the
+    /// sums of the four channels.
+    const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Uint;
+
+    /// u32 sums for 8 bit images, fixed point sums for anything else.
+    pub fn for_format(format: PixelFormat) -> Self {
+        match format {
+            PixelFormat::Rgba8 => SumType::U32,
+            PixelFormat::Rgba16f | PixelFormat::Rgba32f => SumType::Fixed64,
+        }
+    }
+
+    /// Texels per sum, next to each other in a row of the table.
+    fn words(self) -> u32 {
+        match self {
+            SumType::U32 => 1,
+            SumType::Fixed64 => 2,
+        }
+    }
+
+    /// Define `Sum`, the arithmetic on it and its conversions from and to
+    /// pixel values and texels in front of `source`.
+    pub fn shader_source(self, source: &str) -> String {
+        let prelude = match self {
+            SumType::U32 => include_str!("summed_area_u32.wgsl"),
+            SumType::Fixed64 => include_str!("summed_area_fixed64.wgsl"),
+        };
+        format!("{prelude}{source}")
+    }
+}
+
+/// Shader of a filter reading a summed-area table: common.wgsl and
+/// summed_area.wgsl followed by `$file`, writing `$format` and reading sums
+/// of `$sum_type`.
+macro_rules! summed_area_shader {
+    ($file:literal, $format:expr, $sum_type:expr) => {
+        wgpu::ShaderModuleDescriptor {
+            label: Some($file),
+            source: wgpu::ShaderSource::Wgsl(
+                $sum_type
+                    .shader_source(&crate::gpu_image::shader_source(
+                        concat!(
+                            include_str!("common.wgsl"),
+                            include_str!("summed_area.wgsl"),
+                            include_str!($file)
+                        ),
+                        $format,
+                    ))
+                    .into(),
+            ),
+        }
+    };
+}
+pub(super) use summed_area_shader;
+
+/// The summed-area table of an image, written by
+/// [`SummedAreaTable::encode`]. Sum (x, y) holds the sum of all pixels
+/// (x', y') with x' <= x and y' <= y, its words are the texels from
+/// (x * words, y) on.
+pub struct SummedArea {
+    pub texture: wgpu::Texture,
+}
+
+/// Builds summed-area tables of any number of images with a parallel prefix
+/// sum over the rows and then the columns.
+pub struct SummedAreaTable {
+    sum_type: SumType,
+    rows: wgpu::ComputePipeline,
+    columns: wgpu::ComputePipeline,
+}
+
+impl SummedAreaTable {
+    pub fn new(gpu_context: &GpuContext, sum_type: SumType) -> Self {
+        let device = &gpu_context.device;
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("summed_area_table.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                sum_type
+                    .shader_source(concat!(
+                        include_str!("common.wgsl"),
+                        include_str!("summed_area_table.wgsl")
+                    ))
+                    .into(),
+            ),
+        });
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let storage_texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: SumType::TEXTURE_FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let pipeline = |entry_point, entries: &[wgpu::BindGroupLayoutEntry]| {
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("summed-area table bind group layout"),
+                    entries,
+                });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("summed-area table pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        Self {
+            sum_type,
+            rows: pipeline(
+                "rows",
+                &[
+                    uniform_entry,
+                    // only ever read with textureLoad, so rgba32float works
+                    // without the float32-filterable feature
+                    texture_entry(1, wgpu::TextureSampleType::Float { filterable: false }),
+                    storage_texture_entry(2),
+                ],
+            ),
+            columns: pipeline(
+                "columns",
+                &[
+                    uniform_entry,
+                    texture_entry(3, wgpu::TextureSampleType::Uint),
+                    storage_texture_entry(4),
+                ],
+            ),
+        }
+    }
+
+    /// Largest width of image whose table fits in a texture.
+    pub fn max_size(&self, gpu_context: &GpuContext) -> u32 {
+        gpu_context.device.limits().max_texture_dimension_2d / self.sum_type.words()
+    }
+
+    /// Record building the summed-area table of `img`.
+    pub fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        img: &GpuImage,
+    ) -> SummedArea {
+        let device = &gpu_context.device;
+        let (width, height) = (img.width(), img.height());
+        // only the size of the `Shape` uniform in common.wgsl is used
+        let shape = [width, height, 0, 0];
+        let uniforms_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("summed-area table uniforms buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::bytes_of(&shape),
+        });
+        let table = |label| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: width * self.sum_type.words(),
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: SumType::TEXTURE_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+            (texture, view)
+        };
+        let (_, row_sums_view) = table("row sums");
+        let (texture, view) = table("summed-area table");
+
+        let bind_group = |pipeline: &wgpu::ComputePipeline, first_binding, views: [_; 2]| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("summed-area table bind group"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniforms_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: first_binding,
+                        resource: wgpu::BindingResource::TextureView(views[0]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: first_binding + 1,
+                        resource: wgpu::BindingResource::TextureView(views[1]),
+                    },
+                ],
+            })
+        };
+        let rows_bind_group = bind_group(&self.rows, 1, [&img.view, &row_sums_view]);
+        let columns_bind_group = bind_group(&self.columns, 3, [&row_sums_view, &view]);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("summed-area table pass"),
+            timestamp_writes: None,
+        });
+        // one workgroup per row, then one per column
+        compute_pass.set_pipeline(&self.rows);
+        compute_pass.set_bind_group(0, Some(&rows_bind_group), &[]);
+        compute_pass.dispatch_workgroups(height, 1, 1);
+        compute_pass.set_pipeline(&self.columns);
+        compute_pass.set_bind_group(0, Some(&columns_bind_group), &[]);
+        compute_pass.dispatch_workgroups(width, 1, 1);
+        drop(compute_pass);
+        SummedArea { texture }
+    }
+}
+
+/// A pass of a filter reading a summed-area table, e.g. a box blur, a
+/// depth of field with a radius per pixel or adaptive thresholding. Binding 0
+/// is the `Shape` uniform of common.wgsl, binding 1 the table and binding 2
+/// the storage texture written, one invocation per pixel in 8x8 workgroups.
+/// Filters can add more bindings after those.
+pub(super) struct SummedAreaPass {
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl SummedAreaPass {
+    pub fn new(
+        gpu_context: &GpuContext,
+        shader_module: &wgpu::ShaderModule,
+        entry_point: &str,
+        output_format: PixelFormat,
+        extra_entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Self {
+        let device = &gpu_context.device;
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Uint,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: output_format.texture_format(),
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ];
+        entries.extend_from_slice(extra_entries);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("summed-area bind group layout"),
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("summed-area pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            module: shader_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Self { pipeline }
+    }
+
+    /// Record the pass reading `table` and writing `output`.
+    pub fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        uniforms: &[u8],
+        extra_entries: &[wgpu::BindGroupEntry],
+        table: &SummedArea,
+        output: &GpuImage,
+    ) {
+        let device = &gpu_context.device;
+        let table_view = table.texture.create_view(&Default::default());
+        let uniforms_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("uniforms buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: uniforms,
+        });
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&table_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&output.view),
+            },
+        ];
+        entries.extend_from_slice(extra_entries);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("summed-area bind group"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("summed-area pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, Some(&bind_group), &[]);
+        compute_pass.dispatch_workgroups(
+            output.width().div_ceil(8),
+            output.height().div_ceil(8),
+            1,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SumType, SummedAreaTable};
+    use crate::{
+        box_blur_2d::random_image,
+        gpu_context::GpuContext,
+        gpu_image::{GpuImage, PixelFormat},
+        offscreen::TextureReadback,
+    };
+
+    #[test]
+    fn test_table_matches_reference() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // taller and wider than one scan of 256 so the carry is needed
+        let (width, height) = (300, 261);
+        let img = random_image(width, height, 7);
+        // uploaded as f32 so the values summed don't depend on how the GPU
+        // converts 8 bit ones
+        let img = image::DynamicImage::ImageRgba32F(image::Rgba32FImage::from_fn(
+            width,
+            height,
+            |x, y| image::Rgba(img.get_pixel(x, y).0.map(|v| v as f32 / 255.0)),
+        ));
+        for sum_type in [SumType::U32, SumType::Fixed64] {
+            // what every value is summed as, `round` in WGSL rounds ties to even
+            let quantize = |v: f32| match sum_type {
+                SumType::U32 => (v * 255.0).round() as u64,
+                SumType::Fixed64 => (v as f64 * 16777216.0).round_ties_even() as u64,
+            };
+            let mut expected = vec![[0u64; 4]; (width * height) as usize];
+            for (x, y, pixel) in img.as_rgba32f().unwrap().enumerate_pixels() {
+                let i = (y * width + x) as usize;
+                for c in 0..4 {
+                    let mut sum = quantize(pixel[c]);
+                    if x > 0 {
+                        sum += expected[i - 1][c];
+                    }
+                    if y > 0 {
+                        sum += expected[i - width as usize][c];
+                    }
+                    if x > 0 && y > 0 {
+                        sum -= expected[i - width as usize - 1][c];
+                    }
+                    expected[i][c] = sum;
+                }
+            }
+
+            let format = PixelFormat::Rgba32f;
+            let gpu_image = GpuImage::upload(&gpu_context, &img, format);
+            let mut encoder =
+                gpu_context
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("summed-area table test encoder"),
+                    });
+            let table = SummedAreaTable::new(&gpu_context, sum_type).encode(
+                &gpu_context,
+                &mut encoder,
+                &gpu_image,
+            );
+            let data = TextureReadback::submit(&gpu_context, encoder, &table.texture)
+                .unwrap()
+                .finish(&gpu_context)
+                .unwrap();
+            let words = sum_type.words() as usize;
+            for (i, (texels, expected)) in data.chunks_exact(16 * words).zip(&expected).enumerate()
+            {
+                for c in 0..4 {
+                    let word = |w: usize| {
+                        let bytes = texels[16 * w + 4 * c..][..4].try_into().unwrap();
+                        u32::from_le_bytes(bytes) as u64
+                    };
+                    let (sum, expected) = match sum_type {
+                        SumType::U32 => (word(0), expected[c] % (1 << 32)),
+                        SumType::Fixed64 => (word(0) | word(1) << 32, expected[c]),
+                    };
+                    assert_eq!(
+                        sum,
+                        expected,
+                        "{sum_type:?} ({}, {}) channel {c}",
+                        i as u32 % width,
+                        i as u32 / width
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// Sums of boxes of pixels read from a summed-area table in constant time,
+// for filters built on `SummedAreaTable`. The shader including this declares
+// the table as `sat: texture_2d<u32>`. Parts of a box outside of the
+// image are counted the way `sample_index` in common.wgsl maps them for
+// `shape.edge`, with EDGE_SKIP and EDGE_ZERO they are left out.
+
+// Sum of the first `i` pixels of an axis extended past its ends, which is
+// negative for i < 0. It is written as `c.x * S(k.x) + c.y * S(k.y)` where
+// S(k) is the sum of the first k pixels of the image, so a box sum is a few
+// lookups in the table whatever the radius.
+struct Prefix {
+    c: vec2i,
+    k: vec2i,
+}
+
+fn prefix(i: i32, len: i32) -> Prefix {
+    switch shape.edge {
+        case EDGE_CLAMP: {
+            if (i <= 0) {
+                // i copies of the first pixel
+                return Prefix(vec2i(i, 0), vec2i(1, 0));
+            }
+            if (i <= len) {
+                return Prefix(vec2i(1, 0), vec2i(i, 0));
+            }
+            // the whole axis and i - len copies of the last pixel
+            return Prefix(vec2i(1 + i - len, len - i), vec2i(len, len - 1));
+        }
+        case EDGE_WRAP: {
+            let m = modulo(i, len);
+            return Prefix(vec2i((i - m) / len, 1), vec2i(len, m));
+        }
+        case EDGE_MIRROR: {
+            // every period of 2 len pixels is the axis forwards and backwards
+            let m = modulo(i, 2 * len);
+            let periods = (i - m) / len;
+            if (m <= len) {
+                return Prefix(vec2i(periods, 1), vec2i(len, m));
+            }
+            return Prefix(vec2i(periods + 2, -1), vec2i(len, 2 * len - m));
+        }
+        default: {
+            return Prefix(vec2i(1, 0), vec2i(clamp(i, 0, len), 0));
+        }
+    }
+}
+
+// sum of the `k.x` x `k.y` pixels in the top left corner of the image
+fn table_at(k: vec2i) -> Sum {
+    if (k.x <= 0 || k.y <= 0) {
+        return ZERO_SUM;
+    }
+    return load_sum(sat, vec2u(k - 1));
+}
+
+// sum over the first `x` columns and `y` rows of the extended image
+fn corner_sum(x: Prefix, y: Prefix) -> Sum {
+    var sum = ZERO_SUM;
+    for (var a = 0; a < 2; a++) {
+        for (var b = 0; b < 2; b++) {
+            let c = x.c[a] * y.c[b];
+            if (c != 0) {
+                // the sums wrap around, differences of them are still right
+                sum = add_sums(sum, scale_sum(table_at(vec2i(x.k[a], y.k[b])), c));
+            }
+        }
+    }
+    return sum;
+}
+
+// sum of the pixels from `lo` to `hi`, both included
+fn box_sum(lo: vec2i, hi: vec2i) -> vec4f {
+    let size = vec2i(i32(shape.w), i32(shape.h));
+    let x0 = prefix(lo.x, size.x);
+    let x1 = prefix(hi.x + 1, size.x);
+    let y0 = prefix(lo.y, size.y);
+    let y1 = prefix(hi.y + 1, size.y);
+    let right = sub_sums(corner_sum(x1, y1), corner_sum(x1, y0));
+    let left = sub_sums(corner_sum(x0, y1), corner_sum(x0, y0));
+    return from_sum(sub_sums(right, left));
+}
@@ -0,0 +1,81 @@
+// `Sum` of `SumType::Fixed64`: values rounded to multiples of 2^-24 and
+// summed in 64 bit two's complement that wraps around, as a low and a high
+// u32 word.
+
+struct Sum {
+    lo: vec4u,
+    hi: vec4u,
+}
+
+const ZERO_SUM = Sum(vec4u(0u), vec4u(0u));
+// texels per sum in a table
+const SUM_WORDS: u32 = 2u;
+// 2^24, the fixed point one
+const SUM_ONE: f32 = 16777216.0;
+// 2^32, the weight of the high word
+const SUM_WORD: f32 = 4294967296.0;
+
+fn add_sums(a: Sum, b: Sum) -> Sum {
+    let lo = a.lo + b.lo;
+    return Sum(lo, a.hi + b.hi + vec4u(lo < a.lo));
+}
+
+fn negate_sum(s: Sum) -> Sum {
+    let lo = ~s.lo + 1u;
+    return Sum(lo, ~s.hi + vec4u(lo == vec4u(0u)));
+}
+
+fn sub_sums(a: Sum, b: Sum) -> Sum {
+    return add_sums(a, negate_sum(b));
+}
+
+// high word of the 64 bit products a * b, from their 16 bit halves
+fn mul_high(a: vec4u, b: u32) -> vec4u {
+    let a0 = a & vec4u(0xffffu);
+    let a1 = a >> vec4u(16u);
+    let b0 = b & 0xffffu;
+    let b1 = b >> 16u;
+    let mid0 = a1 * b0 + ((a0 * b0) >> vec4u(16u));
+    let mid1 = a0 * b1 + (mid0 & vec4u(0xffffu));
+    return a1 * b1 + (mid0 >> vec4u(16u)) + (mid1 >> vec4u(16u));
+}
+
+fn scale_sum(s: Sum, c: i32) -> Sum {
+    let m = u32(abs(c));
+    let product = Sum(s.lo * m, s.hi * m + mul_high(s.lo, m));
+    if (c < 0) {
+        return negate_sum(product);
+    }
+    return product;
+}
+
+fn to_sum(v: vec4f) -> Sum {
+    // the low word is the part of |v| 2^24 below 2^32, which f32 holds
+    // exactly
+    let s = round(abs(v) * SUM_ONE);
+    let hi = floor(s / SUM_WORD);
+    let magnitude = Sum(vec4u(s - hi * SUM_WORD), vec4u(hi));
+    let negated = negate_sum(magnitude);
+    let negative = v < vec4f(0.0);
+    return Sum(select(magnitude.lo, negated.lo, negative), select(magnitude.hi, negated.hi, negative));
+}
+
+fn from_sum(s: Sum) -> vec4f {
+    let negative = s.hi >= vec4u(0x80000000u);
+    let negated = negate_sum(s);
+    let lo = select(s.lo, negated.lo, negative);
+    let hi = select(s.hi, negated.hi, negative);
+    let magnitude = (vec4f(hi) * SUM_WORD + vec4f(lo)) / SUM_ONE;
+    return select(magnitude, -magnitude, negative);
+}
+
+// sum (x, y) of a table, its words are texels (2x, y) and (2x + 1, y)
+fn load_sum(table: texture_2d<u32>, p: vec2u) -> Sum {
+    let x = 2u * p.x;
+    return Sum(textureLoad(table, vec2u(x, p.y), 0), textureLoad(table, vec2u(x + 1u, p.y), 0));
+}
+
+// texel `i` of the SUM_WORDS a sum is stored in
+fn sum_word(s: Sum, i: u32) -> vec4u {
+    return select(s.lo, s.hi, i == 1u);
+}
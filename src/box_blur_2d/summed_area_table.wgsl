@@ -0,0 +1,79 @@
+// Builds the summed-area table of an image: every texel holds the sum of
+// all the pixels above and left of it, itself included. `rows` turns every
+// row into its prefix sums and `columns` does the same to the columns of
+// that. One workgroup scans a whole row or column, 256 pixels at a time,
+// carrying the total of the ones before. `Sum` and the functions on it are
+// defined by `SumType::shader_source`.
+
+@group(0) @binding(1) var img_in: texture_2d<f32>;
+@group(0) @binding(2) var rows_out: texture_storage_2d<rgba32uint, write>;
+@group(0) @binding(3) var rows_in: texture_2d<u32>;
+@group(0) @binding(4) var sat_out: texture_storage_2d<rgba32uint, write>;
+
+const SCAN_SIZE: u32 = 256u;
+
+var<workgroup> partial: array<Sum, SCAN_SIZE>;
+
+// sum of `value` over the invocations up to and including `index`, Hillis
+// and Steele style. Has to be called by all SCAN_SIZE invocations, the
+// total stays in `partial[SCAN_SIZE - 1]` until the next call.
+fn inclusive_scan(index: u32, value: Sum) -> Sum {
+    // others may still be reading the total of the previous scan
+    workgroupBarrier();
+    partial[index] = value;
+    workgroupBarrier();
+    for (var offset = 1u; offset < SCAN_SIZE; offset *= 2u) {
+        var sum = partial[index];
+        if (index >= offset) {
+            sum = add_sums(sum, partial[index - offset]);
+        }
+        workgroupBarrier();
+        partial[index] = sum;
+        workgroupBarrier();
+    }
+    return partial[index];
+}
+
+// one workgroup per row
+@compute
+@workgroup_size(256, 1, 1)
+fn rows(@builtin(local_invocation_index) index: u32, @builtin(workgroup_id) group: vec3u) {
+    let y = group.x;
+    var carry = ZERO_SUM;
+    for (var start = 0u; start < shape.w; start += SCAN_SIZE) {
+        let x = start + index;
+        var value = ZERO_SUM;
+        if (x < shape.w) {
+            value = to_sum(textureLoad(img_in, vec2u(x, y), 0));
+        }
+        let sum = add_sums(carry, inclusive_scan(index, value));
+        if (x < shape.w) {
+            for (var i = 0u; i < SUM_WORDS; i++) {
+                textureStore(rows_out, vec2u(x * SUM_WORDS + i, y), sum_word(sum, i));
+            }
+        }
+        carry = add_sums(carry, partial[SCAN_SIZE - 1u]);
+    }
+}
+
+// one workgroup per column
+@compute
+@workgroup_size(256, 1, 1)
+fn columns(@builtin(local_invocation_index) index: u32, @builtin(workgroup_id) group: vec3u) {
+    let x = group.x;
+    var carry = ZERO_SUM;
+    for (var start = 0u; start < shape.h; start += SCAN_SIZE) {
+        let y = start + index;
+        var value = ZERO_SUM;
+        if (y < shape.h) {
+            value = load_sum(rows_in, vec2u(x, y));
+        }
+        let sum = add_sums(carry, inclusive_scan(index, value));
+        if (y < shape.h) {
+            for (var i = 0u; i < SUM_WORDS; i++) {
+                textureStore(sat_out, vec2u(x * SUM_WORDS + i, y), sum_word(sum, i));
+            }
+        }
+        carry = add_sums(carry, partial[SCAN_SIZE - 1u]);
+    }
+}
@@ -0,0 +1,37 @@
+// `Sum` of `SumType::U32`: 8 bit values summed in a u32 that wraps around.
+
+alias Sum = vec4u;
+
+const ZERO_SUM = Sum(0u);
+// texels per sum in a table
+const SUM_WORDS: u32 = 1u;
+
+fn to_sum(v: vec4f) -> Sum {
+    return Sum(round(saturate(v) * 255.0));
+}
+
+fn from_sum(s: Sum) -> vec4f {
+    return vec4f(s) / 255.0;
+}
+
+fn add_sums(a: Sum, b: Sum) -> Sum {
+    return a + b;
+}
+
+fn sub_sums(a: Sum, b: Sum) -> Sum {
+    return a - b;
+}
+
+fn scale_sum(s: Sum, c: i32) -> Sum {
+    return s * bitcast<u32>(c);
+}
+
+// sum (x, y) of a table
+fn load_sum(table: texture_2d<u32>, p: vec2u) -> Sum {
+    return textureLoad(table, p, 0);
+}
+
+// texel `i` of the SUM_WORDS a sum is stored in
+fn sum_word(s: Sum, i: u32) -> vec4u {
+    return s;
+}
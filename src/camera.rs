@@ -0,0 +1,358 @@
+use clap::Args;
+use nalgebra::{Matrix4, Orthographic3, Perspective3, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::gpu_context::GpuContext;
+
+/// Options of the camera used by the 3D demos.
+#[derive(Args, Clone, Debug, Default)]
+pub(crate) struct CameraOpts {
+    /// Use an orthographic instead of a perspective projection
+    #[arg(long)]
+    orthographic: bool,
+}
+
+// nalgebra builds OpenGL style projections that map depth to -1..1, wgpu
+// expects 0..1
+#[rustfmt::skip]
+const OPENGL_TO_WGPU: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.5,
+    0.0, 0.0, 0.0, 1.0,
+);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Projection {
+    /// `fovy` is the vertical field of view in radians.
+    Perspective { fovy: f32 },
+    /// `height` is the height of the view volume in world units.
+    Orthographic { height: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    /// Width divided by height of the viewport.
+    pub aspect: f32,
+    pub projection: Projection,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Camera {
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(&self.eye, &self.target, &self.up)
+    }
+
+    pub fn projection(&self) -> Matrix4<f32> {
+        let projection = match self.projection {
+            Projection::Perspective { fovy } => {
+                Perspective3::new(self.aspect, fovy, self.znear, self.zfar).to_homogeneous()
+            }
+            Projection::Orthographic { height } => {
+                let (half_width, half_height) = (height * self.aspect / 2.0, height / 2.0);
+                Orthographic3::new(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+                .to_homogeneous()
+            }
+        };
+        OPENGL_TO_WGPU * projection
+    }
+
+    /// Takes world space to wgpu clip space.
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.projection() * self.view()
+    }
+}
+
+/// Layout of the camera uniform in the shaders:
+///
+/// ```wgsl
+/// struct Camera {
+///     view_projection: mat4x4<f32>,
+///     eye: vec4f,
+/// }
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraUniform {
+    view_projection: [f32; 16],
+    eye: [f32; 4],
+}
+
+impl From<&Camera> for CameraUniform {
+    fn from(camera: &Camera) -> Self {
+        let mut view_projection = [0.0; 16];
+        view_projection.copy_from_slice(camera.view_projection().as_slice());
+        Self {
+            view_projection,
+            eye: camera.eye.to_homogeneous().into(),
+        }
+    }
+}
+
+/// Rotates around a target point while the left mouse button is dragged
+/// and moves closer or further away on scroll.
+#[derive(Clone, Debug)]
+pub(crate) struct OrbitController {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    /// Rotation around the y axis in radians, 0 looks down -z.
+    pub yaw: f32,
+    /// Angle above the xz plane in radians.
+    pub pitch: f32,
+    dragging: bool,
+    cursor: Option<winit::dpi::PhysicalPosition<f64>>,
+}
+
+impl OrbitController {
+    /// Radians per pixel of mouse movement.
+    const ROTATE_SPEED: f32 = 0.01;
+    /// Fraction of the distance moved per line scrolled.
+    const ZOOM_SPEED: f32 = 0.1;
+    const MIN_DISTANCE: f32 = 0.1;
+    const MAX_DISTANCE: f32 = 100.0;
+    // looking straight up or down makes the up vector degenerate
+    const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+    pub fn new(target: Point3<f32>, distance: f32) -> Self {
+        Self {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            dragging: false,
+            cursor: None,
+        }
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        let direction = Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        self.target + direction * self.distance
+    }
+
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * Self::ROTATE_SPEED;
+        self.pitch = (self.pitch + dy * Self::ROTATE_SPEED).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
+
+    /// Positive `lines` move towards the target.
+    pub fn zoom(&mut self, lines: f32) {
+        self.distance = (self.distance * (1.0 - Self::ZOOM_SPEED).powf(lines))
+            .clamp(Self::MIN_DISTANCE, Self::MAX_DISTANCE);
+    }
+
+    /// Returns whether the event moved the camera.
+    pub fn handle_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.dragging = *state == ElementState::Pressed;
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let previous = self.cursor.replace(*position);
+                match previous {
+                    Some(previous) if self.dragging => {
+                        self.rotate(
+                            (position.x - previous.x) as f32,
+                            (position.y - previous.y) as f32,
+                        );
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // roughly what a line is on most platforms
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+                self.zoom(lines);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A camera orbiting the origin together with the uniform buffer and bind
+/// group holding it. Demos bind `bind_group` wherever their shaders expect
+/// the camera and forward window events and resizes to it.
+pub(crate) struct OrbitCamera {
+    pub camera: Camera,
+    pub controller: OrbitController,
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl OrbitCamera {
+    const DISTANCE: f32 = 3.0;
+    const FOVY: f32 = std::f32::consts::FRAC_PI_4;
+
+    pub fn new(opts: &CameraOpts, gpu_context: &GpuContext, width: u32, height: u32) -> Self {
+        let controller = OrbitController::new(Point3::origin(), Self::DISTANCE);
+        let projection = if opts.orthographic {
+            Projection::Orthographic {
+                height: Self::ortho_height(Self::DISTANCE),
+            }
+        } else {
+            Projection::Perspective { fovy: Self::FOVY }
+        };
+        let camera = Camera {
+            eye: controller.eye(),
+            target: controller.target,
+            up: Vector3::y(),
+            aspect: width as f32 / height as f32,
+            projection,
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let buffer = gpu_context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("camera"),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                contents: bytemuck::bytes_of(&CameraUniform::from(&camera)),
+            });
+
+        let bind_group_layout =
+            gpu_context
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("camera bind group layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                });
+
+        let bind_group = gpu_context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("camera bind group"),
+                layout: &bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            });
+
+        Self {
+            camera,
+            controller,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Height of the orthographic view volume that shows about as much of the
+    /// target as the perspective projection does from `distance`.
+    fn ortho_height(distance: f32) -> f32 {
+        2.0 * distance * (Self::FOVY / 2.0).tan()
+    }
+
+    pub fn handle_event(&mut self, event: &winit::event::WindowEvent) {
+        self.controller.handle_event(event);
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.camera.aspect = width as f32 / height as f32;
+    }
+
+    /// Apply the controller to the camera and upload it.
+    pub fn update(&mut self, gpu_context: &GpuContext) {
+        self.camera.eye = self.controller.eye();
+        self.camera.target = self.controller.target;
+        if let Projection::Orthographic { height } = &mut self.camera.projection {
+            *height = Self::ortho_height(self.controller.distance);
+        }
+        gpu_context.queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::bytes_of(&CameraUniform::from(&self.camera)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point3, Vector3};
+
+    use super::{Camera, OrbitController, Projection};
+
+    fn camera(projection: Projection) -> Camera {
+        Camera {
+            eye: Point3::new(0.0, 0.0, 3.0),
+            target: Point3::origin(),
+            up: Vector3::y(),
+            aspect: 2.0,
+            projection,
+            znear: 1.0,
+            zfar: 5.0,
+        }
+    }
+
+    #[test]
+    fn test_depth_range() {
+        for projection in [
+            Projection::Perspective { fovy: 1.0 },
+            Projection::Orthographic { height: 2.0 },
+        ] {
+            let view_projection = camera(projection).view_projection();
+            let near = view_projection.transform_point(&Point3::new(0.0, 0.0, 2.0));
+            let far = view_projection.transform_point(&Point3::new(0.0, 0.0, -2.0));
+            assert!(near.z.abs() < 1e-6, "{projection:?} near {near}");
+            assert!((far.z - 1.0).abs() < 1e-6, "{projection:?} far {far}");
+        }
+    }
+
+    #[test]
+    fn test_orthographic_aspect() {
+        let view_projection = camera(Projection::Orthographic { height: 2.0 }).view_projection();
+        // the view is twice as wide as it is high
+        let corner = view_projection.transform_point(&Point3::new(2.0, 1.0, 0.0));
+        assert!((corner.x - 1.0).abs() < 1e-6 && (corner.y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_orbit() {
+        let mut controller = OrbitController::new(Point3::origin(), 2.0);
+        assert!((controller.eye() - Point3::new(0.0, 0.0, 2.0)).norm() < 1e-6);
+        controller.pitch = std::f32::consts::FRAC_PI_2;
+        controller.zoom(-1.0);
+        assert!(controller.distance > 2.0);
+        assert!((controller.eye() - Point3::new(0.0, controller.distance, 0.0)).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::Instant;
+
+use clap::Args;
+
+/// Options controlling how time advances in the demos.
+#[derive(Args)]
+pub(crate) struct ClockOpts {
+    /// Time in seconds of the first frame. Without --fps time stands still
+    /// at this value
+    #[arg(long, global = true, value_name = "SECONDS")]
+    time: Option<f32>,
+    /// Advance time by exactly 1 / FPS every frame instead of following the
+    /// wall clock
+    #[arg(long, global = true)]
+    fps: Option<f32>,
+    /// Exit after rendering this many frames
+    #[arg(long, global = true)]
+    frames: Option<u64>,
+    /// Render exactly these timestamps, one per frame, then exit
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECONDS",
+        value_delimiter = ',',
+        conflicts_with_all = ["time", "fps", "frames"]
+    )]
+    times: Option<Vec<f32>>,
+}
+
+enum Mode {
+    RealTime { start_instant: Instant },
+    FixedStep { start: f32, step: f32 },
+    Scripted { times: Vec<f32> },
+}
+
+/// Source of the timestamp demos are rendered at. Demos read the time from
+/// here instead of `Instant` so a frame can be reproduced exactly.
+pub(crate) struct Clock {
+    mode: Mode,
+    frame: u64,
+    frames: Option<u64>,
+}
+
+impl Clock {
+    /// Follows the wall clock, starting at 0.
+    pub fn real_time() -> Self {
+        Self {
+            mode: Mode::RealTime {
+                start_instant: Instant::now(),
+            },
+            frame: 0,
+            frames: None,
+        }
+    }
+
+    /// Starts at `start` and advances by `1 / fps` every frame.
+    pub fn fixed_step(start: f32, fps: f32) -> Self {
+        Self {
+            mode: Mode::FixedStep {
+                start,
+                step: 1.0 / fps,
+            },
+            frame: 0,
+            frames: None,
+        }
+    }
+
+    /// Always reports `time`.
+    pub fn frozen(time: f32) -> Self {
+        Self {
+            mode: Mode::FixedStep {
+                start: time,
+                step: 0.0,
+            },
+            frame: 0,
+            frames: None,
+        }
+    }
+
+    /// Reports each of `times` for one frame and then finishes.
+    pub fn scripted(times: Vec<f32>) -> Self {
+        let frames = Some(times.len() as u64);
+        Self {
+            mode: Mode::Scripted { times },
+            frame: 0,
+            frames,
+        }
+    }
+
+    /// Build the clock requested on the command line. `default_fps` makes the
+    /// clock fixed-step even when `--fps` isn't given.
+    pub fn from_opts(opts: &ClockOpts, default_fps: Option<f32>) -> anyhow::Result<Self> {
+        if let Some(times) = &opts.times {
+            if times.is_empty() {
+                anyhow::bail!("--times needs at least one timestamp");
+            }
+            return Ok(Self::scripted(times.clone()));
+        }
+
+        let mut clock = match (opts.time, opts.fps.or(default_fps)) {
+            (_, Some(fps)) if fps <= 0.0 => anyhow::bail!("--fps must be positive"),
+            (time, Some(fps)) => Self::fixed_step(time.unwrap_or(0.0), fps),
+            (Some(time), None) => Self::frozen(time),
+            (None, None) => Self::real_time(),
+        };
+        clock.frames = opts.frames;
+        Ok(clock)
+    }
+
+    /// Seconds since the start of the demo for the current frame.
+    pub fn time(&self) -> f32 {
+        match &self.mode {
+            Mode::RealTime { start_instant } => start_instant.elapsed().as_secs_f32(),
+            Mode::FixedStep { start, step } => start + self.frame as f32 * step,
+            Mode::Scripted { times } => times[(self.frame as usize).min(times.len() - 1)],
+        }
+    }
+
+    /// Frames per second of a fixed-step clock.
+    pub fn fps(&self) -> Option<f32> {
+        match &self.mode {
+            Mode::FixedStep { step, .. } if *step > 0.0 => Some(1.0 / step),
+            _ => None,
+        }
+    }
+
+    /// Move on to the next frame.
+    pub fn tick(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Whether every requested frame has been rendered.
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_some_and(|frames| self.frame >= frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Clock;
+
+    #[test]
+    fn test_fixed_step() {
+        let mut clock = Clock::fixed_step(2.5, 4.0);
+        let mut times = vec![];
+        for _ in 0..3 {
+            times.push(clock.time());
+            clock.tick();
+        }
+        assert_eq!(times, vec![2.5, 2.75, 3.0]);
+        assert!(!clock.is_finished());
+    }
+
+    #[test]
+    fn test_scripted() {
+        let mut clock = Clock::scripted(vec![1.0, 0.5]);
+        assert_eq!(clock.time(), 1.0);
+        clock.tick();
+        assert_eq!(clock.time(), 0.5);
+        assert!(!clock.is_finished());
+        clock.tick();
+        assert!(clock.is_finished());
+    }
+}
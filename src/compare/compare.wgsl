@@ -0,0 +1,125 @@
+// Compares two images of the same size. Every invocation computes the
+// metrics of one pixel, then every workgroup adds up those of its 16x16
+// pixels into one element of `partials`. Has to match the CPU version in
+// metrics.rs.
+
+struct Params {
+    w: u32,
+    h: u32,
+    // squared YIQ distance above which a pixel counts as mismatched
+    threshold: f32,
+}
+
+// sums of one workgroup
+struct Partial {
+    squared_error: f32,
+    ssim: f32,
+    mismatched: f32,
+    // largest difference of any channel, the only one that isn't summed
+    max_difference: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var img_a: texture_2d<f32>;
+@group(0) @binding(2) var img_b: texture_2d<f32>;
+@group(0) @binding(3) var<storage, read_write> partials: array<Partial>;
+
+const SSIM_RADIUS: i32 = 5;
+const SSIM_SIGMA: f32 = 1.5;
+const C1: f32 = 0.0001;
+const C2: f32 = 0.0009;
+
+fn luma(color: vec4f) -> f32 {
+    return dot(color.rgb, vec3f(0.2126, 0.7152, 0.0722));
+}
+
+// see `yiq_delta` in metrics.rs
+fn yiq_delta(a: vec4f, b: vec4f) -> f32 {
+    // blend against white so differences in transparent pixels don't count
+    let rgb_a = 255.0 + (a.rgb * 255.0 - 255.0) * a.a;
+    let rgb_b = 255.0 + (b.rgb * 255.0 - 255.0) * b.a;
+    let d = rgb_a - rgb_b;
+    let dy = dot(d, vec3f(0.2988953, 0.5866225, 0.1144822));
+    let di = dot(d, vec3f(0.595978, -0.2741761, -0.3218019));
+    let dq = dot(d, vec3f(0.2114702, -0.5226171, 0.3111469));
+    return (0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / 35215.0;
+}
+
+// structural similarity of the lumas around `p`, weighted by a gaussian
+// that is cut off at the edges of the image
+fn ssim(p: vec2i) -> f32 {
+    var weight_sum = 0.0;
+    var mean_a = 0.0;
+    var mean_b = 0.0;
+    var mean_aa = 0.0;
+    var mean_bb = 0.0;
+    var mean_ab = 0.0;
+    for (var dy = -SSIM_RADIUS; dy <= SSIM_RADIUS; dy++) {
+        let y = p.y + dy;
+        if (y < 0 || y >= i32(params.h)) {
+            continue;
+        }
+        for (var dx = -SSIM_RADIUS; dx <= SSIM_RADIUS; dx++) {
+            let x = p.x + dx;
+            if (x < 0 || x >= i32(params.w)) {
+                continue;
+            }
+            let w = exp(-f32(dx * dx + dy * dy) / (2.0 * SSIM_SIGMA * SSIM_SIGMA));
+            let a = luma(textureLoad(img_a, vec2i(x, y), 0));
+            let b = luma(textureLoad(img_b, vec2i(x, y), 0));
+            weight_sum += w;
+            mean_a += w * a;
+            mean_b += w * b;
+            mean_aa += w * a * a;
+            mean_bb += w * b * b;
+            mean_ab += w * a * b;
+        }
+    }
+    mean_a /= weight_sum;
+    mean_b /= weight_sum;
+    let var_a = mean_aa / weight_sum - mean_a * mean_a;
+    let var_b = mean_bb / weight_sum - mean_b * mean_b;
+    let cov = mean_ab / weight_sum - mean_a * mean_b;
+    return ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+}
+
+var<workgroup> sums: array<vec4f, 256>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+    @builtin(global_invocation_id) id: vec3u,
+    @builtin(local_invocation_index) index: u32,
+    @builtin(workgroup_id) group: vec3u,
+    @builtin(num_workgroups) groups: vec3u,
+) {
+    var metrics = vec4f(0.0);
+    if (id.x < params.w && id.y < params.h) {
+        let p = vec2i(id.xy);
+        let a = textureLoad(img_a, p, 0);
+        let b = textureLoad(img_b, p, 0);
+        let d = a - b;
+        metrics = vec4f(
+            dot(d, d) / 4.0,
+            ssim(p),
+            select(0.0, 1.0, yiq_delta(a, b) > params.threshold),
+            max(max(abs(d.r), abs(d.g)), max(abs(d.b), abs(d.a))),
+        );
+    }
+    sums[index] = metrics;
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride /= 2u) {
+        if (index < stride) {
+            let other = sums[index + stride];
+            sums[index] = vec4f(sums[index].xyz + other.xyz, max(sums[index].w, other.w));
+        }
+        workgroupBarrier();
+    }
+
+    if (index == 0u) {
+        let s = sums[0];
+        partials[group.y * groups.x + group.x] = Partial(s.x, s.y, s.z, s.w);
+    }
+}
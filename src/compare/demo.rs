@@ -0,0 +1,72 @@
+use clap::Args;
+
+use super::metrics::{self, Comparison};
+use crate::gpu_context::{GpuContext, GpuOpts};
+
+#[derive(Args)]
+pub struct Opts {
+    /// First image
+    a: std::path::PathBuf,
+    /// Second image, of the same size
+    b: std::path::PathBuf,
+    /// Write an image of where the two differ, white where they differ most
+    #[arg(long, value_name = "PNG")]
+    heatmap: Option<std::path::PathBuf>,
+    /// YIQ distance (0 is exact, 1 is anything goes) above which a pixel
+    /// counts as mismatched
+    #[arg(long, default_value_t = 0.1)]
+    threshold: f32,
+    /// Fail if the mean squared error is larger than this
+    #[arg(long, value_name = "MSE")]
+    max_mse: Option<f64>,
+    /// Fail if the SSIM is smaller than this
+    #[arg(long, value_name = "SSIM")]
+    min_ssim: Option<f64>,
+    /// Compare on the CPU instead of the GPU
+    #[arg(long)]
+    cpu: bool,
+}
+
+pub fn demo(opts: Opts, gpu_opts: &GpuOpts) -> anyhow::Result<()> {
+    let a = image::ImageReader::open(&opts.a)?.decode()?;
+    let b = image::ImageReader::open(&opts.b)?.decode()?;
+
+    let comparison = if opts.cpu {
+        Comparison::cpu(&a, &b, opts.threshold)?
+    } else {
+        match GpuContext::new(gpu_opts, wgpu::Features::empty()) {
+            Ok(gpu_context) => Comparison::gpu(&gpu_context, &a, &b, opts.threshold)?,
+            Err(err) => {
+                eprintln!("No GPU ({err:#}), comparing on the CPU");
+                Comparison::cpu(&a, &b, opts.threshold)?
+            }
+        }
+    };
+
+    println!("MSE            {:.6}", comparison.mse);
+    println!("PSNR           {:.2} dB", comparison.psnr());
+    println!("SSIM           {:.4}", comparison.ssim);
+    println!(
+        "max difference {:.4} ({} / 255)",
+        comparison.max_difference,
+        (comparison.max_difference * 255.0).round()
+    );
+    println!(
+        "mismatched     {} of {} pixels ({:.3}%)",
+        comparison.mismatched,
+        comparison.pixels,
+        100.0 * comparison.mismatched as f64 / comparison.pixels as f64
+    );
+
+    if let Some(path) = &opts.heatmap {
+        metrics::heatmap(&a, &b).save(path)?;
+    }
+
+    if let Some(max_mse) = opts.max_mse.filter(|&max| comparison.mse > max) {
+        anyhow::bail!("MSE {} is above {max_mse}", comparison.mse);
+    }
+    if let Some(min_ssim) = opts.min_ssim.filter(|&min| comparison.ssim < min) {
+        anyhow::bail!("SSIM {} is below {min_ssim}", comparison.ssim);
+    }
+    Ok(())
+}
@@ -0,0 +1,401 @@
+use image::GenericImageView;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gpu_context::GpuContext,
+    gpu_image::{GpuImage, PixelFormat},
+    offscreen,
+};
+
+/// How two images of the same size differ. Channels are compared as 0..1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Comparison {
+    /// Mean squared difference over all channels of all pixels
+    pub mse: f64,
+    /// Mean structural similarity of the lumas, 1 for identical images
+    pub ssim: f64,
+    /// Largest difference of any channel of any pixel
+    pub max_difference: f32,
+    /// Pixels whose YIQ distance is above the threshold
+    pub mismatched: u64,
+    pub pixels: u64,
+}
+
+impl Comparison {
+    /// Peak signal to noise ratio in dB, infinite for identical images.
+    pub fn psnr(&self) -> f64 {
+        -10.0 * self.mse.log10()
+    }
+
+    /// Compare on the GPU. `threshold` is the YIQ distance (0 is exact, 1 is
+    /// anything goes) above which a pixel counts as mismatched.
+    pub fn gpu(
+        gpu_context: &GpuContext,
+        a: &image::DynamicImage,
+        b: &image::DynamicImage,
+        threshold: f32,
+    ) -> anyhow::Result<Self> {
+        check_sizes(a, b)?;
+        let device = &gpu_context.device;
+        let (width, height) = a.dimensions();
+        let img_a = GpuImage::upload(gpu_context, a, PixelFormat::for_image(a));
+        let img_b = GpuImage::upload(gpu_context, b, PixelFormat::for_image(b));
+
+        // the `Params` struct in compare.wgsl, padded to 16 bytes
+        let params = [width, height, (threshold * threshold).to_bits(), 0];
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("compare params buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::cast_slice(&params),
+        });
+        let groups = (width.div_ceil(16), height.div_ceil(16));
+        let partials_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("compare partials buffer"),
+            size: (groups.0 * groups.1) as u64 * 16,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let pipeline = create_pipeline(gpu_context);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("compare bind group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&img_a.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&img_b.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: partials_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("compare encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("compare pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.set_bind_group(0, Some(&bind_group), &[]);
+            compute_pass.dispatch_workgroups(groups.0, groups.1, 1);
+        }
+        let data = offscreen::read_buffer(gpu_context, encoder, &partials_buffer)?;
+
+        let mut sums = Sums::default();
+        for partial in bytemuck::cast_slice::<u8, [f32; 4]>(&data) {
+            sums.add(partial[0], partial[1], partial[2] as u64, partial[3]);
+        }
+        Ok(sums.finish(width, height))
+    }
+
+    /// Compare on the CPU, giving the same results as [`Comparison::gpu`]
+    /// up to rounding.
+    pub fn cpu(
+        a: &image::DynamicImage,
+        b: &image::DynamicImage,
+        threshold: f32,
+    ) -> anyhow::Result<Self> {
+        check_sizes(a, b)?;
+        let (a, b) = (a.to_rgba32f(), b.to_rgba32f());
+        let ssim = ssim_map(&luma(&a), &luma(&b), a.width(), a.height());
+
+        let mut sums = Sums::default();
+        for ((pa, pb), ssim) in a.pixels().zip(b.pixels()).zip(ssim) {
+            let d = [0, 1, 2, 3].map(|c| pa[c] - pb[c]);
+            let mismatched = yiq_delta(pa, pb) > threshold * threshold;
+            sums.add(
+                d.iter().map(|d| d * d).sum::<f32>() / 4.0,
+                ssim,
+                mismatched as u64,
+                d.iter().fold(0.0, |max, d| d.abs().max(max)),
+            );
+        }
+        Ok(sums.finish(a.width(), a.height()))
+    }
+}
+
+fn check_sizes(a: &image::DynamicImage, b: &image::DynamicImage) -> anyhow::Result<()> {
+    if a.dimensions() != b.dimensions() {
+        anyhow::bail!(
+            "Can't compare a {:?} image with a {:?} one",
+            a.dimensions(),
+            b.dimensions()
+        );
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Sums {
+    squared_error: f64,
+    ssim: f64,
+    mismatched: u64,
+    max_difference: f32,
+}
+
+impl Sums {
+    fn add(&mut self, squared_error: f32, ssim: f32, mismatched: u64, max_difference: f32) {
+        self.squared_error += squared_error as f64;
+        self.ssim += ssim as f64;
+        self.mismatched += mismatched;
+        self.max_difference = self.max_difference.max(max_difference);
+    }
+
+    fn finish(self, width: u32, height: u32) -> Comparison {
+        let pixels = width as u64 * height as u64;
+        Comparison {
+            mse: self.squared_error / pixels as f64,
+            ssim: self.ssim / pixels as f64,
+            max_difference: self.max_difference,
+            mismatched: self.mismatched,
+            pixels,
+        }
+    }
+}
+
+fn create_pipeline(gpu_context: &GpuContext) -> wgpu::ComputePipeline {
+    let device = &gpu_context.device;
+    let shader_module = device.create_shader_module(wgpu::include_wgsl!("compare.wgsl"));
+    // only ever read with textureLoad, so rgba32float works without the
+    // float32-filterable feature
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("compare bind group layout"),
+        entries: &[
+            buffer_entry(0, wgpu::BufferBindingType::Uniform),
+            texture_entry(1),
+            texture_entry(2),
+            buffer_entry(3, wgpu::BufferBindingType::Storage { read_only: false }),
+        ],
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("compare pipeline layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("compare pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
+
+/// Squared distance between two colors in YIQ space, normalized to 0..1.
+/// Weights are from "Measuring perceived color difference using YIQ NTSC
+/// transmission color space in mobile applications" (Kotsarenko, Ramos).
+pub fn yiq_delta(a: &image::Rgba<f32>, b: &image::Rgba<f32>) -> f32 {
+    // blend against white so differences in transparent pixels don't count
+    let rgb = |p: &image::Rgba<f32>| [0, 1, 2].map(|c| 255.0 + (p[c] * 255.0 - 255.0) * p[3]);
+    let [r1, g1, b1] = rgb(a);
+    let [r2, g2, b2] = rgb(b);
+    let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+    let dy = dr * 0.2988953 + dg * 0.5866225 + db * 0.1144822;
+    let di = dr * 0.595978 - dg * 0.2741761 - db * 0.3218019;
+    let dq = dr * 0.2114702 - dg * 0.5226171 + db * 0.3111469;
+    // 35215 is the largest possible delta (black vs white)
+    (0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / 35215.0
+}
+
+// has to match compare.wgsl
+const SSIM_RADIUS: i64 = 5;
+const SSIM_SIGMA: f32 = 1.5;
+const C1: f32 = 0.0001;
+const C2: f32 = 0.0009;
+
+fn luma(img: &image::Rgba32FImage) -> Vec<f32> {
+    img.pixels()
+        .map(|p| p[0] * 0.2126 + p[1] * 0.7152 + p[2] * 0.0722)
+        .collect()
+}
+
+/// Structural similarity (Wang et al. 2004) of the lumas `a` and `b` around
+/// every pixel, weighted by a gaussian that is cut off at the edges of the
+/// image. Cutting off a 2D gaussian at a rectangle keeps it separable, so
+/// the weighted sums are computed along the rows and then the columns.
+fn ssim_map(a: &[f32], b: &[f32], width: u32, height: u32) -> Vec<f32> {
+    let (width, height) = (width as i64, height as i64);
+    let weights: Vec<f32> = (-SSIM_RADIUS..=SSIM_RADIUS)
+        .map(|d| (-((d * d) as f32) / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp())
+        .collect();
+    // weights, a, b, a², b² and ab around every pixel, summed along rows
+    // (`step` 1) or columns (`step` width) of `len` pixels
+    let window_sums = |values: &[[f32; 6]], step: i64, len: i64| {
+        let mut sums = vec![[0.0; 6]; values.len()];
+        for (i, sum) in sums.iter_mut().enumerate() {
+            let at = i as i64 / step % len;
+            let from = (at - SSIM_RADIUS).max(0);
+            let to = (at + SSIM_RADIUS).min(len - 1);
+            for j in from..=to {
+                let w = weights[(j - at + SSIM_RADIUS) as usize];
+                let v = &values[(i as i64 + (j - at) * step) as usize];
+                for k in 0..6 {
+                    sum[k] += w * v[k];
+                }
+            }
+        }
+        sums
+    };
+    let values: Vec<[f32; 6]> = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| [1.0, a, b, a * a, b * b, a * b])
+        .collect();
+    let sums = window_sums(&window_sums(&values, 1, width), width, height);
+
+    sums.iter()
+        .map(|&[weight_sum, a, b, aa, bb, ab]| {
+            let (mean_a, mean_b) = (a / weight_sum, b / weight_sum);
+            let var_a = aa / weight_sum - mean_a * mean_a;
+            let var_b = bb / weight_sum - mean_b * mean_b;
+            let cov = ab / weight_sum - mean_a * mean_b;
+            ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2))
+        })
+        .collect()
+}
+
+/// Largest channel difference of every pixel as a black, red, yellow, white
+/// ramp, scaled so the largest difference in the image is white.
+pub fn heatmap(a: &image::DynamicImage, b: &image::DynamicImage) -> image::RgbImage {
+    let (a, b) = (a.to_rgba32f(), b.to_rgba32f());
+    let differences: Vec<f32> = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(pa, pb)| (0..4).fold(0.0, |max: f32, c| (pa[c] - pb[c]).abs().max(max)))
+        .collect();
+    let max = differences.iter().copied().fold(0.0, f32::max);
+    let ramp =
+        |t: f32| [3.0 * t, 3.0 * t - 1.0, 3.0 * t - 2.0].map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+    image::RgbImage::from_fn(a.width(), a.height(), |x, y| {
+        let difference = differences[(y * a.width() + x) as usize];
+        image::Rgb(ramp(if max > 0.0 { difference / max } else { 0.0 }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Comparison, heatmap, yiq_delta};
+    use crate::gpu_context::GpuContext;
+
+    #[test]
+    fn test_yiq_delta_range() {
+        let black = image::Rgba([0.0, 0.0, 0.0, 1.0]);
+        let white = image::Rgba([1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(yiq_delta(&black, &black), 0.0);
+        let delta = yiq_delta(&black, &white);
+        assert!(delta > 0.9 && delta <= 1.0);
+    }
+
+    #[test]
+    fn test_identical() {
+        let img = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap();
+        let comparison = Comparison::cpu(&img, &img, 0.0).unwrap();
+        assert_eq!(comparison.mse, 0.0);
+        assert_eq!(comparison.psnr(), f64::INFINITY);
+        assert!((comparison.ssim - 1.0).abs() < 1e-6);
+        assert_eq!(comparison.mismatched, 0);
+
+        let other = image::DynamicImage::new_rgba8(3, 3);
+        assert!(Comparison::cpu(&img, &other, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_gpu_matches_cpu() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let lenna = image::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/test_data/lenna.png"
+        ))
+        .unwrap()
+        // odd size so the workgroups don't line up with the image
+        .crop_imm(100, 150, 83, 61);
+        let blurred = lenna.blur(2.0);
+        let mut noisy = lenna.to_rgba8();
+        for (x, y, p) in noisy.enumerate_pixels_mut() {
+            for c in &mut p.0[..3] {
+                *c = c.saturating_add(((x * 7 + y * 13) % 5) as u8);
+            }
+        }
+        let noisy = noisy.into();
+
+        for other in [&lenna, &blurred, &noisy] {
+            let cpu = Comparison::cpu(&lenna, other, 0.05).unwrap();
+            let gpu = Comparison::gpu(&gpu_context, &lenna, other, 0.05).unwrap();
+            assert!(
+                (cpu.mse - gpu.mse).abs() <= 1e-6 * cpu.mse.max(1e-6),
+                "{cpu:?} {gpu:?}"
+            );
+            assert!((cpu.ssim - gpu.ssim).abs() <= 1e-4, "{cpu:?} {gpu:?}");
+            assert!(
+                (cpu.max_difference - gpu.max_difference).abs() <= 1e-6,
+                "{cpu:?} {gpu:?}"
+            );
+            assert!(
+                cpu.mismatched.abs_diff(gpu.mismatched) <= 1,
+                "{cpu:?} {gpu:?}"
+            );
+        }
+        // the blur changes the image a lot more than the noise
+        let blurred = Comparison::cpu(&lenna, &blurred, 0.05).unwrap();
+        let noisy = Comparison::cpu(&lenna, &noisy, 0.05).unwrap();
+        assert!(blurred.ssim < noisy.ssim);
+        assert!(blurred.mse > noisy.mse);
+    }
+
+    #[test]
+    fn test_mismatched_and_heatmap() {
+        let expected = image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 255]));
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 2, image::Rgba([255, 255, 255, 255]));
+        // barely visible change stays under the threshold
+        actual.put_pixel(3, 3, image::Rgba([2, 2, 2, 255]));
+        let (actual, expected) = (actual.into(), expected.into());
+
+        let comparison = Comparison::cpu(&actual, &expected, 0.1).unwrap();
+        assert_eq!(comparison.mismatched, 1);
+        assert_eq!(comparison.max_difference, 1.0);
+        let heatmap = heatmap(&actual, &expected);
+        assert_eq!(heatmap.get_pixel(1, 2).0, [255, 255, 255]);
+        assert_eq!(heatmap.get_pixel(0, 0).0, [0, 0, 0]);
+        assert!(heatmap.get_pixel(3, 3).0 != [0, 0, 0]);
+    }
+}
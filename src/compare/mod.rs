@@ -0,0 +1,6 @@
+mod demo;
+mod metrics;
+
+pub use demo::{Opts, demo};
+#[cfg(test)]
+pub use metrics::{Comparison, heatmap};
@@ -1,7 +1,16 @@
-use crate::gpu_context::GpuContext;
+use crate::{gpu_context::GpuContext, runner::Frame};
+
+/// Seconds between the passes of an offscreen frame, see
+/// `EguiRenderer::frame_offscreen`.
+const OFFSCREEN_PASS_DT: f32 = 1.0 / 60.0;
+/// Offscreen frames stop after this many passes even if egui keeps asking
+/// for repaints.
+const MAX_OFFSCREEN_PASSES: u32 = 60;
 
 pub(crate) struct EguiRenderer {
-    state: egui_winit::State,
+    /// `None` when rendering offscreen, there is no window to get input from.
+    state: Option<egui_winit::State>,
+    context: egui::Context,
     renderer: egui_wgpu::Renderer,
     gpu_context: GpuContext,
     frame_started: bool,
@@ -10,7 +19,7 @@ pub(crate) struct EguiRenderer {
 
 impl EguiRenderer {
     pub fn egui_context(&self) -> &egui::Context {
-        self.state.egui_ctx()
+        &self.context
     }
 
     pub fn new(
@@ -18,18 +27,20 @@ impl EguiRenderer {
         output_color_format: wgpu::TextureFormat,
         output_depth_format: Option<wgpu::TextureFormat>,
         msaa_samples: u32,
-        window: &winit::window::Window,
+        window: Option<&winit::window::Window>,
         title: &'static str,
     ) -> Self {
         let egui_context = egui::Context::default();
-        let egui_state = egui_winit::State::new(
-            egui_context,
-            egui::viewport::ViewportId::ROOT,
-            &window,
-            Some(window.scale_factor() as f32),
-            None,
-            Some(2 * 1024),
-        );
+        let egui_state = window.map(|window| {
+            egui_winit::State::new(
+                egui_context.clone(),
+                egui::viewport::ViewportId::ROOT,
+                &window,
+                Some(window.scale_factor() as f32),
+                None,
+                Some(2 * 1024),
+            )
+        });
         let egui_renderer = egui_wgpu::Renderer::new(
             &gpu_context.device,
             output_color_format,
@@ -40,6 +51,7 @@ impl EguiRenderer {
 
         Self {
             state: egui_state,
+            context: egui_context,
             renderer: egui_renderer,
             gpu_context,
             frame_started: false,
@@ -47,12 +59,17 @@ impl EguiRenderer {
         }
     }
 
+    /// Returns whether egui used the event, e.g. a click on one of its
+    /// widgets, in which case the demo shouldn't act on it too.
     pub fn handle_input(
         &mut self,
         window: &winit::window::Window,
         event: &winit::event::WindowEvent,
-    ) {
-        let _ = self.state.on_window_event(window, event);
+    ) -> bool {
+        match &mut self.state {
+            Some(state) => state.on_window_event(window, event).consumed,
+            None => false,
+        }
     }
 
     pub fn ppp(&mut self, v: f32) {
@@ -60,8 +77,9 @@ impl EguiRenderer {
     }
 
     pub fn begin_frame(&mut self, window: &winit::window::Window) {
-        let raw_input = self.state.take_egui_input(window);
-        self.state.egui_ctx().begin_pass(raw_input);
+        let state = self.state.as_mut().expect("no window to take input from");
+        let raw_input = state.take_egui_input(window);
+        self.context.begin_pass(raw_input);
         self.frame_started = true;
     }
 
@@ -69,7 +87,7 @@ impl EguiRenderer {
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
         window: &winit::window::Window,
-        window_surface_view: &wgpu::TextureView,
+        frame: &Frame,
         screen_descriptor: egui_wgpu::ScreenDescriptor,
     ) {
         if !self.frame_started {
@@ -78,14 +96,25 @@ impl EguiRenderer {
 
         self.ppp(screen_descriptor.pixels_per_point);
 
-        let full_output = self.state.egui_ctx().end_pass();
-        self.state
-            .handle_platform_output(window, full_output.platform_output);
+        let mut full_output = self.context.end_pass();
+        if let Some(state) = &mut self.state {
+            let platform_output = std::mem::take(&mut full_output.platform_output);
+            state.handle_platform_output(window, platform_output);
+        }
+        self.draw(encoder, frame, full_output, screen_descriptor);
+        self.frame_started = false;
+    }
 
+    fn draw(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &Frame,
+        full_output: egui::FullOutput,
+        screen_descriptor: egui_wgpu::ScreenDescriptor,
+    ) {
         let tris = self
-            .state
-            .egui_ctx()
-            .tessellate(full_output.shapes, self.state.egui_ctx().pixels_per_point());
+            .context
+            .tessellate(full_output.shapes, self.context.pixels_per_point());
         for (id, image_delta) in &full_output.textures_delta.set {
             self.renderer.update_texture(
                 &self.gpu_context.device,
@@ -103,15 +132,10 @@ impl EguiRenderer {
         );
         let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("egui main render pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: window_surface_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
+            color_attachments: &[Some(frame.color_attachment(wgpu::LoadOp::Load))],
+            // egui doesn't depth test but its pipeline has to match the
+            // attachments of the pass when the demo uses a depth buffer
+            depth_stencil_attachment: frame.depth_attachment(wgpu::LoadOp::Load),
             timestamp_writes: None,
             occlusion_query_set: None,
         });
@@ -124,13 +148,12 @@ impl EguiRenderer {
         for x in &full_output.textures_delta.free {
             self.renderer.free_texture(x);
         }
-        self.frame_started = false;
     }
 
     pub fn frame<R>(
         &mut self,
         window: &winit::window::Window,
-        view: &wgpu::TextureView,
+        frame: &Frame,
         encoder: &mut wgpu::CommandEncoder,
         surface_config: &wgpu::SurfaceConfiguration,
         show_fn: impl FnOnce(&mut egui::Ui) -> R,
@@ -144,6 +167,52 @@ impl EguiRenderer {
             .resizable(true)
             .default_open(true)
             .show(self.egui_context(),show_fn);
-        self.end_frame_and_draw(encoder, window, &view, screen_descriptor);
+        self.end_frame_and_draw(encoder, window, frame, screen_descriptor);
+    }
+
+    /// Draw the ui into a `width` x `height` frame without a window, with
+    /// no input and one point per pixel. Windows are laid out and faded in
+    /// over several passes, so this runs passes with a fixed time step
+    /// until egui stops asking for repaints and draws the last one. The
+    /// result only depends on the ui.
+    pub fn frame_offscreen(
+        &mut self,
+        frame: &Frame,
+        encoder: &mut wgpu::CommandEncoder,
+        width: u32,
+        height: u32,
+        mut show_fn: impl FnMut(&mut egui::Ui),
+    ) {
+        let screen_rect =
+            egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(width as f32, height as f32));
+        self.ppp(1.0);
+        let mut full_output = egui::FullOutput::default();
+        for pass in 0..MAX_OFFSCREEN_PASSES {
+            let raw_input = egui::RawInput {
+                screen_rect: Some(screen_rect),
+                time: Some((pass as f32 * OFFSCREEN_PASS_DT) as f64),
+                predicted_dt: OFFSCREEN_PASS_DT,
+                ..Default::default()
+            };
+            let output = self.context.run(raw_input, |ctx| {
+                egui::Window::new(self.title)
+                    .resizable(true)
+                    .default_open(true)
+                    .show(ctx, |ui| show_fn(ui));
+            });
+            let repaint = output
+                .viewport_output
+                .get(&egui::ViewportId::ROOT)
+                .is_some_and(|viewport| viewport.repaint_delay.is_zero());
+            full_output.append(output);
+            if !repaint {
+                break;
+            }
+        }
+        let screen_descriptor = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [width, height],
+            pixels_per_point: 1.0,
+        };
+        self.draw(encoder, frame, full_output, screen_descriptor);
     }
 }
@@ -0,0 +1,96 @@
+// Radix-2 Stockham FFT over the rows or columns of a spectrum, one
+// butterfly pass at a time. Every element holds four complex numbers so an
+// RGBA image fits in one, as `re` and `im`.
+//
+// A line of `len` elements is transformed in log2(len) passes with the
+// stride going 1, 2, 4 ... len / 2. Each pass reads the pair of elements
+// len / 2 apart and writes them next to each other with `stride` between
+// them, so the output comes out in order without a bit reversal.
+
+// has to match `Complex4` in fft_convolve.wgsl
+struct Complex4 {
+    re: vec4f,
+    im: vec4f,
+}
+
+struct Stage {
+    // length of the lines transformed
+    len: u32,
+    stride: u32,
+    // length of the rows of the spectrum
+    width: u32,
+    // whether the lines are columns
+    vertical: u32,
+    // index step in `twiddles` per step of the angle for this length
+    twiddle_step: u32,
+    // 1 for the inverse transform, which also halves every pass so the
+    // result is divided by len in the end
+    inverse: u32,
+}
+
+struct Size {
+    width: u32,
+    height: u32,
+}
+
+@group(0) @binding(0) var<uniform> stage: Stage;
+@group(0) @binding(1) var<storage, read> src: array<Complex4>;
+@group(0) @binding(2) var<storage, read_write> dst: array<Complex4>;
+// exp(-2 pi i k / n) for k < n / 2 with n the longest side of the spectrum
+@group(0) @binding(3) var<storage, read> twiddles: array<vec2f>;
+
+@group(0) @binding(4) var<uniform> size: Size;
+@group(0) @binding(5) var<storage, read_write> product: array<Complex4>;
+@group(0) @binding(6) var<storage, read> factors: array<Complex4>;
+
+fn index(line: u32, i: u32) -> u32 {
+    if (stage.vertical != 0u) {
+        return i * stage.width + line;
+    }
+    return line * stage.width + i;
+}
+
+fn times(a: Complex4, w: vec2f) -> Complex4 {
+    return Complex4(a.re * w.x - a.im * w.y, a.re * w.y + a.im * w.x);
+}
+
+// one invocation per pair of elements of a line, one line per y
+@compute
+@workgroup_size(64, 1, 1)
+fn butterfly(@builtin(global_invocation_id) id: vec3u) {
+    let half = stage.len / 2u;
+    let t = id.x;
+    let line = id.y;
+    if (t >= half) {
+        return;
+    }
+
+    let p = t / stage.stride;
+    let q = t % stage.stride;
+    var w = twiddles[p * stage.stride * stage.twiddle_step];
+    var scale = 1.0;
+    if (stage.inverse != 0u) {
+        w.y = -w.y;
+        scale = 0.5;
+    }
+    let a = src[index(line, t)];
+    let b = src[index(line, t + half)];
+    let out = q + 2u * p * stage.stride;
+    dst[index(line, out)] = Complex4((a.re + b.re) * scale, (a.im + b.im) * scale);
+    let d = times(Complex4(a.re - b.re, a.im - b.im), w);
+    dst[index(line, out + stage.stride)] = Complex4(d.re * scale, d.im * scale);
+}
+
+// multiplies every complex number of `product` with the one in `factors`
+@compute
+@workgroup_size(8, 8, 1)
+fn multiply(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= size.width || id.y >= size.height) {
+        return;
+    }
+
+    let i = id.y * size.width + id.x;
+    let a = product[i];
+    let b = factors[i];
+    product[i] = Complex4(a.re * b.re - a.im * b.im, a.re * b.im + a.im * b.re);
+}
@@ -0,0 +1,382 @@
+//! Fast Fourier transforms of 2D arrays on the GPU, for convolutions with
+//! large kernels and other work in the frequency domain.
+
+use wgpu::util::DeviceExt;
+
+use crate::gpu_context::GpuContext;
+
+/// Bytes of the `Complex4` struct in fft.wgsl, four complex numbers as a
+/// vec4 of real parts and one of imaginary parts.
+pub const COMPLEX4_SIZE: u64 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// exp(-2 pi i k x / n), not scaled
+    Forward,
+    /// exp(2 pi i k x / n), divided by the number of elements so it undoes
+    /// the forward transform
+    Inverse,
+}
+
+/// A `width` x `height` array of `Complex4` in row major order in a storage
+/// buffer, both sides a power of two. The transforms go back and forth
+/// between two buffers, [`Spectrum::buffer`] is the one holding the data.
+pub struct Spectrum {
+    pub width: u32,
+    pub height: u32,
+    buffers: [wgpu::Buffer; 2],
+    current: usize,
+}
+
+impl Spectrum {
+    /// A spectrum of zeros.
+    pub fn new(gpu_context: &GpuContext, width: u32, height: u32) -> Self {
+        assert!(
+            width.is_power_of_two() && height.is_power_of_two(),
+            "{width}x{height} spectrum"
+        );
+        let buffer = |label| {
+            gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: Self::size(width, height),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            width,
+            height,
+            buffers: [buffer("spectrum buffer"), buffer("spectrum scratch buffer")],
+            current: 0,
+        }
+    }
+
+    /// Bytes of a `width` x `height` spectrum.
+    pub fn size(width: u32, height: u32) -> u64 {
+        width as u64 * height as u64 * COMPLEX4_SIZE
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffers[self.current]
+    }
+}
+
+/// The FFT compute pipelines, reusable for spectra of any size.
+pub struct Fft {
+    butterfly: wgpu::ComputePipeline,
+    multiply: wgpu::ComputePipeline,
+}
+
+impl Fft {
+    pub fn new(gpu_context: &GpuContext) -> Self {
+        let device = &gpu_context.device;
+        let shader_module = device.create_shader_module(wgpu::include_wgsl!("fft.wgsl"));
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform = wgpu::BufferBindingType::Uniform;
+        let read = wgpu::BufferBindingType::Storage { read_only: true };
+        let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+        let pipeline = |entry_point, entries: &[wgpu::BindGroupLayoutEntry]| {
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("fft bind group layout"),
+                    entries,
+                });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("fft pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        Self {
+            butterfly: pipeline(
+                "butterfly",
+                &[
+                    buffer_entry(0, uniform),
+                    buffer_entry(1, read),
+                    buffer_entry(2, read_write),
+                    buffer_entry(3, read),
+                ],
+            ),
+            multiply: pipeline(
+                "multiply",
+                &[
+                    buffer_entry(4, uniform),
+                    buffer_entry(5, read_write),
+                    buffer_entry(6, read),
+                ],
+            ),
+        }
+    }
+
+    /// Record transforming `spectrum` in place, the rows and then the
+    /// columns.
+    pub fn encode(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        spectrum: &mut Spectrum,
+        direction: Direction,
+    ) {
+        let device = &gpu_context.device;
+        let (width, height) = (spectrum.width, spectrum.height);
+        let longest = width.max(height);
+        let twiddles: Vec<[f32; 2]> = (0..longest / 2)
+            .map(|k| {
+                let angle = -2.0 * std::f64::consts::PI * k as f64 / longest as f64;
+                [angle.cos() as f32, angle.sin() as f32]
+            })
+            .collect();
+        let twiddles_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fft twiddles buffer"),
+            usage: wgpu::BufferUsages::STORAGE,
+            // a binding can't be empty
+            contents: bytemuck::cast_slice(if twiddles.is_empty() {
+                &[[1.0, 0.0]]
+            } else {
+                &twiddles
+            }),
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("fft pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.butterfly);
+        for (len, lines, vertical) in [(width, height, false), (height, width, true)] {
+            let mut stride = 1;
+            while stride < len {
+                // the `Stage` struct in fft.wgsl
+                let stage = [
+                    len,
+                    stride,
+                    width,
+                    vertical as u32,
+                    longest / len,
+                    (direction == Direction::Inverse) as u32,
+                ];
+                let stage_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("fft stage buffer"),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                    contents: bytemuck::bytes_of(&stage),
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("fft bind group"),
+                    layout: &self.butterfly.get_bind_group_layout(0),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: stage_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: spectrum.buffers[spectrum.current].as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: spectrum.buffers[1 - spectrum.current].as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: twiddles_buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+                compute_pass.set_bind_group(0, Some(&bind_group), &[]);
+                // one invocation per butterfly, one line per y
+                compute_pass.dispatch_workgroups((len / 2).div_ceil(64), lines, 1);
+                spectrum.current = 1 - spectrum.current;
+                stride *= 2;
+            }
+        }
+    }
+
+    /// Record multiplying every element of `spectrum` with the one of
+    /// `factors`, which convolves what they are the transforms of.
+    pub fn multiply(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        spectrum: &Spectrum,
+        factors: &Spectrum,
+    ) {
+        assert_eq!(
+            (spectrum.width, spectrum.height),
+            (factors.width, factors.height)
+        );
+        let device = &gpu_context.device;
+        // the `Size` struct in fft.wgsl
+        let size = [spectrum.width, spectrum.height];
+        let size_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fft size buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::bytes_of(&size),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fft multiply bind group"),
+            layout: &self.multiply.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: size_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: spectrum.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: factors.buffer().as_entire_binding(),
+                },
+            ],
+        });
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("fft multiply pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.multiply);
+        compute_pass.set_bind_group(0, Some(&bind_group), &[]);
+        compute_pass.dispatch_workgroups(
+            spectrum.width.div_ceil(8),
+            spectrum.height.div_ceil(8),
+            1,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Fft, Spectrum};
+    use crate::{box_blur_2d::random_image, gpu_context::GpuContext, offscreen};
+
+    /// Four lanes of complex numbers per element, as in `Complex4`.
+    type Element = [[f64; 2]; 4];
+
+    fn to_bytes(data: &[Element]) -> Vec<u8> {
+        let floats: Vec<f32> = data
+            .iter()
+            .flat_map(|e| {
+                let re = e.map(|c| c[0] as f32);
+                let im = e.map(|c| c[1] as f32);
+                re.into_iter().chain(im)
+            })
+            .collect();
+        bytemuck::cast_slice(&floats).to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Vec<Element> {
+        bytemuck::cast_slice::<u8, f32>(bytes)
+            .chunks_exact(8)
+            .map(|e| std::array::from_fn(|lane| [e[lane] as f64, e[lane + 4] as f64]))
+            .collect()
+    }
+
+    /// Straightforward 2D DFT.
+    fn dft(data: &[Element], width: usize, height: usize) -> Vec<Element> {
+        let mut out = vec![[[0.0; 2]; 4]; data.len()];
+        for v in 0..height {
+            for u in 0..width {
+                for y in 0..height {
+                    for x in 0..width {
+                        let angle = -2.0
+                            * std::f64::consts::PI
+                            * ((u * x) as f64 / width as f64 + (v * y) as f64 / height as f64);
+                        let (sin, cos) = angle.sin_cos();
+                        for lane in 0..4 {
+                            let [re, im] = data[y * width + x][lane];
+                            let out = &mut out[v * width + u][lane];
+                            out[0] += re * cos - im * sin;
+                            out[1] += re * sin + im * cos;
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_matches_dft() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let fft = Fft::new(&gpu_context);
+        for (width, height) in [(1, 1), (8, 1), (1, 4), (16, 8), (4, 32)] {
+            // the real and imaginary parts side by side
+            let noise = random_image(2 * width as u32, height as u32, 3);
+            let value = |x, y, lane| noise.get_pixel(x, y)[lane] as f64 / 255.0 - 0.5;
+            let data: Vec<Element> = (0..(width * height) as u32)
+                .map(|i| {
+                    let (x, y) = (2 * (i % width as u32), i / width as u32);
+                    std::array::from_fn(|lane| [value(x, y, lane), value(x + 1, y, lane)])
+                })
+                .collect();
+            let mut spectrum = Spectrum::new(&gpu_context, width as u32, height as u32);
+            gpu_context
+                .queue
+                .write_buffer(spectrum.buffer(), 0, &to_bytes(&data));
+            let new_encoder = || {
+                gpu_context
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("fft test encoder"),
+                    })
+            };
+
+            let mut encoder = new_encoder();
+            fft.encode(
+                &gpu_context,
+                &mut encoder,
+                &mut spectrum,
+                Direction::Forward,
+            );
+            let transformed = from_bytes(
+                &offscreen::read_buffer(&gpu_context, encoder, spectrum.buffer()).unwrap(),
+            );
+            let expected = dft(&data, width, height);
+            for (i, (a, b)) in transformed.iter().zip(&expected).enumerate() {
+                for (a, b) in a.iter().flatten().zip(b.iter().flatten()) {
+                    assert!(
+                        (a - b).abs() < 1e-4,
+                        "{width}x{height} element {i}: {a} != {b}"
+                    );
+                }
+            }
+
+            let mut encoder = new_encoder();
+            fft.encode(
+                &gpu_context,
+                &mut encoder,
+                &mut spectrum,
+                Direction::Inverse,
+            );
+            let restored = from_bytes(
+                &offscreen::read_buffer(&gpu_context, encoder, spectrum.buffer()).unwrap(),
+            );
+            for (i, (a, b)) in restored.iter().zip(&data).enumerate() {
+                for (a, b) in a.iter().flatten().zip(b.iter().flatten()) {
+                    assert!(
+                        (a - b).abs() < 1e-5,
+                        "{width}x{height} inverse element {i}: {a} != {b}"
+                    );
+                }
+            }
+        }
+    }
+}
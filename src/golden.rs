@@ -0,0 +1,116 @@
+//! Golden image tests. Rendered frames are compared against reference PNGs
+//! in `assets/golden` with a perceptual tolerance so small rasterization
+//! differences between drivers don't fail the tests. The comparison is the
+//! one of the `compare` command, on the CPU.
+//!
+//! Set `DEMOSCENE_BLESS=1` to (re)write the reference images instead of
+//! comparing against them.
+
+use std::path::PathBuf;
+
+use crate::{
+    clock::Clock,
+    compare,
+    gpu_context::GpuContext,
+    runner::{self, Demo},
+};
+
+/// Size the demos are rendered at.
+pub const WIDTH: u32 = 320;
+pub const HEIGHT: u32 = 240;
+/// Timestamp the animated demos are rendered at.
+pub const TIME: f32 = 1.0;
+
+/// Two pixels count as different when their YIQ distance is above
+/// `threshold` (0 is exact, 1 is anything goes), see [`compare::Comparison`].
+/// The images match when at most `max_mismatched` of the pixels differ.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    pub threshold: f32,
+    pub max_mismatched: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            threshold: 0.1,
+            max_mismatched: 0.001,
+        }
+    }
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("assets/golden")
+        .join(format!("{name}.png"))
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+/// Compare `actual` against the reference image called `name`. On failure
+/// the actual image and a diff are written to `target/golden`.
+pub fn assert_golden(name: &str, actual: &image::RgbaImage, tolerance: Tolerance) {
+    let reference_path = reference_path(name);
+    if std::env::var_os("DEMOSCENE_BLESS").is_some() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        actual.save(&reference_path).unwrap();
+        return;
+    }
+
+    let expected = image::open(&reference_path)
+        .unwrap_or_else(|e| {
+            panic!(
+                "missing reference image {} ({e}), run with DEMOSCENE_BLESS=1 to create it",
+                reference_path.display()
+            )
+        })
+        .into_rgba8();
+
+    let output_dir = output_dir();
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let actual_path = output_dir.join(format!("{name}-actual.png"));
+
+    if actual.dimensions() != expected.dimensions() {
+        actual.save(&actual_path).unwrap();
+        panic!(
+            "{name}: size {:?} does not match reference size {:?}, actual image written to {}",
+            actual.dimensions(),
+            expected.dimensions(),
+            actual_path.display()
+        );
+    }
+
+    let (actual_image, expected) = (actual.clone().into(), expected.into());
+    let comparison =
+        compare::Comparison::cpu(&actual_image, &expected, tolerance.threshold).unwrap();
+    let mismatched_fraction = comparison.mismatched as f32 / comparison.pixels as f32;
+    if mismatched_fraction > tolerance.max_mismatched {
+        let diff_path = output_dir.join(format!("{name}-diff.png"));
+        actual.save(&actual_path).unwrap();
+        compare::heatmap(&actual_image, &expected)
+            .save(&diff_path)
+            .unwrap();
+        panic!(
+            "{name}: {} pixels ({:.3}%) differ from the reference (PSNR {:.2} dB, SSIM {:.4}), \
+             actual image written to {} and diff to {}",
+            comparison.mismatched,
+            mismatched_fraction * 100.0,
+            comparison.psnr(),
+            comparison.ssim,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+/// Render a demo offscreen at the fixed golden size and timestamp and
+/// compare it against its reference image.
+pub fn assert_demo_golden<D: Demo>(name: &str, opts: D::Opts) {
+    let gpu_context = GpuContext::headless(D::required_features()).unwrap();
+    let actual =
+        runner::render_offscreen::<D>(opts, &gpu_context, WIDTH, HEIGHT, 1, &Clock::frozen(TIME))
+            .unwrap();
+    assert_golden(name, &actual, Tolerance::default());
+}
@@ -1,6 +1,88 @@
 use anyhow::Result;
+use clap::{Args, ValueEnum};
 use pollster::block_on;
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum PowerPreference {
+    Low,
+    High,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(power_preference: PowerPreference) -> Self {
+        match power_preference {
+            PowerPreference::Low => wgpu::PowerPreference::LowPower,
+            PowerPreference::High => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// Options controlling which adapter the demos run on.
+#[derive(Args, Clone, Debug, Default)]
+pub(crate) struct GpuOpts {
+    /// Only consider adapters of this backend
+    #[arg(long, global = true)]
+    backend: Option<Backend>,
+    /// Prefer a low power (integrated) or high performance (discrete) adapter
+    #[arg(long, global = true)]
+    power_preference: Option<PowerPreference>,
+    /// Use the first adapter whose name contains this, ignoring case. See
+    /// `demoscene adapters` for the names
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        conflicts_with_all = ["power_preference", "fallback"]
+    )]
+    adapter: Option<String>,
+    /// Use the software fallback adapter
+    #[arg(long, global = true)]
+    fallback: bool,
+}
+
+impl GpuOpts {
+    /// Options for rendering without a window. Unless an adapter was asked
+    /// for explicitly the fallback adapter is used so it works on machines
+    /// without a GPU.
+    pub fn headless(&self) -> Self {
+        let mut opts = self.clone();
+        if self.backend.is_none() && self.power_preference.is_none() && self.adapter.is_none() {
+            opts.fallback = true;
+        }
+        opts
+    }
+
+    fn backends(&self) -> wgpu::Backends {
+        self.backend.map_or(wgpu::Backends::all(), Into::into)
+    }
+
+    fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: self.backends(),
+            ..Default::default()
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GpuContext {
     pub instance: wgpu::Instance,
@@ -10,13 +92,36 @@ pub struct GpuContext {
 }
 
 impl GpuContext {
-    pub fn new(required_features: wgpu::Features) -> Result<Self> {
-        let instance = wgpu::Instance::new(&Default::default());
-        let adapter = block_on(instance.request_adapter(&Default::default()))?;
+    pub fn new(opts: &GpuOpts, required_features: wgpu::Features) -> Result<Self> {
+        let instance = opts.instance();
+        let adapter = match &opts.adapter {
+            Some(name) => {
+                let needle = name.to_lowercase();
+                instance
+                    .enumerate_adapters(opts.backends())
+                    .into_iter()
+                    .find(|adapter| adapter.get_info().name.to_lowercase().contains(&needle))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "No adapter matching {name:?}, run `demoscene adapters` to list them"
+                        )
+                    })?
+            }
+            None => block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: opts.power_preference.map_or_else(Default::default, Into::into),
+                force_fallback_adapter: opts.fallback,
+                compatible_surface: None,
+            }))?,
+        };
+        // without this feature only the format capabilities guaranteed by
+        // WebGPU can be used, e.g. no 2x or 8x MSAA even where the adapter
+        // supports it
+        let adapter_format_features =
+            adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
         let (device, queue) = block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
                 label: Some("device descriptor"),
-                required_features,
+                required_features: required_features | adapter_format_features,
                 ..Default::default()
             }
         ))?;
@@ -27,4 +132,34 @@ impl GpuContext {
             queue
         })
     }
-}
\ No newline at end of file
+
+    /// Context for rendering without a window. Forces the software fallback
+    /// adapter so it works on machines without a GPU.
+    #[cfg(test)]
+    pub fn headless(required_features: wgpu::Features) -> Result<Self> {
+        Self::new(&GpuOpts::default().headless(), required_features)
+    }
+}
+
+/// Print every adapter the selected backends expose along with what it
+/// supports.
+pub(crate) fn print_adapters(opts: &GpuOpts) {
+    let adapters = opts.instance().enumerate_adapters(opts.backends());
+    if adapters.is_empty() {
+        println!("No adapters found");
+    }
+    for (i, adapter) in adapters.iter().enumerate() {
+        let info = adapter.get_info();
+        println!("{i}: {}", info.name);
+        println!("  backend: {:?}", info.backend);
+        println!("  device type: {:?}", info.device_type);
+        println!("  vendor: {:#06x}, device: {:#06x}", info.vendor, info.device);
+        println!("  driver: {} {}", info.driver, info.driver_info);
+        println!("  features:");
+        for (name, _) in adapter.features().iter_names() {
+            println!("    {name}");
+        }
+        println!("  limits: {:#?}", adapter.limits());
+        println!();
+    }
+}
@@ -0,0 +1,192 @@
+use clap::ValueEnum;
+use half::f16;
+
+use crate::{gpu_context::GpuContext, offscreen::TextureReadback};
+
+/// How the pixels of a [`GpuImage`] are stored on the GPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum PixelFormat {
+    /// 8 bit unsigned normalized channels
+    Rgba8,
+    /// 16 bit float channels, about 3 significant digits
+    Rgba16f,
+    /// 32 bit float channels, holds 8 and 16 bit images exactly
+    Rgba32f,
+}
+
+impl PixelFormat {
+    /// The smallest format that holds every value of `img` exactly.
+    pub fn for_image(img: &image::DynamicImage) -> Self {
+        match bytes_per_channel(img.color()) {
+            1 => PixelFormat::Rgba8,
+            _ => PixelFormat::Rgba32f,
+        }
+    }
+
+    pub fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            PixelFormat::Rgba8 => wgpu::TextureFormat::Rgba8Unorm,
+            PixelFormat::Rgba16f => wgpu::TextureFormat::Rgba16Float,
+            PixelFormat::Rgba32f => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    /// Texel format of a `texture_storage_2d` of this format in WGSL.
+    pub fn wgsl(self) -> &'static str {
+        match self {
+            PixelFormat::Rgba8 => "rgba8unorm",
+            PixelFormat::Rgba16f => "rgba16float",
+            PixelFormat::Rgba32f => "rgba32float",
+        }
+    }
+}
+
+fn bytes_per_channel(color: image::ColorType) -> u8 {
+    color.bytes_per_pixel() / color.channel_count()
+}
+
+/// Replace `{{format}}` in a filter shader with the storage texel format of
+/// the image it writes, WGSL has no way to be generic over it.
+pub(crate) fn shader_source(source: &str, format: PixelFormat) -> String {
+    source.replace("{{format}}", format.wgsl())
+}
+
+/// An RGBA image in a texture. Filters read it as a `texture_2d<f32>`,
+/// either with `textureLoad` or a sampler, and write it as a
+/// `texture_storage_2d`.
+pub(crate) struct GpuImage {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: PixelFormat,
+}
+
+impl GpuImage {
+    const USAGE: wgpu::TextureUsages = wgpu::TextureUsages::TEXTURE_BINDING
+        .union(wgpu::TextureUsages::STORAGE_BINDING)
+        .union(wgpu::TextureUsages::COPY_SRC)
+        .union(wgpu::TextureUsages::COPY_DST);
+
+    pub fn new(gpu_context: &GpuContext, width: u32, height: u32, format: PixelFormat) -> Self {
+        let texture = gpu_context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("gpu image"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: format.texture_format(),
+            usage: Self::USAGE,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        Self {
+            texture,
+            view,
+            format,
+        }
+    }
+
+    /// Upload `img` converted to `format`.
+    pub fn upload(
+        gpu_context: &GpuContext,
+        img: &image::DynamicImage,
+        format: PixelFormat,
+    ) -> Self {
+        let gpu_image = Self::new(gpu_context, img.width(), img.height(), format);
+        let data = match format {
+            PixelFormat::Rgba8 => img.to_rgba8().into_raw(),
+            PixelFormat::Rgba16f => img
+                .to_rgba32f()
+                .iter()
+                .flat_map(|c| f16::from_f32(*c).to_le_bytes())
+                .collect(),
+            PixelFormat::Rgba32f => bytemuck::cast_slice(img.to_rgba32f().as_raw()).to_vec(),
+        };
+        let bytes_per_texel = format.texture_format().block_copy_size(None).unwrap();
+        gpu_context.queue.write_texture(
+            gpu_image.texture.as_image_copy(),
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(img.width() * bytes_per_texel),
+                rows_per_image: Some(img.height()),
+            },
+            gpu_image.texture.size(),
+        );
+        gpu_image
+    }
+
+    pub fn width(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.texture.height()
+    }
+
+    /// Submit `encoder` followed by a copy of the image back to the CPU,
+    /// without waiting for either of them. 8 bit images come back as rgba8,
+    /// float ones as rgba32f.
+    pub fn submit_read(
+        &self,
+        gpu_context: &GpuContext,
+        encoder: wgpu::CommandEncoder,
+    ) -> anyhow::Result<ImageReadback> {
+        Ok(ImageReadback {
+            readback: TextureReadback::submit(gpu_context, encoder, &self.texture)?,
+            width: self.width(),
+            height: self.height(),
+            format: self.format,
+        })
+    }
+}
+
+/// A [`GpuImage`] on its way back to the CPU.
+pub(crate) struct ImageReadback {
+    readback: TextureReadback,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+}
+
+impl ImageReadback {
+    /// Wait for the copy and convert it to an image.
+    pub fn finish(self, gpu_context: &GpuContext) -> anyhow::Result<image::DynamicImage> {
+        let data = self.readback.finish(gpu_context)?;
+        let (width, height) = (self.width, self.height);
+        let img = match self.format {
+            PixelFormat::Rgba8 => image::RgbaImage::from_vec(width, height, data).map(Into::into),
+            PixelFormat::Rgba16f => {
+                let channels = data
+                    .chunks_exact(2)
+                    .map(|c| f16::from_le_bytes([c[0], c[1]]).to_f32())
+                    .collect();
+                image::Rgba32FImage::from_vec(width, height, channels).map(Into::into)
+            }
+            PixelFormat::Rgba32f => {
+                let channels = data
+                    .chunks_exact(4)
+                    .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect();
+                image::Rgba32FImage::from_vec(width, height, channels).map(Into::into)
+            }
+        };
+        img.ok_or(anyhow::anyhow!("Unable to convert GPU texture to image"))
+    }
+}
+
+/// Convert a filtered image to RGBA with the channel depth of the image it
+/// was made from, so it can be saved in the same kind of file.
+pub(crate) fn with_depth_of(
+    img: image::DynamicImage,
+    original: &image::DynamicImage,
+) -> image::DynamicImage {
+    match bytes_per_channel(original.color()) {
+        1 => img.into_rgba8().into(),
+        2 => img.into_rgba16().into(),
+        _ => img.into_rgba32f().into(),
+    }
+}
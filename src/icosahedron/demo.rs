@@ -1,13 +1,20 @@
 use clap::Args;
-use std::{sync::Arc, time::Instant};
 
 use wgpu::{RenderPassDescriptor, util::DeviceExt};
 
 use super::icosahedron::{Icosahedron, Vertex};
-use crate::gpu_context::GpuContext;
-
-#[derive(Args)]
-pub(crate) struct Opts {}
+use crate::{
+    camera::{CameraOpts, OrbitCamera},
+    clock::Clock,
+    gpu_context::GpuContext,
+    runner::{self, Demo, Frame, RunOpts, Target},
+};
+
+#[derive(Args, Default)]
+pub(crate) struct Opts {
+    #[command(flatten)]
+    camera: CameraOpts,
+}
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -15,250 +22,115 @@ struct TimeUniform {
     time: f32,
 }
 
-pub struct App<'a> {
-    window: Option<Arc<winit::window::Window>>,
-    gpu_context: Option<GpuContext>,
-    surface: Option<wgpu::Surface<'a>>,
-    render_pipeline: Option<wgpu::RenderPipeline>,
-    vertex_buffer: Option<wgpu::Buffer>,
-    index_buffer: Option<wgpu::Buffer>,
+pub(crate) struct IcosahedronDemo {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
     indices_len: u32,
-    time_buffer: Option<wgpu::Buffer>,
-    time_bind_group: Option<wgpu::BindGroup>,
-    start_instant: Instant,
+    time_buffer: wgpu::Buffer,
+    time_bind_group: wgpu::BindGroup,
+    camera: OrbitCamera,
 }
 
-impl<'a> App<'a> {
-    pub fn new() -> Self {
-        App {
-            window: None,
-            gpu_context: None,
-            surface: None,
-            render_pipeline: None,
-            vertex_buffer: None,
-            index_buffer: None,
-            indices_len: 0,
-            time_buffer: None,
-            time_bind_group: None,
-            start_instant: Instant::now(),
-        }
-    }
-
-    fn render(&self) -> anyhow::Result<()> {
-        if let (
-            Some(gpu_context),
-            Some(render_pipeline),
-            Some(surface),
-            Some(vertex_buffer),
-            Some(index_buffer),
-            Some(time_buffer),
-            Some(time_bind_group),
-            Some(window)
-        ) = (
-            &self.gpu_context,
-            &self.render_pipeline,
-            &self.surface,
-            &self.vertex_buffer,
-            &self.index_buffer,
-            &self.time_buffer,
-            &self.time_bind_group,
-            &self.window
-        ) {
-            let surface_texture = match surface.get_current_texture() {
-                Ok(st) => Ok(st),
-                Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
-                    let window = self
-                        .window
-                        .as_ref()
-                        .ok_or(anyhow::anyhow!("window not initialized"))?;
-                    self.resize(window.inner_size());
-                    surface.get_current_texture()
-                }
-                Err(e) => Err(e),
-            }?;
-            // let surface_texture = surface.get_current_texture().unwrap();
-            let view = surface_texture.texture.create_view(&Default::default());
-            let mut encoder = gpu_context
-                .device
-                .create_command_encoder(&Default::default());
-            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("render pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            render_pass.set_pipeline(render_pipeline);
-            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+impl Demo for IcosahedronDemo {
+    type Opts = Opts;
 
-            // set time uniform by:
-            // 1. calculating the timestamp
-            // 2. building the time struct
-            // 3. moving the time struct to the GPU
-            // 4. binding the GPU resident time struct to the right slot
-            //    (setting the bind group)
-            let timestamp = Instant::now()
-                .duration_since(self.start_instant)
-                .as_secs_f32();
-            let time_uniform = TimeUniform { time: timestamp };
-            gpu_context
-                .queue
-                .write_buffer(time_buffer, 0, bytemuck::bytes_of(&time_uniform));
-            render_pass.set_bind_group(0, time_bind_group, &[]);
+    const DEPTH: bool = true;
+    const TITLE: &'static str = "Icosahedron";
 
-            render_pass.draw_indexed(0..self.indices_len, 0, 0..1);
-            drop(render_pass);
-            gpu_context.queue.submit(std::iter::once(encoder.finish()));
-            surface_texture.present();
-            window.request_redraw();
-        }
-        Ok(())
+    fn required_features() -> wgpu::Features {
+        wgpu::Features::POLYGON_MODE_LINE
     }
 
-    fn resize(&self, size: winit::dpi::PhysicalSize<u32>) {
-        if size.width == 0 {
-            return;
-        }
-        if let (Some(surface), Some(gpu_context), Some(_)) =
-            (&self.surface, &self.gpu_context, &self.window)
-        {
-            let capabilities = surface.get_capabilities(&gpu_context.adapter);
-            surface.configure(
-                &gpu_context.device,
-                &wgpu::SurfaceConfiguration {
-                    alpha_mode: capabilities.alpha_modes[0],
-                    desired_maximum_frame_latency: 2,
-                    format: capabilities.formats[0],
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                    height: size.height,
-                    width: size.width,
-                    present_mode: capabilities.present_modes[0],
-                    view_formats: vec![],
-                },
-            );
-        }
-    }
-}
+    fn init(opts: Opts, gpu_context: &GpuContext, target: &Target) -> anyhow::Result<Self> {
+        let icosahedron = Icosahedron::new();
+        let indices_len = icosahedron.indices.len() as u32;
 
-impl<'a> winit::application::ApplicationHandler for App<'a> {
-    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        if self.window.is_none() {
-            let window = event_loop.create_window(Default::default()).unwrap();
-            window.set_title("Icosahedron");
-            let window = Arc::new(window);
-            let gpu_context = GpuContext::new(wgpu::Features::POLYGON_MODE_LINE).unwrap();
-            let surface = gpu_context.instance.create_surface(window.clone()).unwrap();
-            let capabilities = surface.get_capabilities(&gpu_context.adapter);
-            surface.configure(
-                &gpu_context.device,
-                &wgpu::SurfaceConfiguration {
-                    alpha_mode: capabilities.alpha_modes[0],
-                    desired_maximum_frame_latency: 2,
-                    format: capabilities.formats[0],
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                    height: window.inner_size().height,
-                    width: window.inner_size().width,
-                    present_mode: capabilities.present_modes[0],
-                    view_formats: vec![],
-                },
-            );
-
-            let icosahedron = Icosahedron::new();
-            self.indices_len = icosahedron.indices.len() as u32;
-
-            self.vertex_buffer = Some(gpu_context.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
+        let vertex_buffer =
+            gpu_context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("vertices"),
                     usage: wgpu::BufferUsages::VERTEX,
                     contents: bytemuck::cast_slice(&icosahedron.vertices),
-                },
-            ));
+                });
 
-            self.index_buffer = Some(gpu_context.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
+        let index_buffer =
+            gpu_context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("indices"),
                     usage: wgpu::BufferUsages::INDEX,
                     contents: bytemuck::cast_slice(&icosahedron.indices),
-                },
-            ));
-
-            // to make a uniform available to the shaders we need to
-            // create a bind_group_layout which represents a slot for our
-            // uniform and add it to the pipeline layout which represents
-            // all the binding groups and then make sure the pipeline is
-            // using that layout.
+                });
 
-            // at render time, we need to have a bind group which references
-            // a buffer that contains our uniform data. each pass we bind
-            // the bind group into the appropriate slot.
+        // to make a uniform available to the shaders we need to
+        // create a bind_group_layout which represents a slot for our
+        // uniform and add it to the pipeline layout which represents
+        // all the binding groups and then make sure the pipeline is
+        // using that layout.
 
-            // we can make our buffer and its bind group once, then update
-            // it as needed.
-            let time_buffer =
-                gpu_context
-                    .device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("time"),
-                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                        contents: bytemuck::bytes_of(&TimeUniform { time: 0.0 }),
-                    });
+        // at render time, we need to have a bind group which references
+        // a buffer that contains our uniform data. each pass we bind
+        // the bind group into the appropriate slot.
 
-            let time_bind_group_layout =
-                gpu_context
-                    .device
-                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                        label: Some("time bind group layout"),
-                        entries: &[wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        }],
-                    });
+        // we can make our buffer and its bind group once, then update
+        // it as needed.
+        let time_buffer =
+            gpu_context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("time"),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    contents: bytemuck::bytes_of(&TimeUniform { time: 0.0 }),
+                });
 
-            self.time_bind_group = Some(gpu_context.device.create_bind_group(
-                &wgpu::BindGroupDescriptor {
-                    label: Some("time bind group"),
-                    layout: &time_bind_group_layout,
-                    entries: &[wgpu::BindGroupEntry {
+        let time_bind_group_layout =
+            gpu_context
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("time bind group layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        resource: time_buffer.as_entire_binding(),
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     }],
-                },
-            ));
-            self.time_buffer = Some(time_buffer);
+                });
+
+        let time_bind_group = gpu_context
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("time bind group"),
+                layout: &time_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: time_buffer.as_entire_binding(),
+                }],
+            });
 
-            let shader_module = gpu_context
-                .device
-                .create_shader_module(wgpu::include_wgsl!("demo.wgsl"));
+        let camera = OrbitCamera::new(&opts.camera, gpu_context, target.width, target.height);
 
-            let pipeline_layout =
-                gpu_context
-                    .device
-                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                        label: Some("pipeline layout"),
-                        bind_group_layouts: &[&time_bind_group_layout],
-                        push_constant_ranges: &[],
-                    });
-            let render_pipeline = gpu_context.device.create_render_pipeline(
-                &wgpu::RenderPipelineDescriptor {
+        let shader_module = gpu_context
+            .device
+            .create_shader_module(wgpu::include_wgsl!("demo.wgsl"));
+
+        let pipeline_layout =
+            gpu_context
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("pipeline layout"),
+                    bind_group_layouts: &[&time_bind_group_layout, &camera.bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let render_pipeline =
+            gpu_context
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                     label: Some("render pipeline"),
                     layout: Some(&pipeline_layout),
                     vertex: wgpu::VertexState {
@@ -280,57 +152,91 @@ impl<'a> winit::application::ApplicationHandler for App<'a> {
                         polygon_mode: wgpu::PolygonMode::Line,
                         conservative: false,
                     },
-                    depth_stencil: None,
-                    multisample: Default::default(),
+                    depth_stencil: target.depth_stencil(),
+                    multisample: target.multisample(),
                     fragment: Some(wgpu::FragmentState {
                         module: &shader_module,
                         entry_point: Some("fs_main"),
                         compilation_options: Default::default(),
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: capabilities.formats[0],
+                            format: target.format,
                             blend: Some(wgpu::BlendState::REPLACE),
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
                     }),
                     multiview: None,
                     cache: None,
-                },
-            );
+                });
+
+        Ok(Self {
+            render_pipeline,
+            vertex_buffer,
+            index_buffer,
+            indices_len,
+            time_buffer,
+            time_bind_group,
+            camera,
+        })
+    }
 
-            self.gpu_context = Some(gpu_context);
-            self.window = Some(window);
-            self.surface = Some(surface);
-            self.render_pipeline = Some(render_pipeline);
-            if let Some(window) = &self.window {
-                window.request_redraw();
-            }
-        }
+    fn update(&mut self, gpu_context: &GpuContext, clock: &Clock) {
+        self.camera.update(gpu_context);
+        // moving the time struct to the GPU, it gets bound to the right
+        // slot (by setting the bind group) during render
+        let time_uniform = TimeUniform { time: clock.time() };
+        gpu_context
+            .queue
+            .write_buffer(&self.time_buffer, 0, bytemuck::bytes_of(&time_uniform));
     }
 
-    fn window_event(
+    fn render(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        _window_id: winit::window::WindowId,
-        event: winit::event::WindowEvent,
+        _gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &Frame,
     ) {
-        match event {
-            winit::event::WindowEvent::CloseRequested => {
-                event_loop.exit();
-            }
-            winit::event::WindowEvent::RedrawRequested => {
-                self.render().unwrap();
-            }
-            winit::event::WindowEvent::Resized(size) => {
-                self.resize(size);
-            }
-            _ => {}
-        }
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("render pass"),
+            color_attachments: &[Some(frame.color_attachment(wgpu::LoadOp::Clear(
+                wgpu::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 1.0,
+                },
+            )))],
+            depth_stencil_attachment: frame.depth_attachment(wgpu::LoadOp::Clear(1.0)),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_bind_group(0, &self.time_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera.bind_group, &[]);
+        render_pass.draw_indexed(0..self.indices_len, 0, 0..1);
     }
+
+    fn handle_event(&mut self, event: &winit::event::WindowEvent) {
+        self.camera.handle_event(event);
+    }
+
+    fn resize(&mut self, _gpu_context: &GpuContext, width: u32, height: u32) {
+        self.camera.resize(width, height);
+    }
+}
+
+pub fn demo(opts: Opts, run_opts: &RunOpts) -> anyhow::Result<()> {
+    runner::run::<IcosahedronDemo>(opts, run_opts)
 }
 
-pub fn demo() -> anyhow::Result<()> {
-    let event_loop = winit::event_loop::EventLoop::new()?;
-    let mut app = App::new();
-    event_loop.run_app(&mut app)?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::{IcosahedronDemo, Opts};
+    use crate::golden;
+
+    #[test]
+    fn test_golden() {
+        golden::assert_demo_golden::<IcosahedronDemo>("icosahedron", Opts::default());
+    }
 }
@@ -1,6 +1,6 @@
 use std::{
     collections::HashSet,
-    f32::consts::{PHI, PI},
+    f32::consts::{GOLDEN_RATIO as PHI, PI},
 };
 
 use nalgebra::{Point3, Rotation3, distance};
@@ -136,7 +136,7 @@ mod tests {
             .indices
             .iter()
             .array_chunks::<3>()
-            .map(|f| {
+            .flat_map(|f| {
                 let a = Point3::from_slice(
                     &icosahedron.vertices[*f[0] as usize].position,
                 );
@@ -148,7 +148,6 @@ mod tests {
                 );
                 [distance(&a, &b), distance(&a, &c), distance(&b, &c)]
             })
-            .flatten()
             .collect::<Vec<f32>>();
         for s in side_lens.iter() {
             assert!((s - side_lens[0]).abs() < 1e-4);
@@ -4,6 +4,13 @@ struct Time {
 
 @group(0) @binding(0) var<uniform> time: Time;
 
+struct Camera {
+    view_projection: mat4x4<f32>,
+    eye: vec4f,
+}
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
 struct Vertex {
     @location(0) pos: vec3f,
     @location(1) normal: vec3f,
@@ -35,7 +42,7 @@ fn vs_main(
     );
 
     return VsOut(
-        vec4f(pos.xy, (pos.z + 1.0)/2.0, 1.0),
+        camera.view_projection * vec4f(pos, 1.0),
         normal
     );
 
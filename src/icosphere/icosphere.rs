@@ -1,6 +1,6 @@
 use std::{
     collections::{HashMap, HashSet},
-    f32::consts::{PHI, PI},
+    f32::consts::{GOLDEN_RATIO as PHI, PI},
 };
 
 use nalgebra::{Point3, Rotation3, distance};
@@ -114,7 +114,7 @@ impl Icosphere {
         let mut edge_was_split = HashMap::<[usize; 2], usize>::new();
         // Subdivide the icosahedron resolution times to get the sphere
         for _ in 0..resolution {
-            faces = faces.iter().map(|face| {
+            faces = faces.iter().flat_map(|face| {
                 // split face half way along its three edges to form
                 // 4 triangles out of the original one.
                 let [i0, i1, i2] = *face;
@@ -134,38 +134,26 @@ impl Icosphere {
                 let i01 = {
                     let mut edge = [i0, i1];
                     edge.sort();
-                    if edge_was_split.contains_key(&edge) {
-                        edge_was_split[&edge]
-                    } else {
-                        let new_idx = positions.len();
+                    *edge_was_split.entry(edge).or_insert_with(|| {
                         positions.push(p01);
-                        edge_was_split.insert(edge, new_idx);
-                        new_idx
-                    }
+                        positions.len() - 1
+                    })
                 };
                 let i02 = {
                     let mut edge = [i0, i2];
                     edge.sort();
-                    if edge_was_split.contains_key(&edge) {
-                        edge_was_split[&edge]
-                    } else {
-                        let new_idx = positions.len();
+                    *edge_was_split.entry(edge).or_insert_with(|| {
                         positions.push(p02);
-                        edge_was_split.insert(edge, new_idx);
-                        new_idx
-                    }
+                        positions.len() - 1
+                    })
                 };
                 let i12 = {
                     let mut edge = [i1, i2];
                     edge.sort();
-                    if edge_was_split.contains_key(&edge) {
-                        edge_was_split[&edge]
-                    } else {
-                        let new_idx = positions.len();
+                    *edge_was_split.entry(edge).or_insert_with(|| {
                         positions.push(p12);
-                        edge_was_split.insert(edge, new_idx);
-                        new_idx
-                    }
+                        positions.len() - 1
+                    })
                 };
 
                 // now we need to emit 4 new faces, paying attention to orientation
@@ -176,7 +164,7 @@ impl Icosphere {
                     [i02, i12, i2],
                     [i01, i12, i02],
                 ]
-            }).flatten().collect();
+            }).collect();
         }
 
         let vertices = positions
@@ -1,4 +1,5 @@
 mod demo;
+#[allow(clippy::module_inception)]
 mod icosphere;
 
 pub(crate) use demo::{demo, Opts};
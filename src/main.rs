@@ -1,14 +1,23 @@
-#![feature(more_float_constants)]
-#![feature(iter_array_chunks)]
+#![cfg_attr(test, feature(iter_array_chunks))]
 use clap::{CommandFactory, Parser, Subcommand};
 
 mod bare_window;
 mod box_blur_2d;
+mod camera;
+mod clock;
+mod compare;
 mod egui_inside;
 mod egui_renderer;
+mod fft;
 mod gpu_context;
+mod gpu_image;
+#[cfg(test)]
+mod golden;
 mod icosahedron;
 mod icosphere;
+mod offscreen;
+mod recorder;
+mod runner;
 mod single_triangle;
 mod affine_transforms;
 
@@ -17,6 +26,9 @@ mod affine_transforms;
 struct CLIOptions {
     #[command(subcommand)]
     demo: Option<Demo>,
+
+    #[command(flatten)]
+    run: runner::RunOpts,
 }
 
 #[derive(Subcommand)]
@@ -32,6 +44,36 @@ enum Demo {
     /// Blur an image
     #[command(name = "box-blur-2d")]
     BoxBlur2D(box_blur_2d::Opts),
+    /// Time the box blur algorithms for several radii
+    BlurBenchmark(box_blur_2d::BlurBenchmarkOpts),
+    /// Gaussian blur an image
+    GaussianBlur(box_blur_2d::GaussianOpts),
+    /// Convolve an image with a kernel from a file or a preset
+    Convolve(box_blur_2d::ConvolveOpts),
+    /// Convolve an image with a kernel through its Fourier transform, for
+    /// large kernels
+    FftConvolve(box_blur_2d::ConvolveOpts),
+    /// Replace every pixel with the median of its neighborhood
+    Median(box_blur_2d::MedianOpts),
+    /// Blur an image while keeping its edges sharp
+    Bilateral(box_blur_2d::BilateralOpts),
+    /// Find the edges in an image with Sobel or Canny
+    Edges(box_blur_2d::EdgesOpts),
+    /// Scale an image to a new size
+    Resize(box_blur_2d::ResizeOpts),
+    /// Generate the mip levels of an image
+    Mipmaps(box_blur_2d::MipmapsOpts),
+    /// Count the values of an image's channels
+    Histogram(box_blur_2d::HistogramOpts),
+    /// Move the black and white points and the midtones of an image
+    Levels(box_blur_2d::LevelsOpts),
+    /// Spread the values of an image over the whole range to boost contrast
+    Equalize(box_blur_2d::EqualizeOpts),
+    /// Run a chain of image filters on the GPU
+    #[command(name = "image-pipeline")]
+    ImagePipeline(box_blur_2d::PipelineOpts),
+    /// Compare two images and report MSE, PSNR and SSIM
+    Compare(compare::Opts),
     /// Show egui inside of winit + wgpu
     #[command(name = "egui-inside")]
     EguiInside(egui_inside::Opts),
@@ -39,6 +81,9 @@ enum Demo {
     /// See how affine transforms affect vertex data
     #[command(name = "affine-transforms")]
     AffineTransforms(affine_transforms::Opts),
+
+    /// List the GPU adapters with their limits and features
+    Adapters,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -47,23 +92,68 @@ fn main() -> anyhow::Result<()> {
         Some(Demo::BareWindow(_)) => {
             bare_window::demo()?;
         }
-        Some(Demo::SingleTriangle(_)) => {
-            single_triangle::demo()?;
+        Some(Demo::SingleTriangle(demo_opts)) => {
+            single_triangle::demo(demo_opts, &opts.run)?;
+        }
+        Some(Demo::Icosahedron(demo_opts)) => {
+            icosahedron::demo(demo_opts, &opts.run)?;
+        }
+        Some(Demo::Icosphere(demo_opts)) => {
+            icosphere::demo(demo_opts, &opts.run)?;
+        }
+        Some(Demo::BoxBlur2D(demo_opts)) => {
+            box_blur_2d::demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::BlurBenchmark(demo_opts)) => {
+            box_blur_2d::blur_benchmark(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::GaussianBlur(demo_opts)) => {
+            box_blur_2d::gaussian_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Convolve(demo_opts)) => {
+            box_blur_2d::convolve_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::FftConvolve(demo_opts)) => {
+            box_blur_2d::fft_convolve_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Median(demo_opts)) => {
+            box_blur_2d::median_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Bilateral(demo_opts)) => {
+            box_blur_2d::bilateral_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Edges(demo_opts)) => {
+            box_blur_2d::edges_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Resize(demo_opts)) => {
+            box_blur_2d::resize_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Mipmaps(demo_opts)) => {
+            box_blur_2d::mipmaps_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Histogram(demo_opts)) => {
+            box_blur_2d::histogram_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Levels(demo_opts)) => {
+            box_blur_2d::levels_demo(demo_opts, &opts.run.gpu)?;
+        }
+        Some(Demo::Equalize(demo_opts)) => {
+            box_blur_2d::equalize_demo(demo_opts, &opts.run.gpu)?;
         }
-        Some(Demo::Icosahedron(_)) => {
-            icosahedron::demo()?;
+        Some(Demo::ImagePipeline(demo_opts)) => {
+            box_blur_2d::pipeline_demo(demo_opts, &opts.run.gpu)?;
         }
-        Some(Demo::Icosphere(opts)) => {
-            icosphere::demo(opts)?;
+        Some(Demo::Compare(demo_opts)) => {
+            compare::demo(demo_opts, &opts.run.gpu)?;
         }
-        Some(Demo::BoxBlur2D(opts)) => {
-            box_blur_2d::demo(opts)?;
+        Some(Demo::EguiInside(demo_opts)) => {
+            egui_inside::demo(demo_opts, &opts.run)?;
         }
-        Some(Demo::EguiInside(_)) => {
-            egui_inside::demo()?;
+        Some(Demo::AffineTransforms(demo_opts)) => {
+            affine_transforms::demo(demo_opts, &opts.run)?;
         }
-        Some(Demo::AffineTransforms(_)) => {
-            affine_transforms::demo()?;
+        Some(Demo::Adapters) => {
+            gpu_context::print_adapters(&opts.run.gpu);
         }
         None => {
             let mut cmd = CLIOptions::command();
@@ -0,0 +1,229 @@
+use crate::gpu_context::GpuContext;
+
+/// A color texture that can be rendered into instead of a window surface
+/// and read back to the CPU afterwards.
+pub(crate) struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl OffscreenTarget {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(gpu_context: &GpuContext, width: u32, height: u32) -> Self {
+        let texture = gpu_context.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        Self { texture, view }
+    }
+
+    pub fn read_image(&self, gpu_context: &GpuContext) -> anyhow::Result<image::RgbaImage> {
+        read_texture(gpu_context, &self.texture)
+    }
+}
+
+/// Copy an 8 bit RGBA or BGRA texture back to the CPU.
+pub(crate) fn read_texture(
+    gpu_context: &GpuContext,
+    texture: &wgpu::Texture,
+) -> anyhow::Result<image::RgbaImage> {
+    let swap_red_blue = match texture.format() {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        format => anyhow::bail!("Unable to read back texture with format {format:?}"),
+    };
+
+    let mut pixels = read_texture_data(gpu_context, texture)?;
+    if swap_red_blue {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    image::RgbaImage::from_vec(texture.width(), texture.height(), pixels)
+        .ok_or(anyhow::anyhow!("Unable to convert GPU buffer to image"))
+}
+
+/// Copy the texels of a texture back to the CPU, row after row without
+/// any gaps.
+pub(crate) fn read_texture_data(
+    gpu_context: &GpuContext,
+    texture: &wgpu::Texture,
+) -> anyhow::Result<Vec<u8>> {
+    let encoder = gpu_context
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("readback encoder"),
+        });
+    TextureReadback::submit(gpu_context, encoder, texture)?.finish(gpu_context)
+}
+
+/// Submit `encoder` followed by a copy of `buffer` to the CPU and wait for
+/// it. The buffer needs `COPY_SRC` usage.
+pub(crate) fn read_buffer(
+    gpu_context: &GpuContext,
+    mut encoder: wgpu::CommandEncoder,
+    buffer: &wgpu::Buffer,
+) -> anyhow::Result<Vec<u8>> {
+    let staging_buffer = gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback staging buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    let submission_index = gpu_context.queue.submit([encoder.finish()]);
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    staging_buffer.map_async(wgpu::MapMode::Read, .., move |r| {
+        tx.send(r).unwrap();
+    });
+    gpu_context
+        .device
+        .poll(wgpu::PollType::WaitForSubmissionIndex(submission_index))?;
+    rx.recv()??;
+
+    let data = staging_buffer.get_mapped_range(..).to_vec();
+    staging_buffer.unmap();
+    Ok(data)
+}
+
+/// A copy of a texture to the CPU that has been submitted but not waited
+/// for, so the CPU can get on with something else in the meantime.
+///
+/// Rows of a texture to buffer copy have to be padded to
+/// `COPY_BYTES_PER_ROW_ALIGNMENT` so the padding is stripped again
+/// afterwards.
+pub(crate) struct TextureReadback {
+    staging_buffer: wgpu::Buffer,
+    submission_index: wgpu::SubmissionIndex,
+    mapped: std::sync::mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+    unpadded_bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl TextureReadback {
+    /// Record the copy of `texture` at the end of `encoder` and submit it.
+    pub fn submit(
+        gpu_context: &GpuContext,
+        mut encoder: wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> anyhow::Result<Self> {
+        let format = texture.format();
+        let bytes_per_texel = format
+            .block_copy_size(None)
+            .ok_or_else(|| anyhow::anyhow!("Unable to read back texture with format {format:?}"))?;
+        let (width, height) = (texture.width(), texture.height());
+        let unpadded_bytes_per_row = width * bytes_per_texel;
+        let padded_bytes_per_row =
+            unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let staging_buffer = gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback staging buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        let submission_index = gpu_context.queue.submit([encoder.finish()]);
+
+        // a buffer can only be mapped after the submission writing it
+        let (tx, rx) = std::sync::mpsc::channel();
+        staging_buffer.map_async(wgpu::MapMode::Read, .., move |r| {
+            tx.send(r).unwrap();
+        });
+
+        Ok(Self {
+            staging_buffer,
+            submission_index,
+            mapped: rx,
+            unpadded_bytes_per_row,
+            padded_bytes_per_row,
+        })
+    }
+
+    /// Wait for the copy and return the texels.
+    pub fn finish(self, gpu_context: &GpuContext) -> anyhow::Result<Vec<u8>> {
+        gpu_context
+            .device
+            .poll(wgpu::PollType::WaitForSubmissionIndex(
+                self.submission_index,
+            ))?;
+        self.mapped.recv()??;
+
+        let staging_buffer_view = self.staging_buffer.get_mapped_range(..);
+        let mut data = Vec::with_capacity(
+            self.unpadded_bytes_per_row as usize * staging_buffer_view.len()
+                / self.padded_bytes_per_row as usize,
+        );
+        for row in staging_buffer_view.chunks_exact(self.padded_bytes_per_row as usize) {
+            data.extend_from_slice(&row[..self.unpadded_bytes_per_row as usize]);
+        }
+        drop(staging_buffer_view);
+        self.staging_buffer.unmap();
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OffscreenTarget;
+    use crate::gpu_context::GpuContext;
+
+    #[test]
+    fn test_offscreen_clear_readback() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        // odd width so the rows need padding
+        let target = OffscreenTarget::new(&gpu_context, 37, 5);
+
+        let mut encoder = gpu_context
+            .device
+            .create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("clear pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::RED),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        gpu_context.queue.submit([encoder.finish()]);
+
+        let img = target.read_image(&gpu_context).unwrap();
+        assert_eq!(img.dimensions(), (37, 5));
+        for pixel in img.pixels() {
+            assert_eq!(pixel.0, [255, 0, 0, 255]);
+        }
+    }
+}
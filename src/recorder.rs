@@ -0,0 +1,104 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use image::codecs::gif::{GifEncoder, Repeat};
+
+/// Writes the frames of a demo to disk. The output format is picked from
+/// the path: `.gif` is an animated gif, `.png` / `.apng` an animated png
+/// and anything else is treated as a directory of numbered png frames.
+pub(crate) enum Recorder {
+    Frames {
+        dir: PathBuf,
+        frame: u32,
+    },
+    Gif {
+        encoder: GifEncoder<BufWriter<File>>,
+        fps: f32,
+    },
+    // apng needs the frame count up front so frames are kept until `finish`
+    Apng {
+        path: PathBuf,
+        fps: f32,
+        frames: Vec<image::RgbaImage>,
+    },
+}
+
+impl Recorder {
+    pub fn new(path: &Path, fps: f32) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("gif") => {
+                // speed trades color quantization quality for encoding time,
+                // 1 is slowest and 30 fastest
+                let mut encoder =
+                    GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Ok(Self::Gif { encoder, fps })
+            }
+            Some("png" | "apng") => Ok(Self::Apng {
+                path: path.to_path_buf(),
+                fps,
+                frames: vec![],
+            }),
+            _ => {
+                std::fs::create_dir_all(path)?;
+                Ok(Self::Frames {
+                    dir: path.to_path_buf(),
+                    frame: 0,
+                })
+            }
+        }
+    }
+
+    pub fn add_frame(&mut self, img: image::RgbaImage) -> anyhow::Result<()> {
+        match self {
+            Self::Frames { dir, frame } => {
+                img.save(dir.join(format!("frame_{frame:05}.png")))?;
+                *frame += 1;
+            }
+            Self::Gif { encoder, fps } => {
+                let delay = image::Delay::from_numer_denom_ms(1000, fps.round() as u32);
+                encoder.encode_frame(image::Frame::from_parts(img, 0, 0, delay))?;
+            }
+            Self::Apng { frames, .. } => {
+                if frames
+                    .first()
+                    .is_some_and(|first| first.dimensions() != img.dimensions())
+                {
+                    anyhow::bail!("Window size changed while recording an animated png");
+                }
+                frames.push(img);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<()> {
+        if let Self::Apng { path, fps, frames } = self {
+            let Some(first) = frames.first() else {
+                return Ok(());
+            };
+            let mut encoder = png::Encoder::new(
+                BufWriter::new(File::create(path)?),
+                first.width(),
+                first.height(),
+            );
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frames.len() as u32, 0)?;
+            encoder.set_frame_delay(100, (fps * 100.0).round() as u16)?;
+            let mut writer = encoder.write_header()?;
+            for frame in &frames {
+                writer.write_image_data(frame.as_raw())?;
+            }
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
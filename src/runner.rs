@@ -0,0 +1,586 @@
+use std::{path::PathBuf, sync::Arc};
+
+use clap::{
+    Args,
+    builder::{PossibleValuesParser, TypedValueParser},
+};
+
+use crate::{
+    clock::{Clock, ClockOpts},
+    egui_renderer::EguiRenderer,
+    gpu_context::{GpuContext, GpuOpts},
+    offscreen::{self, OffscreenTarget},
+    recorder::Recorder,
+};
+
+/// Size used for headless frames when `--size` is not given.
+const DEFAULT_HEADLESS_SIZE: (u32, u32) = (800, 600);
+/// Frame rate of recordings when `--fps` is not given.
+const DEFAULT_RECORD_FPS: f32 = 30.0;
+/// Format of the depth buffer of demos with `Demo::DEPTH`.
+pub(crate) const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Options shared by every demo that goes through the runner.
+#[derive(Args)]
+pub(crate) struct RunOpts {
+    /// Render a single frame offscreen and save it to this path instead of
+    /// opening a window
+    #[arg(long, global = true, value_name = "PNG")]
+    headless: Option<PathBuf>,
+    /// Record every frame to a directory of pngs, or to an animated .gif or
+    /// .png. Time advances at a fixed step so the recording plays back smoothly
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "headless")]
+    record: Option<PathBuf>,
+    /// Size of the window or headless frame, e.g. 1280x720
+    #[arg(long, global = true, value_parser = parse_size)]
+    size: Option<(u32, u32)>,
+    /// Number of samples per pixel used for anti-aliasing
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1,
+        value_parser = PossibleValuesParser::new(["1", "2", "4", "8"])
+            .map(|s| s.parse::<u32>().unwrap())
+    )]
+    msaa: u32,
+
+    #[command(flatten)]
+    clock: ClockOpts,
+    #[command(flatten)]
+    pub gpu: GpuOpts,
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or(format!("expected WIDTHxHEIGHT, got {s}"))?;
+    let width = width.parse::<u32>().map_err(|e| e.to_string())?;
+    let height = height.parse::<u32>().map_err(|e| e.to_string())?;
+    if width == 0 || height == 0 {
+        return Err("size must be non-zero".to_string());
+    }
+    Ok((width, height))
+}
+
+/// The color target a demo renders into. Pipelines are created against it.
+/// The size is the one at `init`, later changes are passed to
+/// `Demo::resize`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Target {
+    pub format: wgpu::TextureFormat,
+    pub depth_format: Option<wgpu::TextureFormat>,
+    pub sample_count: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Target {
+    fn new<D: Demo>(
+        gpu_context: &GpuContext,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<Self> {
+        let target = Self {
+            format,
+            depth_format: D::DEPTH.then_some(DEPTH_FORMAT),
+            sample_count,
+            width,
+            height,
+        };
+        for format in std::iter::once(target.format).chain(target.depth_format) {
+            let flags = gpu_context.adapter.get_texture_format_features(format).flags;
+            if !flags.sample_count_supported(sample_count) {
+                anyhow::bail!(
+                    "The adapter does not support {sample_count}x MSAA for {format:?}, supported \
+                     sample counts are {:?}",
+                    flags.supported_sample_counts()
+                );
+            }
+        }
+        Ok(target)
+    }
+
+    /// Depth test for pipelines drawing into this target: nearer fragments
+    /// win and write their depth. `None` when the target has no depth buffer.
+    pub fn depth_stencil(&self) -> Option<wgpu::DepthStencilState> {
+        self.depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        })
+    }
+
+    pub fn multisample(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.sample_count,
+            ..Default::default()
+        }
+    }
+}
+
+/// The views a demo renders a frame into.
+pub(crate) struct Frame<'a> {
+    /// Multisampled when MSAA is on, it is then resolved into
+    /// `resolve_target` at the end of every pass.
+    pub view: &'a wgpu::TextureView,
+    pub resolve_target: Option<&'a wgpu::TextureView>,
+    pub depth_view: Option<&'a wgpu::TextureView>,
+}
+
+impl<'a> Frame<'a> {
+    pub fn color_attachment(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'a> {
+        wgpu::RenderPassColorAttachment {
+            view: self.view,
+            resolve_target: self.resolve_target,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+
+    /// `None` unless the demo asked for a depth buffer.
+    pub fn depth_attachment(
+        &self,
+        load: wgpu::LoadOp<f32>,
+    ) -> Option<wgpu::RenderPassDepthStencilAttachment<'a>> {
+        self.depth_view
+            .map(|view| wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: Some(wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            })
+    }
+}
+
+/// Textures rendered into alongside the output texture: the multisampled
+/// color texture when MSAA is on and the depth buffer of demos with
+/// `Demo::DEPTH`. They have to be recreated when the output is resized.
+struct Attachments {
+    msaa_view: Option<wgpu::TextureView>,
+    depth_view: Option<wgpu::TextureView>,
+}
+
+impl Attachments {
+    fn new(gpu_context: &GpuContext, target: &Target) -> Self {
+        let create_view = |label, format| {
+            gpu_context
+                .device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: target.width,
+                        height: target.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: target.sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                })
+                .create_view(&Default::default())
+        };
+        Self {
+            msaa_view: (target.sample_count > 1).then(|| create_view("msaa color", target.format)),
+            depth_view: target.depth_format.map(|format| create_view("depth", format)),
+        }
+    }
+
+    fn frame<'a>(&'a self, view: &'a wgpu::TextureView) -> Frame<'a> {
+        match &self.msaa_view {
+            Some(msaa_view) => Frame {
+                view: msaa_view,
+                resolve_target: Some(view),
+                depth_view: self.depth_view.as_ref(),
+            },
+            None => Frame {
+                view,
+                resolve_target: None,
+                depth_view: self.depth_view.as_ref(),
+            },
+        }
+    }
+}
+
+/// A demo only has to deal with its own pipelines and buffers. The window,
+/// surface, gpu context and egui are owned by the runner.
+pub(crate) trait Demo: Sized {
+    type Opts;
+
+    /// Title of the window
+    const TITLE: &'static str;
+    /// Title of the egui window. Demos without one don't get a ui.
+    const UI_TITLE: Option<&'static str> = None;
+    /// Whether the runner should attach a depth buffer, see
+    /// `Target::depth_stencil` and `Frame::depth_attachment`.
+    const DEPTH: bool = false;
+
+    fn required_features() -> wgpu::Features {
+        wgpu::Features::empty()
+    }
+
+    fn init(opts: Self::Opts, gpu_context: &GpuContext, target: &Target) -> anyhow::Result<Self>;
+
+    /// Called once per frame before `render`. Animations should read the
+    /// time from `clock` so frames can be reproduced.
+    fn update(&mut self, _gpu_context: &GpuContext, _clock: &Clock) {}
+
+    fn render(
+        &mut self,
+        gpu_context: &GpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &Frame,
+    );
+
+    fn ui(&mut self, _ui: &mut egui::Ui) {}
+
+    /// Window events egui didn't consume.
+    fn handle_event(&mut self, _event: &winit::event::WindowEvent) {}
+
+    /// The window was resized, `width` and `height` are in physical pixels.
+    fn resize(&mut self, _gpu_context: &GpuContext, _width: u32, _height: u32) {}
+}
+
+/// Run a demo in a window, or offscreen when `--headless` is given.
+pub(crate) fn run<D: Demo>(opts: D::Opts, run_opts: &RunOpts) -> anyhow::Result<()> {
+    let default_fps = run_opts.record.as_ref().map(|_| DEFAULT_RECORD_FPS);
+    let clock = Clock::from_opts(&run_opts.clock, default_fps)?;
+    if let Some(path) = &run_opts.headless {
+        let (width, height) = run_opts.size.unwrap_or(DEFAULT_HEADLESS_SIZE);
+        let gpu_context = GpuContext::new(&run_opts.gpu.headless(), D::required_features())?;
+        let img =
+            render_offscreen::<D>(opts, &gpu_context, width, height, run_opts.msaa, &clock)?;
+        img.save(path)?;
+        return Ok(());
+    }
+
+    let recorder = match &run_opts.record {
+        Some(path) => Some(Recorder::new(
+            path,
+            clock.fps().unwrap_or(DEFAULT_RECORD_FPS),
+        )?),
+        None => None,
+    };
+
+    let event_loop = winit::event_loop::EventLoop::new()?;
+    let mut runner = Runner::<D> {
+        opts: Some(opts),
+        size: run_opts.size,
+        msaa: run_opts.msaa,
+        gpu_opts: run_opts.gpu.clone(),
+        state: None,
+        clock,
+        recorder,
+        error: None,
+    };
+    event_loop.run_app(&mut runner)?;
+    if let Some(e) = runner.error {
+        return Err(e);
+    }
+    if let Some(recorder) = runner.recorder {
+        recorder.finish()?;
+    }
+    Ok(())
+}
+
+/// Render the current frame of `clock` into an offscreen texture. The
+/// context must have been created with `D::required_features()`.
+pub(crate) fn render_offscreen<D: Demo>(
+    opts: D::Opts,
+    gpu_context: &GpuContext,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    clock: &Clock,
+) -> anyhow::Result<image::RgbaImage> {
+    let offscreen_target = OffscreenTarget::new(gpu_context, width, height);
+    let target =
+        Target::new::<D>(gpu_context, OffscreenTarget::FORMAT, sample_count, width, height)?;
+    let attachments = Attachments::new(gpu_context, &target);
+
+    let mut demo = D::init(opts, gpu_context, &target)?;
+    demo.update(gpu_context, clock);
+    let mut encoder = gpu_context
+        .device
+        .create_command_encoder(&Default::default());
+    let frame = attachments.frame(&offscreen_target.view);
+    demo.render(gpu_context, &mut encoder, &frame);
+    if let Some(title) = D::UI_TITLE {
+        let mut egui_renderer = EguiRenderer::new(
+            gpu_context.clone(),
+            target.format,
+            target.depth_format,
+            target.sample_count,
+            None,
+            title,
+        );
+        egui_renderer.frame_offscreen(&frame, &mut encoder, width, height, |ui| demo.ui(ui));
+    }
+    gpu_context.queue.submit([encoder.finish()]);
+
+    offscreen_target.read_image(gpu_context)
+}
+
+struct Runner<D: Demo> {
+    opts: Option<D::Opts>,
+    size: Option<(u32, u32)>,
+    msaa: u32,
+    gpu_opts: GpuOpts,
+    state: Option<State<D>>,
+    clock: Clock,
+    recorder: Option<Recorder>,
+    error: Option<anyhow::Error>,
+}
+
+struct State<D: Demo> {
+    window: Arc<winit::window::Window>,
+    gpu_context: GpuContext,
+    surface: wgpu::Surface<'static>,
+    surface_config: wgpu::SurfaceConfiguration,
+    target: Target,
+    attachments: Attachments,
+    egui_renderer: Option<EguiRenderer>,
+    demo: D,
+}
+
+impl<D: Demo> State<D> {
+    fn new(
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        opts: D::Opts,
+        size: Option<(u32, u32)>,
+        msaa: u32,
+        gpu_opts: &GpuOpts,
+        recording: bool,
+    ) -> anyhow::Result<Self> {
+        let mut window_attributes = winit::window::Window::default_attributes().with_title(D::TITLE);
+        if let Some((width, height)) = size {
+            window_attributes =
+                window_attributes.with_inner_size(winit::dpi::PhysicalSize::new(width, height));
+        }
+        let window = Arc::new(event_loop.create_window(window_attributes)?);
+
+        let gpu_context = GpuContext::new(gpu_opts, D::required_features())?;
+        let surface = gpu_context.instance.create_surface(window.clone())?;
+        let capabilities = surface.get_capabilities(&gpu_context.adapter);
+        let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        if recording {
+            // frames are copied out of the surface texture before presenting
+            if !capabilities.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+                anyhow::bail!("The window surface does not support copying frames for recording");
+            }
+            usage |= wgpu::TextureUsages::COPY_SRC;
+        }
+        let surface_config = wgpu::SurfaceConfiguration {
+            alpha_mode: capabilities.alpha_modes[0],
+            desired_maximum_frame_latency: 2,
+            format: capabilities.formats[0],
+            usage,
+            height: window.inner_size().height.max(1),
+            width: window.inner_size().width.max(1),
+            present_mode: capabilities.present_modes[0],
+            view_formats: vec![],
+        };
+        surface.configure(&gpu_context.device, &surface_config);
+
+        let target = Target::new::<D>(
+            &gpu_context,
+            surface_config.format,
+            msaa,
+            surface_config.width,
+            surface_config.height,
+        )?;
+        let demo = D::init(opts, &gpu_context, &target)?;
+        let attachments = Attachments::new(&gpu_context, &target);
+
+        let egui_renderer = D::UI_TITLE.map(|title| {
+            EguiRenderer::new(
+                gpu_context.clone(),
+                target.format,
+                target.depth_format,
+                target.sample_count,
+                Some(&window),
+                title,
+            )
+        });
+
+        Ok(Self {
+            window,
+            gpu_context,
+            surface,
+            surface_config,
+            target,
+            attachments,
+            egui_renderer,
+            demo,
+        })
+    }
+
+    fn render(&mut self, recorder: Option<&mut Recorder>) -> anyhow::Result<()> {
+        let surface_texture = match self.surface.get_current_texture() {
+            Ok(surface_texture) => surface_texture,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.resize(self.window.inner_size());
+                self.surface.get_current_texture()?
+            }
+            Err(wgpu::SurfaceError::Timeout | wgpu::SurfaceError::Other) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let view = surface_texture.texture.create_view(&Default::default());
+        let mut encoder = self
+            .gpu_context
+            .device
+            .create_command_encoder(&Default::default());
+        let frame = self.attachments.frame(&view);
+        self.demo.render(&self.gpu_context, &mut encoder, &frame);
+
+        if let Some(egui_renderer) = &mut self.egui_renderer {
+            let demo = &mut self.demo;
+            egui_renderer.frame(
+                &self.window,
+                &frame,
+                &mut encoder,
+                &self.surface_config,
+                |ui| demo.ui(ui),
+            );
+        }
+
+        self.gpu_context.queue.submit([encoder.finish()]);
+        if let Some(recorder) = recorder {
+            recorder.add_frame(offscreen::read_texture(
+                &self.gpu_context,
+                &surface_texture.texture,
+            )?)?;
+        }
+        self.window.pre_present_notify();
+        surface_texture.present();
+        Ok(())
+    }
+
+    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.surface_config.width = size.width;
+        self.surface_config.height = size.height;
+        self.surface
+            .configure(&self.gpu_context.device, &self.surface_config);
+        self.target.width = size.width;
+        self.target.height = size.height;
+        self.attachments = Attachments::new(&self.gpu_context, &self.target);
+        self.demo.resize(&self.gpu_context, size.width, size.height);
+    }
+}
+
+impl<D: Demo> Runner<D> {
+    fn fail(&mut self, event_loop: &winit::event_loop::ActiveEventLoop, error: anyhow::Error) {
+        self.error = Some(error);
+        event_loop.exit();
+    }
+}
+
+impl<D: Demo> winit::application::ApplicationHandler for Runner<D> {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Some(opts) = self.opts.take() {
+            match State::<D>::new(
+                event_loop,
+                opts,
+                self.size,
+                self.msaa,
+                &self.gpu_opts,
+                self.recorder.is_some(),
+            ) {
+                Ok(state) => {
+                    state.window.request_redraw();
+                    self.state = Some(state);
+                }
+                Err(e) => self.fail(event_loop, e),
+            }
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: winit::event::WindowEvent,
+    ) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        let consumed = match &mut state.egui_renderer {
+            Some(egui_renderer) => egui_renderer.handle_input(&state.window, &event),
+            None => false,
+        };
+        if !consumed {
+            state.demo.handle_event(&event);
+        }
+
+        match event {
+            winit::event::WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            winit::event::WindowEvent::RedrawRequested => {
+                state.demo.update(&state.gpu_context, &self.clock);
+                if let Err(e) = state.render(self.recorder.as_mut()) {
+                    self.fail(event_loop, e);
+                    return;
+                }
+                self.clock.tick();
+                if self.clock.is_finished() {
+                    event_loop.exit();
+                } else {
+                    state.window.request_redraw();
+                }
+            }
+            winit::event::WindowEvent::Resized(size) => {
+                state.resize(size);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::render_offscreen;
+    use crate::{
+        clock::Clock,
+        gpu_context::GpuContext,
+        single_triangle::{Opts, SingleTriangleDemo},
+    };
+
+    #[test]
+    fn test_msaa_blends_edges() {
+        let gpu_context = GpuContext::headless(wgpu::Features::empty()).unwrap();
+        let colors = |sample_count| {
+            let img = render_offscreen::<SingleTriangleDemo>(
+                Opts {},
+                &gpu_context,
+                64,
+                64,
+                sample_count,
+                &Clock::frozen(0.0),
+            )
+            .unwrap();
+            img.pixels().map(|p| p.0).collect::<HashSet<_>>().len()
+        };
+        // a solid red triangle on a blue background
+        assert_eq!(colors(1), 2);
+        assert!(colors(4) > 2);
+    }
+}
@@ -1,3 +1,5 @@
 mod demo;
 
-pub(crate) use demo::{demo, Opts};
\ No newline at end of file
+pub(crate) use demo::{demo, Opts};
+#[cfg(test)]
+pub(crate) use demo::SingleTriangleDemo;